@@ -0,0 +1,455 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::{queue, AppState, DownloadParams};
+
+// ── Download history ──────────────────────────────────────────────────────────
+//
+// Every download that completes or fails is appended to `history.json` in the
+// app data folder, so past items can be opened or downloaded again without
+// pasting their URLs. Only the newest `MAX_HISTORY_ENTRIES` are kept.
+
+const MAX_HISTORY_ENTRIES: usize = 500;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HistoryEntry {
+    url: String,
+    /// Read from the file name, so `None` when nothing was saved.
+    title: Option<String>,
+    /// The downloaded file, or the output folder when it is not known.
+    output_path: String,
+    /// Audio format, quality or format selector the download asked for.
+    format: Option<String>,
+    /// Seconds since the Unix epoch when the download ended.
+    timestamp: u64,
+    status: HistoryStatus,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HistoryStatus {
+    Completed,
+    Failed,
+}
+
+impl HistoryEntry {
+    pub(crate) fn new(
+        params: &DownloadParams,
+        output_file: Option<String>,
+        status: HistoryStatus,
+    ) -> Self {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        Self {
+            url: params.url.clone(),
+            title: output_file.as_deref().and_then(title_from_file),
+            output_path: output_file.unwrap_or_else(|| params.output_path.clone()),
+            format: format_label(params),
+            timestamp,
+            status,
+        }
+    }
+}
+
+/// Recovers the title from a file named by the download templates:
+/// `Title [id].ext` or `Title [id] [720p].ext`.
+pub(crate) fn title_from_file(file: &str) -> Option<String> {
+    let stem = Path::new(file).file_stem()?.to_string_lossy();
+    let mut title = stem.as_ref();
+    // Streams that are merged later are saved as `Title [id] [720p].f137.mp4`.
+    if let Some((rest, format_id)) = title.rsplit_once(".f") {
+        if !format_id.is_empty() && format_id.bytes().all(|b| b.is_ascii_digit()) {
+            title = rest;
+        }
+    }
+    if let Some((rest, height)) = title.rsplit_once(" [") {
+        let height = height.strip_suffix("p]").unwrap_or_default();
+        if height == "NA" || (!height.is_empty() && height.bytes().all(|b| b.is_ascii_digit())) {
+            title = rest;
+        }
+    }
+    if let Some((rest, _id)) = title.rsplit_once(" [") {
+        title = rest;
+    }
+    Some(title.to_string()).filter(|title| !title.is_empty())
+}
+
+fn format_label(params: &DownloadParams) -> Option<String> {
+    let options = &params.options;
+    options
+        .audio_format
+        .clone()
+        .or_else(|| options.quality.clone())
+        .or_else(|| {
+            let at = params.format_args.iter().position(|arg| arg == "-f")?;
+            params.format_args.get(at + 1).cloned()
+        })
+}
+
+fn history_path(app: &AppHandle) -> PathBuf {
+    app.path().app_data_dir().unwrap().join("history.json")
+}
+
+async fn read_history(path: &Path) -> Result<Vec<HistoryEntry>, String> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(text) => serde_json::from_str(&text)
+            .map_err(|e| format!("The download history could not be read: {e}")),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(format!("Could not read the download history: {error}")),
+    }
+}
+
+async fn write_history(path: &Path, entries: &[HistoryEntry]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Cannot create the history folder: {e}"))?;
+    }
+    let json = serde_json::to_string_pretty(entries).map_err(|e| e.to_string())?;
+    // Written next to the history and renamed over it, so a crash midway
+    // leaves the old history in place.
+    let temp = path.with_extension("json.tmp");
+    let written = async {
+        tokio::fs::write(&temp, json).await?;
+        tokio::fs::rename(&temp, path).await
+    };
+    if let Err(error) = written.await {
+        tokio::fs::remove_file(&temp).await.ok();
+        return Err(format!("Could not save the download history: {error}"));
+    }
+    Ok(())
+}
+
+/// Appends `entry` to the history at `path`. A history that cannot be read
+/// is moved aside rather than overwritten, and a new one started; the path
+/// it was moved to is returned.
+async fn append_entry(path: &Path, entry: HistoryEntry) -> Result<Option<PathBuf>, String> {
+    let (mut entries, backup) = match read_history(path).await {
+        Ok(entries) => (entries, None),
+        Err(error) => {
+            let timestamp = entry.timestamp;
+            let backup = path.with_extension(format!("json.{timestamp}.bad"));
+            tokio::fs::rename(path, &backup)
+                .await
+                .map_err(|e| format!("{error}, and it could not be set aside: {e}"))?;
+            (Vec::new(), Some(backup))
+        }
+    };
+    entries.push(entry);
+    let excess = entries.len().saturating_sub(MAX_HISTORY_ENTRIES);
+    entries.drain(..excess);
+    write_history(path, &entries).await?;
+    Ok(backup)
+}
+
+/// Appends `entry` to the saved history. Returns where an unreadable
+/// history was moved to, if it was.
+pub(crate) async fn record(
+    app: &AppHandle,
+    state: &AppState,
+    entry: HistoryEntry,
+) -> Result<Option<PathBuf>, String> {
+    let _guard = state.history.lock().await;
+    append_entry(&history_path(app), entry).await
+}
+
+/// Past downloads, newest first.
+#[tauri::command]
+pub(crate) async fn get_history(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<HistoryEntry>, String> {
+    let _guard = state.history.lock().await;
+    let mut entries = read_history(&history_path(&app)).await?;
+    entries.reverse();
+    Ok(entries)
+}
+
+const CSV_COLUMNS: [&str; 6] = [
+    "url",
+    "title",
+    "output_path",
+    "format",
+    "timestamp",
+    "status",
+];
+
+/// Writes `entries` as JSON or CSV, depending on the extension of `path`.
+fn export_entries(entries: &[HistoryEntry], path: &Path) -> Result<String, String> {
+    if !queue::is_csv(path)? {
+        return serde_json::to_string_pretty(entries).map_err(|e| e.to_string());
+    }
+    let mut csv = CSV_COLUMNS.join(",");
+    for entry in entries {
+        let status = match entry.status {
+            HistoryStatus::Completed => "completed",
+            HistoryStatus::Failed => "failed",
+        };
+        let row = [
+            entry.url.clone(),
+            entry.title.clone().unwrap_or_default(),
+            entry.output_path.clone(),
+            entry.format.clone().unwrap_or_default(),
+            entry.timestamp.to_string(),
+            status.to_string(),
+        ];
+        csv.push('\n');
+        csv.push_str(&row.map(|field| queue::csv_field(&field)).join(","));
+    }
+    csv.push('\n');
+    Ok(csv)
+}
+
+/// Reads the entries of an exported history.
+fn import_entries(text: &str, path: &Path) -> Result<Vec<HistoryEntry>, String> {
+    if !queue::is_csv(path)? {
+        return serde_json::from_str(text)
+            .map_err(|e| format!("The file is not an exported history: {e}"));
+    }
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let header = queue::parse_csv_line(lines.next().unwrap_or_default());
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let required =
+        |name: &str| column(name).ok_or_else(|| format!("The CSV file has no \"{name}\" column"));
+    let (url, timestamp, status) = (
+        required("url")?,
+        required("timestamp")?,
+        required("status")?,
+    );
+    let (title, output_path, format) = (column("title"), column("output_path"), column("format"));
+    let mut entries = Vec::new();
+    for (row, line) in lines.enumerate() {
+        let fields = queue::parse_csv_line(line);
+        let field = |index: usize| fields.get(index).cloned().unwrap_or_default();
+        let optional = |index: Option<usize>| index.map(field).filter(|value| !value.is_empty());
+        if field(url).trim().is_empty() {
+            continue;
+        }
+        let line_number = row + 2;
+        let status = match field(status).as_str() {
+            "completed" => HistoryStatus::Completed,
+            "failed" => HistoryStatus::Failed,
+            other => return Err(format!("Line {line_number}: unknown status \"{other}\"")),
+        };
+        let timestamp = field(timestamp)
+            .trim()
+            .parse()
+            .map_err(|_| format!("Line {line_number}: the timestamp is not a number"))?;
+        entries.push(HistoryEntry {
+            url: field(url),
+            title: optional(title),
+            output_path: optional(output_path).unwrap_or_default(),
+            format: optional(format),
+            timestamp,
+            status,
+        });
+    }
+    Ok(entries)
+}
+
+/// Adds the `imported` entries missing from `entries`, keeping them in time
+/// order and within `MAX_HISTORY_ENTRIES`. Returns how many were added.
+fn merge_entries(entries: &mut Vec<HistoryEntry>, imported: Vec<HistoryEntry>) -> usize {
+    let mut added = 0;
+    for entry in imported {
+        if !entries.contains(&entry) {
+            entries.push(entry);
+            added += 1;
+        }
+    }
+    entries.sort_by_key(|entry| entry.timestamp);
+    let excess = entries.len().saturating_sub(MAX_HISTORY_ENTRIES);
+    entries.drain(..excess);
+    added
+}
+
+/// The saved history as JSON or CSV for `path`, with its number of entries.
+pub(crate) async fn export(
+    app: &AppHandle,
+    state: &AppState,
+    path: &Path,
+) -> Result<(String, usize), String> {
+    let _guard = state.history.lock().await;
+    let entries = read_history(&history_path(app)).await?;
+    Ok((export_entries(&entries, path)?, entries.len()))
+}
+
+/// Adds the entries of an exported history in `text` to the saved one.
+/// Returns how many were new.
+pub(crate) async fn import(
+    app: &AppHandle,
+    state: &AppState,
+    text: &str,
+    path: &Path,
+) -> Result<usize, String> {
+    let imported = import_entries(text, path)?;
+    let _guard = state.history.lock().await;
+    let history_path = history_path(app);
+    let mut entries = read_history(&history_path).await?;
+    let added = merge_entries(&mut entries, imported);
+    write_history(&history_path, &entries).await?;
+    Ok(added)
+}
+
+/// When `url` last downloaded completely, as seconds since the Unix epoch.
+fn last_completed(entries: &[HistoryEntry], url: &str) -> Option<u64> {
+    entries
+        .iter()
+        .filter(|entry| entry.url == url.trim() && entry.status == HistoryStatus::Completed)
+        .map(|entry| entry.timestamp)
+        .max()
+}
+
+/// The day (`YYYYMMDD`) `url`, such as a channel, was last downloaded, to
+/// use as `date_after` so a later run only fetches newer uploads. `None`
+/// when it never completed.
+#[tauri::command]
+pub(crate) async fn get_last_download_date(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+) -> Result<Option<String>, String> {
+    let _guard = state.history.lock().await;
+    let entries = read_history(&history_path(&app)).await?;
+    Ok(last_completed(&entries, &url)
+        .and_then(|timestamp| chrono::DateTime::from_timestamp(timestamp as i64, 0))
+        .map(|time| {
+            time.with_timezone(&chrono::Local)
+                .format("%Y%m%d")
+                .to_string()
+        }))
+}
+
+#[tauri::command]
+pub(crate) async fn clear_history(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let _guard = state.history.lock().await;
+    match tokio::fs::remove_file(history_path(&app)).await {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Could not clear the download history: {error}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        append_entry, export_entries, import_entries, last_completed, merge_entries, read_history,
+        title_from_file, HistoryEntry, HistoryStatus,
+    };
+
+    #[test]
+    fn reads_titles_from_downloaded_file_names() {
+        assert_eq!(
+            title_from_file("/out/A [b] talk [dQw4w9WgXcQ] [1080p].mp4").as_deref(),
+            Some("A [b] talk")
+        );
+        assert_eq!(
+            title_from_file("/out/Song [dQw4w9WgXcQ].mp3").as_deref(),
+            Some("Song")
+        );
+        assert_eq!(title_from_file("/out/plain.mp4").as_deref(), Some("plain"));
+    }
+
+    #[test]
+    fn exports_and_imports_as_json_and_csv() {
+        let entries = vec![
+            HistoryEntry {
+                url: "https://a".to_string(),
+                title: Some("Talk, part \"1\"".to_string()),
+                output_path: "/out/Talk.mp4".to_string(),
+                format: Some("mp3".to_string()),
+                timestamp: 10,
+                status: HistoryStatus::Completed,
+            },
+            HistoryEntry {
+                url: "https://b".to_string(),
+                title: None,
+                output_path: "/out".to_string(),
+                format: None,
+                timestamp: 20,
+                status: HistoryStatus::Failed,
+            },
+        ];
+        for name in ["history.json", "history.csv"] {
+            let path = std::path::Path::new(name);
+            let exported = export_entries(&entries, path).unwrap();
+            assert_eq!(import_entries(&exported, path).unwrap(), entries);
+        }
+        assert!(export_entries(&entries, std::path::Path::new("history.txt")).is_err());
+        let bad_status = "url,timestamp,status\nhttps://a,1,done\n";
+        assert!(import_entries(bad_status, std::path::Path::new("h.csv")).is_err());
+
+        let mut saved = vec![entries[1].clone()];
+        assert_eq!(merge_entries(&mut saved, entries.clone()), 1);
+        assert_eq!(saved, entries);
+    }
+
+    #[test]
+    fn finds_the_last_completed_download_of_a_url() {
+        let entry = |url: &str, timestamp, status| HistoryEntry {
+            url: url.to_string(),
+            title: None,
+            output_path: "/out".to_string(),
+            format: None,
+            timestamp,
+            status,
+        };
+        let channel = "https://www.youtube.com/@example";
+        let entries = [
+            entry(channel, 10, HistoryStatus::Completed),
+            entry(channel, 30, HistoryStatus::Failed),
+            entry(channel, 20, HistoryStatus::Completed),
+            entry("https://other", 40, HistoryStatus::Completed),
+        ];
+        assert_eq!(last_completed(&entries, channel), Some(20));
+        assert_eq!(last_completed(&entries, "https://unknown"), None);
+    }
+
+    #[tokio::test]
+    async fn keeps_entries_across_appends() {
+        let path = std::env::temp_dir().join(format!("history-test-{}.json", std::process::id()));
+        let entry = |url: &str, status| HistoryEntry {
+            url: url.to_string(),
+            title: None,
+            output_path: "/out".to_string(),
+            format: None,
+            timestamp: 1,
+            status,
+        };
+        append_entry(&path, entry("https://a", HistoryStatus::Completed))
+            .await
+            .unwrap();
+        append_entry(&path, entry("https://b", HistoryStatus::Failed))
+            .await
+            .unwrap();
+        assert_eq!(
+            read_history(&path).await.unwrap(),
+            [
+                entry("https://a", HistoryStatus::Completed),
+                entry("https://b", HistoryStatus::Failed)
+            ]
+        );
+        std::fs::remove_file(&path).ok();
+
+        std::fs::write(&path, "not json").unwrap();
+        let backup = append_entry(&path, entry("https://c", HistoryStatus::Completed))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "not json");
+        assert_eq!(
+            read_history(&path).await.unwrap(),
+            [entry("https://c", HistoryStatus::Completed)]
+        );
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&backup).ok();
+    }
+}
@@ -1,19 +1,188 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
 use tauri::{AppHandle, Emitter, Manager, State};
-use tokio::sync::{oneshot, Mutex};
+use tokio::sync::{oneshot, Mutex, Notify, Semaphore};
+
+mod history;
+mod logs;
+mod queue;
+mod settings;
+
+use queue::{DownloadQueue, JobStatus};
 
 // ── Shared state ──────────────────────────────────────────────────────────────
 
 pub struct AppState {
-    cancel_tx: Mutex<Option<oneshot::Sender<()>>>,
+    /// Running downloads by job id.
+    active_jobs: Mutex<HashMap<String, ActiveJob>>,
+    last_download: Mutex<Option<DownloadParams>>,
+    queue: Mutex<DownloadQueue>,
+    queue_changed: Notify,
+    session_stats: Mutex<SessionStats>,
+    /// Bandwidth cap in bytes per second shared by all downloads, from the
+    /// saved settings.
+    global_rate_limit: Mutex<Option<u64>>,
+    /// Program run on every completed download, from the saved settings.
+    post_hook: Mutex<Option<PathBuf>>,
+    /// File kept up to date with the running job's snapshot, from the saved
+    /// settings.
+    status_file: Mutex<Option<PathBuf>>,
+    /// How failed jobs are retried automatically, from the saved settings;
+    /// `None` while that is turned off.
+    retry_policy: Mutex<Option<queue::RetryPolicy>>,
+    /// Free bytes below which queued video jobs start at a lower quality,
+    /// from the saved settings; `None` while that is turned off.
+    low_disk_threshold: Mutex<Option<u64>>,
+    /// Result of the last dependency check, reused until the tools change.
+    deps_available: Mutex<Option<bool>>,
+    /// Whether a download already tried to fetch aria2c this session, so a
+    /// failed install is not repeated for every download.
+    aria2c_install_tried: Mutex<bool>,
+    /// Sites from `list_extractors`, with the yt-dlp version they came from.
+    extractors: Mutex<Option<(String, Vec<String>)>>,
+    /// Signalled whenever a download has stopped and cleaned up.
+    download_finished: Notify,
+    /// Set while quitting so the queue stops starting new jobs.
+    shutting_down: AtomicBool,
+    /// Save each download into a folder named after the day it started,
+    /// from the saved settings.
+    datestamp_folders: AtomicBool,
+    /// Set by `pause_queue`: the running job finishes, but no new ones
+    /// start until `resume_queue`. Kept in the saved settings.
+    queue_paused: AtomicBool,
+    /// Proxy for yt-dlp and the app's own downloads, from the saved
+    /// settings.
+    proxy_url: Mutex<Option<String>>,
+    /// Held while the download history file is read or rewritten.
+    history: Mutex<()>,
+    /// Sends lines to this session's log file once it is open.
+    session_log: std::sync::OnceLock<tokio::sync::mpsc::UnboundedSender<String>>,
+    /// Downloads stopped by `pause_download`, until they are resumed.
+    paused_jobs: Mutex<HashMap<String, PausedJob>>,
+    /// Downloads being restarted with a new share of `global_rate_limit`.
+    bandwidth_restarts: Mutex<HashMap<String, BandwidthRestart>>,
+    /// Signalled whenever downloads start or stop, or the bandwidth limit
+    /// changes, so the shares can be recomputed.
+    bandwidth_changed: Notify,
+    /// Limits the `spawn_size_probe`s running at once.
+    size_probes: Semaphore,
+    /// Temp folders of stopped jobs kept for a retry or resume, by job id.
+    /// Nothing can continue them once the app quits, so they go then.
+    kept_temp_dirs: Mutex<HashMap<String, PathBuf>>,
     cookie_dir: PathBuf,
 }
 
+/// A running download. It stays registered until its process has exited
+/// and it has cleaned up, even after being cancelled.
+struct ActiveJob {
+    /// Taken once the job has been told to stop.
+    cancel_tx: Option<oneshot::Sender<()>>,
+    snapshot: JobSnapshot,
+    params: DownloadParams,
+    /// The folder the job writes to, after `datestamp_folders` was applied.
+    output_path: String,
+    /// The `--limit-rate` its process was started with.
+    rate_limit: Option<u64>,
+}
+
+/// A download stopped by `pause_download`. `output_path` is the folder it
+/// was writing to, so resuming finds its partial files even on a later day.
+#[derive(Clone)]
+struct PausedJob {
+    params: DownloadParams,
+    output_path: String,
+}
+
+/// A download stopped by `run_bandwidth_worker` to start again with a new
+/// share of the bandwidth limit.
+struct BandwidthRestart {
+    job: PausedJob,
+    /// Set once its process was stopped; until then, a cancelled job ends
+    /// as usual.
+    stopped: bool,
+}
+
+impl ActiveJob {
+    fn cancel(&mut self) {
+        if let Some(cancel_tx) = self.cancel_tx.take() {
+            let _ = cancel_tx.send(());
+        }
+    }
+}
+
+/// What a running job is doing right now; returned by `get_active_jobs` so
+/// the frontend can catch up after a reload.
+#[derive(Clone, Serialize)]
+struct JobSnapshot {
+    job_id: String,
+    url: String,
+    phase: JobPhase,
+    /// Progress of the current stream, 0–100.
+    percent: f64,
+    /// Bytes per second, while transferring.
+    speed: Option<u64>,
+    /// Seconds until the current stream is done, while transferring.
+    eta_secs: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JobPhase {
+    /// yt-dlp is reading the page and picking formats.
+    Preparing,
+    Downloading,
+    /// Merging, converting or tagging the downloaded files.
+    Processing,
+}
+
+impl JobSnapshot {
+    fn new(job_id: String, url: String) -> Self {
+        Self {
+            job_id,
+            url,
+            phase: JobPhase::Preparing,
+            percent: 0.0,
+            speed: None,
+            eta_secs: None,
+        }
+    }
+
+    fn observe(&mut self, line: &str) {
+        const PROCESSING_PREFIXES: &[&str] = &[
+            "[Merger]",
+            "[ExtractAudio]",
+            "[VideoRemuxer]",
+            "[VideoConvertor]",
+            "[Fixup",
+            "[FFmpeg",
+            "[EmbedThumbnail]",
+            "[Metadata]",
+            "[ModifyChapters]",
+            "[ThumbnailsConvertor]",
+        ];
+        if line.starts_with("[download]") {
+            self.phase = JobPhase::Downloading;
+            if let Some(percent) = parse_progress(line) {
+                self.percent = percent;
+            }
+            self.speed = parse_speed(line);
+            self.eta_secs = parse_eta(line);
+        } else if PROCESSING_PREFIXES
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+        {
+            self.phase = JobPhase::Processing;
+            self.speed = None;
+            self.eta_secs = None;
+        }
+    }
+}
+
 impl Default for AppState {
     fn default() -> Self {
         cleanup_stale_cookie_dirs();
@@ -24,7 +193,31 @@ impl Default for AppState {
         secure_cookie_dir(&cookie_dir);
 
         Self {
-            cancel_tx: Mutex::new(None),
+            active_jobs: Mutex::new(HashMap::new()),
+            last_download: Mutex::new(None),
+            queue: Mutex::new(DownloadQueue::default()),
+            queue_changed: Notify::new(),
+            session_stats: Mutex::new(SessionStats::default()),
+            global_rate_limit: Mutex::new(None),
+            post_hook: Mutex::new(None),
+            status_file: Mutex::new(None),
+            low_disk_threshold: Mutex::new(None),
+            retry_policy: Mutex::new(None),
+            deps_available: Mutex::new(None),
+            aria2c_install_tried: Mutex::new(false),
+            extractors: Mutex::new(None),
+            download_finished: Notify::new(),
+            shutting_down: AtomicBool::new(false),
+            datestamp_folders: AtomicBool::new(false),
+            queue_paused: AtomicBool::new(false),
+            proxy_url: Mutex::new(None),
+            history: Mutex::new(()),
+            session_log: std::sync::OnceLock::new(),
+            paused_jobs: Mutex::new(HashMap::new()),
+            bandwidth_restarts: Mutex::new(HashMap::new()),
+            bandwidth_changed: Notify::new(),
+            size_probes: Semaphore::new(MAX_SIZE_PROBES),
+            kept_temp_dirs: Mutex::new(HashMap::new()),
             cookie_dir,
         }
     }
@@ -58,6 +251,23 @@ fn managed_ffmpeg_path(app: &AppHandle) -> PathBuf {
     })
 }
 
+fn managed_aria2c_path(app: &AppHandle) -> PathBuf {
+    bin_dir(app).join(if cfg!(windows) {
+        "aria2c.exe"
+    } else {
+        "aria2c"
+    })
+}
+
+/// yt-dlp looks for ffprobe in the folder of the ffmpeg it is given.
+fn ffprobe_beside(ffmpeg: &std::path::Path) -> PathBuf {
+    ffmpeg.with_file_name(if cfg!(windows) {
+        "ffprobe.exe"
+    } else {
+        "ffprobe"
+    })
+}
+
 fn external_tool_candidates(tool: &str) -> Vec<PathBuf> {
     let executable = if cfg!(windows) {
         format!("{tool}.exe")
@@ -105,20 +315,74 @@ async fn resolve_ytdlp(app: &AppHandle) -> Option<(PathBuf, bool)> {
         .map(|_| (managed, true))
 }
 
+/// Finds an ffmpeg that has ffprobe next to it; thumbnail embedding and
+/// some other post-processing steps need both.
 async fn resolve_ffmpeg(app: &AppHandle) -> Option<(PathBuf, bool)> {
     for path in external_tool_candidates("ffmpeg") {
-        if ffmpeg_is_working(&path).await {
+        if ffprobe_beside(&path).is_file() && ffmpeg_is_working(&path).await {
             return Some((path, false));
         }
     }
 
     let managed = managed_ffmpeg_path(app);
-    ffmpeg_is_working(&managed).await.then_some((managed, true))
+    let working = ffprobe_beside(&managed).is_file() && ffmpeg_is_working(&managed).await;
+    working.then_some((managed, true))
+}
+
+/// aria2c from the PATH or, on Windows, the copy setup downloaded.
+async fn resolve_aria2c(app: &AppHandle) -> Option<PathBuf> {
+    let mut candidates = external_tool_candidates("aria2c");
+    candidates.push(managed_aria2c_path(app));
+    for path in candidates {
+        if tool_is_working(&path, "--version").await {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// aria2c for a download that asked for it. Setup only fetches aria2c
+/// along with a missing yt-dlp or ffmpeg, so on Windows it is installed
+/// here the first time a download needs it.
+async fn ensure_aria2c(app: &AppHandle, job_id: &str) -> Option<PathBuf> {
+    if let Some(aria2c) = resolve_aria2c(app).await {
+        return Some(aria2c);
+    }
+    if !cfg!(windows) {
+        return None;
+    }
+    let state = app.state::<AppState>();
+    let mut tried = state.aria2c_install_tried.lock().await;
+    // Another download may have installed it while this one waited.
+    if *tried {
+        return resolve_aria2c(app).await;
+    }
+    *tried = true;
+    emit_log(app, Some(job_id), "Downloading aria2c…");
+    let installed = async {
+        let dir = bin_dir(app);
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| e.to_string())?;
+        let client = HttpClient::new(app).await?;
+        let progress = SetupProgress::hidden(app.clone(), &[0.0; SETUP_PART_ARIA2C + 1]);
+        install_aria2c(app, &client, &dir, &progress).await
+    }
+    .await;
+    if let Err(error) = installed {
+        emit_log(app, Some(job_id), &format!("Warning: {error}"));
+        return None;
+    }
+    resolve_aria2c(app).await
 }
 
 fn ytdlp_download_url() -> &'static str {
     if cfg!(windows) {
         "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp.exe"
+    } else if cfg!(all(target_os = "linux", target_arch = "aarch64")) {
+        "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp_linux_aarch64"
+    } else if cfg!(target_os = "linux") {
+        "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp_linux"
     } else {
         "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp_macos"
     }
@@ -126,9 +390,71 @@ fn ytdlp_download_url() -> &'static str {
 
 // ── Commands ──────────────────────────────────────────────────────────────────
 
+/// Whether yt-dlp, ffmpeg and ffprobe are usable. The answer is cached until setup
+/// or an update changes the tools; `force` checks again regardless.
+#[tauri::command]
+async fn check_deps(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    force: Option<bool>,
+) -> Result<bool, ()> {
+    Ok(deps_available(&app, &state, force.unwrap_or(false)).await)
+}
+
+async fn deps_available(app: &AppHandle, state: &AppState, force: bool) -> bool {
+    let mut cached = state.deps_available.lock().await;
+    if let Some(available) = cached.filter(|_| !force) {
+        return available;
+    }
+    let ytdlp = resolve_ytdlp(app).await.is_some();
+    if !ytdlp {
+        // The managed copy is the last candidate, so its error is the one
+        // setup would have to fix.
+        let managed = managed_ytdlp_path(app);
+        if let Err(error) = read_ytdlp_version(&managed).await {
+            emit_log(app, None, &format!("yt-dlp is not usable: {error}"));
+        }
+    }
+    let ffmpeg = resolve_ffmpeg(app).await.is_some();
+    if !ffmpeg {
+        let managed = managed_ffmpeg_path(app);
+        emit_log(
+            app,
+            None,
+            &format!(
+                "ffmpeg is not usable: none on the PATH or at {} runs with ffprobe next to it",
+                managed.display()
+            ),
+        );
+    }
+    let available = ytdlp && ffmpeg;
+    *cached = Some(available);
+    available
+}
+
+/// Encoders the app may ask ffmpeg for, and which of them the installed
+/// build lacks.
+#[derive(Serialize)]
+struct FfmpegFeatures {
+    available: Vec<String>,
+    missing: Vec<String>,
+}
+
+/// Lists which encoders the app relies on are in the installed ffmpeg.
+/// Minimal builds often leave out libmp3lame or libx264.
 #[tauri::command]
-async fn check_deps(app: AppHandle) -> bool {
-    resolve_ytdlp(&app).await.is_some() && resolve_ffmpeg(&app).await.is_some()
+async fn check_ffmpeg_features(app: AppHandle) -> Result<FfmpegFeatures, String> {
+    let (ffmpeg, _) = resolve_ffmpeg(&app)
+        .await
+        .ok_or_else(|| "ffmpeg is not installed".to_string())?;
+    let encoders = ffmpeg_encoders(&ffmpeg)
+        .await
+        .ok_or_else(|| "Could not list ffmpeg's encoders".to_string())?;
+    let (available, missing) = USED_ENCODERS
+        .iter()
+        .map(|encoder| encoder.to_string())
+        .partition(|encoder| encoders.contains(encoder));
+    Ok(FfmpegFeatures { available, missing })
 }
 
 #[tauri::command]
@@ -139,6 +465,38 @@ async fn get_ytdlp_version(app: AppHandle) -> Result<String, String> {
     read_ytdlp_version(&path).await
 }
 
+/// Names of the sites yt-dlp can download from, without those it marks as
+/// broken. The list only changes with yt-dlp, so it is kept per version.
+#[tauri::command]
+async fn list_extractors(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    let (path, _) = resolve_ytdlp(&app)
+        .await
+        .ok_or_else(|| "yt-dlp is not installed".to_string())?;
+    let version = read_ytdlp_version(&path).await?;
+    let mut cached = state.extractors.lock().await;
+    if let Some((_, extractors)) = cached.as_ref().filter(|(cached, _)| *cached == version) {
+        return Ok(extractors.clone());
+    }
+    let output = tokio::process::Command::new(&path)
+        .arg("--list-extractors")
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Could not run yt-dlp: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "yt-dlp could not list its supported sites: {}",
+            last_line(&String::from_utf8_lossy(&output.stderr))
+        ));
+    }
+    let extractors = parse_extractors(&String::from_utf8_lossy(&output.stdout));
+    *cached = Some((version, extractors.clone()));
+    Ok(extractors)
+}
+
 #[derive(Serialize)]
 struct YtdlpStatus {
     current_version: String,
@@ -160,7 +518,10 @@ async fn get_ytdlp_status(app: AppHandle) -> Result<YtdlpStatus, String> {
         .await
         .ok_or_else(|| "yt-dlp is not installed".to_string())?;
     let current_version = read_ytdlp_version(&path).await?;
-    let latest_version = fetch_latest_ytdlp_version().await.ok();
+    let latest_version = match HttpClient::new(&app).await {
+        Ok(client) => fetch_latest_ytdlp_version(&client).await.ok(),
+        Err(_) => None,
+    };
     let update_available = latest_version
         .as_ref()
         .map(
@@ -195,6 +556,14 @@ struct YtdlpUpdateResult {
 
 #[tauri::command]
 async fn update_ytdlp(app: AppHandle) -> Result<YtdlpUpdateResult, String> {
+    let updated = install_ytdlp_update(app.clone()).await;
+    if let Err(error) = &updated {
+        logs::write(&app, None, &format!("yt-dlp update failed: {error}"));
+    }
+    updated
+}
+
+async fn install_ytdlp_update(app: AppHandle) -> Result<YtdlpUpdateResult, String> {
     let (current_path, managed_by_app) = resolve_ytdlp(&app)
         .await
         .ok_or_else(|| "yt-dlp is not installed".to_string())?;
@@ -219,10 +588,13 @@ async fn update_ytdlp(app: AppHandle) -> Result<YtdlpUpdateResult, String> {
             .map_err(|e| format!("Cannot remove old update file: {e}"))?;
     }
 
-    if let Err(error) = download_to_path(ytdlp_download_url(), &update_path).await {
+    let client = HttpClient::new(&app).await?;
+    let checksum = fetch_ytdlp_checksum(&client).await?;
+    if let Err(error) = download_to_path(&client, ytdlp_download_url(), &update_path).await {
         tokio::fs::remove_file(&update_path).await.ok();
         return Err(error);
     }
+    verify_checksum(&update_path, &checksum, "yt-dlp").await?;
     make_executable(&update_path);
 
     let current_version = match read_ytdlp_version(&update_path).await {
@@ -242,7 +614,9 @@ async fn update_ytdlp(app: AppHandle) -> Result<YtdlpUpdateResult, String> {
         });
     }
 
-    replace_ytdlp(&current_path, &update_path).await?;
+    let replaced = replace_ytdlp(&current_path, &update_path).await;
+    *app.state::<AppState>().deps_available.lock().await = None;
+    replaced?;
 
     Ok(YtdlpUpdateResult {
         previous_version,
@@ -251,8 +625,17 @@ async fn update_ytdlp(app: AppHandle) -> Result<YtdlpUpdateResult, String> {
     })
 }
 
+/// The output folder from the saved settings, or else `DownloadedVideos` in
+/// the Downloads folder.
 #[tauri::command]
-fn get_default_output_path() -> String {
+async fn get_default_output_path(app: AppHandle) -> String {
+    match settings::saved_output_path(&app).await {
+        Some(path) => path,
+        None => downloads_output_path(),
+    }
+}
+
+fn downloads_output_path() -> String {
     dirs::download_dir()
         .unwrap_or_else(|| PathBuf::from("."))
         .join("DownloadedVideos")
@@ -262,697 +645,7739 @@ fn get_default_output_path() -> String {
 
 #[tauri::command]
 async fn download_deps(app: AppHandle) -> Result<(), String> {
+    let installed = install_deps(app.clone()).await;
+    if let Err(error) = &installed {
+        logs::write(&app, None, &format!("Setup failed: {error}"));
+    }
+    installed
+}
+
+async fn install_deps(app: AppHandle) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    *state.deps_available.lock().await = None;
     let dir = bin_dir(&app);
     std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
 
-    // — yt-dlp —
-    if resolve_ytdlp(&app).await.is_none() {
-        let managed_ytdlp = managed_ytdlp_path(&app);
-        tokio::fs::remove_file(&managed_ytdlp).await.ok();
-        app.emit("setup-task", "Downloading yt-dlp…").ok();
-        download_file(&app, ytdlp_download_url(), &managed_ytdlp, 0.0, 0.12).await?;
-        make_executable(&managed_ytdlp);
-        read_ytdlp_version(&managed_ytdlp)
-            .await
-            .map_err(|e| format!("Downloaded yt-dlp could not be validated: {e}"))?;
+    let need_ytdlp = resolve_ytdlp(&app).await.is_none();
+    let need_ffmpeg = resolve_ffmpeg(&app).await.is_none();
+    let need_aria2c = cfg!(windows) && resolve_aria2c(&app).await.is_none();
+    let task = match (need_ytdlp, need_ffmpeg) {
+        (true, true) => Some("Downloading yt-dlp and ffmpeg…"),
+        (true, false) => Some("Downloading yt-dlp…"),
+        (false, true) => Some("Downloading ffmpeg…"),
+        (false, false) => None,
+    };
+    if let Some(task) = task {
+        app.emit("setup-task", task).ok();
+        logs::write(&app, None, task);
     }
 
-    // — ffmpeg —
-    if resolve_ffmpeg(&app).await.is_none() {
-        let managed_ffmpeg = managed_ffmpeg_path(&app);
-        tokio::fs::remove_file(&managed_ffmpeg).await.ok();
-        let (ffmpeg_url, ffmpeg_bin) = if cfg!(windows) {
-            (
-                "https://github.com/BtbN/ffmpeg-builds/releases/latest/download/ffmpeg-master-latest-win64-gpl.zip",
-                "ffmpeg.exe",
-            )
-        } else {
-            ("https://evermeet.cx/ffmpeg/getrelease/zip", "ffmpeg")
-        };
+    let client = HttpClient::new(&app).await?;
+    // Both downloads are independent, so they run side by side and report
+    // into their own share of the setup bar.
+    let ffprobe_share = if cfg!(target_os = "macos") { 0.1 } else { 0.0 };
+    let aria2c_share = if need_aria2c { 0.05 } else { 0.0 };
+    let progress = SetupProgress::new(
+        app.clone(),
+        &[
+            0.12,
+            0.81 - ffprobe_share - aria2c_share,
+            0.07,
+            ffprobe_share,
+            aria2c_share,
+        ],
+    );
+    let (ytdlp, ffmpeg, aria2c) = tokio::join!(
+        async {
+            if need_ytdlp {
+                install_ytdlp(&app, &client, &progress).await
+            } else {
+                progress.set(SETUP_PART_YTDLP, 1.0);
+                Ok(())
+            }
+        },
+        async {
+            if need_ffmpeg {
+                install_ffmpeg(&app, &client, &dir, &progress).await
+            } else {
+                progress.set(SETUP_PART_FFMPEG, 1.0);
+                progress.set(SETUP_PART_EXTRACT, 1.0);
+                progress.set(SETUP_PART_FFPROBE, 1.0);
+                Ok(())
+            }
+        },
+        async {
+            if need_aria2c {
+                install_aria2c(&app, &client, &dir, &progress).await
+            } else {
+                Ok(())
+            }
+        },
+    );
+    if let (Err(_), Err(error)) = (&ytdlp, &ffmpeg) {
+        // Only the yt-dlp error is returned, so keep this one in the log.
+        logs::write(&app, None, &format!("ffmpeg setup failed: {error}"));
+    }
+    ytdlp?;
+    ffmpeg?;
+    // aria2c is optional: downloads use yt-dlp's own downloader without it.
+    if let Err(error) = aria2c {
+        emit_log(&app, None, &format!("Warning: {error}"));
+    }
+    *state.deps_available.lock().await = Some(true);
 
-        app.emit("setup-task", "Downloading ffmpeg…").ok();
-        let zip_path = dir.join("ffmpeg.zip");
-        download_file(&app, ffmpeg_url, &zip_path, 0.12, 0.93).await?;
+    app.emit("setup-progress", 1.0_f64).ok();
+    app.emit("setup-done", ()).ok();
+    Ok(())
+}
 
-        app.emit("setup-task", "Extracting ffmpeg…").ok();
-        app.emit("setup-progress", 0.93_f64).ok();
+const SETUP_PART_YTDLP: usize = 0;
+const SETUP_PART_FFMPEG: usize = 1;
+const SETUP_PART_EXTRACT: usize = 2;
+/// Only used on macOS, where ffprobe comes as an archive of its own.
+const SETUP_PART_FFPROBE: usize = 3;
+/// Only used on Windows, the one platform aria2c publishes builds for.
+const SETUP_PART_ARIA2C: usize = 4;
 
-        let dest = managed_ffmpeg.clone();
-        tokio::task::spawn_blocking(move || {
-            extract_binary(&zip_path, ffmpeg_bin, &dest)?;
-            std::fs::remove_file(&zip_path).ok();
-            Ok::<(), String>(())
-        })
-        .await
-        .map_err(|e| e.to_string())??;
+/// Sixteen connections per file, split into as many pieces.
+const ARIA2C_ARGS: &str = "aria2c:-x16 -s16";
+const ARIA2C_WINDOWS_URL: &str =
+    "https://github.com/aria2/aria2/releases/download/release-1.37.0/aria2-1.37.0-win-64bit-build1.zip";
 
-        make_executable(&managed_ffmpeg);
-        if !ffmpeg_is_working(&managed_ffmpeg).await {
-            tokio::fs::remove_file(&managed_ffmpeg).await.ok();
-            return Err("Downloaded ffmpeg could not be validated".to_string());
+/// First-run setup progress combined from several steps that may run at the
+/// same time, so the setup bar moves forward steadily instead of jumping
+/// between the positions of each download.
+struct SetupProgress {
+    app: AppHandle,
+    /// Share of the whole bar and fraction done, per step.
+    parts: std::sync::Mutex<Vec<(f64, f64)>>,
+    /// Off for installs outside of setup, which has no bar on screen then.
+    visible: bool,
+}
+
+impl SetupProgress {
+    fn new(app: AppHandle, shares: &[f64]) -> Self {
+        Self {
+            app,
+            parts: std::sync::Mutex::new(shares.iter().map(|share| (*share, 0.0)).collect()),
+            visible: true,
         }
     }
 
-    app.emit("setup-progress", 1.0_f64).ok();
-    app.emit("setup-done", ()).ok();
+    fn hidden(app: AppHandle, shares: &[f64]) -> Self {
+        Self {
+            visible: false,
+            ..Self::new(app, shares)
+        }
+    }
+
+    fn set(&self, part: usize, fraction: f64) {
+        let total = {
+            let mut parts = self.parts.lock().unwrap();
+            parts[part].1 = fraction.clamp(0.0, 1.0);
+            parts.iter().map(|(share, done)| share * done).sum::<f64>()
+        };
+        if self.visible {
+            self.app.emit("setup-progress", total).ok();
+        }
+    }
+}
+
+async fn install_ytdlp(
+    app: &AppHandle,
+    client: &HttpClient,
+    progress: &SetupProgress,
+) -> Result<(), String> {
+    let managed_ytdlp = managed_ytdlp_path(app);
+    tokio::fs::remove_file(&managed_ytdlp).await.ok();
+    let checksum = fetch_ytdlp_checksum(client).await?;
+    download_file(
+        client,
+        ytdlp_download_url(),
+        &managed_ytdlp,
+        progress,
+        SETUP_PART_YTDLP,
+    )
+    .await?;
+    verify_checksum(&managed_ytdlp, &checksum, "yt-dlp").await?;
+    make_executable(&managed_ytdlp);
+    read_ytdlp_version(&managed_ytdlp)
+        .await
+        .map_err(|e| format!("Downloaded yt-dlp could not be validated: {e}"))?;
     Ok(())
 }
 
-#[tauri::command]
-async fn start_download(
-    app: AppHandle,
-    state: State<'_, AppState>,
-    url: String,
-    format_args: Vec<String>,
-    output_path: String,
-    cookie_browser: String,
+async fn install_ffmpeg(
+    app: &AppHandle,
+    client: &HttpClient,
+    dir: &std::path::Path,
+    progress: &SetupProgress,
 ) -> Result<(), String> {
-    use tokio::io::AsyncBufReadExt;
+    let managed_ffmpeg = managed_ffmpeg_path(app);
+    let managed_ffprobe = ffprobe_beside(&managed_ffmpeg);
+    tokio::fs::remove_file(&managed_ffmpeg).await.ok();
+    tokio::fs::remove_file(&managed_ffprobe).await.ok();
+    // Linux builds come as tar.xz, with the binaries inside a versioned
+    // folder such as `ffmpeg-7.0.2-amd64-static/`; the Windows zip keeps
+    // them under `ffmpeg-master-latest-win64-gpl/bin/`.
+    if cfg!(windows) {
+        let url = "https://github.com/BtbN/ffmpeg-builds/releases/latest/download/ffmpeg-master-latest-win64-gpl.zip";
+        install_ffmpeg_archive(
+            app,
+            client,
+            url,
+            dir,
+            false,
+            &["ffmpeg.exe", "ffprobe.exe"],
+            progress,
+            SETUP_PART_FFMPEG,
+        )
+        .await?;
+    } else if cfg!(target_os = "linux") {
+        let url = if cfg!(target_arch = "aarch64") {
+            "https://johnvansickle.com/ffmpeg/releases/ffmpeg-release-arm64-static.tar.xz"
+        } else {
+            "https://johnvansickle.com/ffmpeg/releases/ffmpeg-release-amd64-static.tar.xz"
+        };
+        install_ffmpeg_archive(
+            app,
+            client,
+            url,
+            dir,
+            true,
+            &["ffmpeg", "ffprobe"],
+            progress,
+            SETUP_PART_FFMPEG,
+        )
+        .await?;
+    } else {
+        // evermeet.cx builds each tool into a zip of its own.
+        install_ffmpeg_archive(
+            app,
+            client,
+            "https://evermeet.cx/ffmpeg/getrelease/zip",
+            dir,
+            false,
+            &["ffmpeg"],
+            progress,
+            SETUP_PART_FFMPEG,
+        )
+        .await?;
+        install_ffmpeg_archive(
+            app,
+            client,
+            "https://evermeet.cx/ffmpeg/getrelease/ffprobe/zip",
+            dir,
+            false,
+            &["ffprobe"],
+            progress,
+            SETUP_PART_FFPROBE,
+        )
+        .await?;
+    }
+    progress.set(SETUP_PART_EXTRACT, 1.0);
 
-    // Cancel any running download first
-    {
-        let mut lock = state.cancel_tx.lock().await;
-        if let Some(tx) = lock.take() {
-            let _ = tx.send(());
+    for (binary, tool) in [(&managed_ffmpeg, "ffmpeg"), (&managed_ffprobe, "ffprobe")] {
+        make_executable(binary);
+        if !ffmpeg_is_working(binary).await {
+            tokio::fs::remove_file(&managed_ffmpeg).await.ok();
+            tokio::fs::remove_file(&managed_ffprobe).await.ok();
+            return Err(format!("Downloaded {tool} could not be validated"));
         }
     }
+    Ok(())
+}
 
-    // Build yt-dlp argument list
-    let (yt_dlp, _) = resolve_ytdlp(&app)
-        .await
-        .ok_or_else(|| "yt-dlp is not installed".to_string())?;
-    let (ffmpeg, _) = resolve_ffmpeg(&app)
-        .await
-        .ok_or_else(|| "ffmpeg is not installed".to_string())?;
-    let audio_only = format_args
-        .iter()
-        .any(|argument| argument == "-x" || argument == "bestaudio/best");
-    let output_template = if audio_only {
-        "%(title)s [%(id)s].%(ext)s"
-    } else {
-        "%(title)s [%(id)s] [%(height)sp].%(ext)s"
-    };
-    let mut args: Vec<String> = format_args;
-    args.push("--no-ignore-errors".to_string());
-    if is_direct_instagram_story_url(&url) {
-        args.push("--no-playlist".to_string());
+async fn install_aria2c(
+    app: &AppHandle,
+    client: &HttpClient,
+    dir: &std::path::Path,
+    progress: &SetupProgress,
+) -> Result<(), String> {
+    let archive_path = dir.join("aria2c.zip");
+    download_file(
+        client,
+        ARIA2C_WINDOWS_URL,
+        &archive_path,
+        progress,
+        SETUP_PART_ARIA2C,
+    )
+    .await?;
+    let extract_to = dir.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let extracted = extract_binaries(&archive_path, &["aria2c.exe"], &extract_to);
+        std::fs::remove_file(&archive_path).ok();
+        extracted
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("Could not install aria2c: {e}"))?;
+
+    let managed_aria2c = managed_aria2c_path(app);
+    make_executable(&managed_aria2c);
+    if !tool_is_working(&managed_aria2c, "--version").await {
+        tokio::fs::remove_file(&managed_aria2c).await.ok();
+        return Err("Downloaded aria2c could not be validated".to_string());
+    }
+    Ok(())
+}
+
+/// Downloads an ffmpeg build archive into `dir`, the app's bin folder, and
+/// extracts `binaries` from it there.
+#[allow(clippy::too_many_arguments)]
+async fn install_ffmpeg_archive(
+    app: &AppHandle,
+    client: &HttpClient,
+    url: &str,
+    dir: &std::path::Path,
+    tar_xz: bool,
+    binaries: &'static [&'static str],
+    progress: &SetupProgress,
+    part: usize,
+) -> Result<(), String> {
+    let archive_path = dir.join(format!(
+        "{}.{}",
+        binaries[0],
+        if tar_xz { "tar.xz" } else { "zip" }
+    ));
+    download_file(client, url, &archive_path, progress, part).await?;
+    // The ffmpeg hosts publish no checksums we can rely on, so a truncated
+    // download is caught by the archive failing to extract or coming out
+    // empty.
+    if !file_has_content(&archive_path).await {
+        tokio::fs::remove_file(&archive_path).await.ok();
+        return Err(
+            "Downloaded ffmpeg archive is empty. Check your connection and try again.".to_string(),
+        );
     }
-    let session_cookie_path = cookie_path_for_browser(&state.cookie_dir, &cookie_browser)?;
-    if let Some(cookie_path) = &session_cookie_path {
-        let has_cached_cookies = cookie_jar_has_entries(cookie_path);
-        let cookie_path = cookie_path.to_string_lossy().into_owned();
-        if has_cached_cookies {
-            args.push("--cookies".to_string());
-            args.push(cookie_path);
+
+    app.emit("setup-task", "Extracting ffmpeg…").ok();
+    logs::write(app, None, "Extracting ffmpeg…");
+    let extract_to = dir.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let extracted = if tar_xz {
+            extract_binaries_from_tar_xz(&archive_path, binaries, &extract_to)
         } else {
-            args.push("--cookies-from-browser".to_string());
-            args.push(cookie_browser);
-            args.push("--cookies".to_string());
-            args.push(cookie_path);
+            extract_binaries(&archive_path, binaries, &extract_to)
+        };
+        std::fs::remove_file(&archive_path).ok();
+        extracted
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    for binary in binaries {
+        let path = dir.join(binary);
+        if !file_has_content(&path).await {
+            tokio::fs::remove_file(&path).await.ok();
+            return Err(format!("{binary} extracted from the archive is empty"));
         }
     }
-    let ffmpeg_location = ffmpeg.to_string_lossy().into_owned();
-    args.extend([
-        "-S".to_string(),
-        "res,fps,br".to_string(),
-        "--merge-output-format".to_string(),
-        "mp4".to_string(),
-        "--remux-video".to_string(),
-        "mp4".to_string(),
-        "--ffmpeg-location".to_string(),
-        ffmpeg_location,
-        "--newline".to_string(),
-        "-P".to_string(),
-        output_path.clone(),
-        "-o".to_string(),
-        output_template.to_string(),
-        url,
-    ]);
+    Ok(())
+}
 
-    std::fs::create_dir_all(&output_path)
-        .map_err(|e| format!("Cannot create output folder: {}", e))?;
+/// Checks for yt-dlp and ffmpeg and downloads whatever is missing,
+/// returning once both are ready. The `setup-*` events are only emitted
+/// when something has to be downloaded.
+#[tauri::command]
+async fn ensure_deps(app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    if deps_available(&app, &state, false).await {
+        return Ok(());
+    }
+    download_deps(app).await
+}
 
-    let mut cmd = tokio::process::Command::new(yt_dlp);
-    cmd.args(&args)
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped());
+/// Optional download settings sent next to the core `start_download`
+/// arguments. Any field the frontend leaves out falls back to its default.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct DownloadOptions {
+    /// Sort for the best resolution and free codecs (AV1/VP9/Opus) merged
+    /// into MKV instead of H.264 remuxed into MP4.
+    prefer_quality_over_compatibility: bool,
+    /// A quality such as `1080p60 av1` or `720p h264`, chosen instead of the
+    /// format arguments. Falls back to the best quality when it cannot be
+    /// read.
+    quality: Option<String>,
+    /// Prefer the tallest video up to this many pixels high; taller formats
+    /// are only used when a video has nothing smaller. Ignored for audio.
+    max_height: Option<u32>,
+    /// Fragments of a DASH or HLS stream to fetch at once, 1 to 16;
+    /// `DEFAULT_CONCURRENT_FRAGMENTS` when unset.
+    concurrent_fragments: Option<u32>,
+    /// Only download uploads from this day on (`YYYYMMDD`).
+    date_after: Option<String>,
+    /// Only download uploads up to and including this day (`YYYYMMDD`).
+    date_before: Option<String>,
+    /// Stop a playlist after this many successfully downloaded items.
+    max_downloads: Option<u32>,
+    /// Only download these playlist items, such as `1-5,8` (counted from
+    /// 1; `-3:` for the last three).
+    playlist_items: Option<String>,
+    /// yt-dlp output template used instead of the default
+    /// `%(title)s [%(id)s] [%(height)sp].%(ext)s`. It may create folders
+    /// inside the output folder, as in `%(uploader)s/%(title)s.%(ext)s`.
+    output_template: Option<String>,
+    /// Keep filenames to ASCII without spaces or shell-special characters.
+    restrict_filenames: bool,
+    /// Avoid characters Windows filesystems reject. When unset this is turned
+    /// on automatically for FAT/exFAT output volumes such as USB sticks.
+    windows_filenames: Option<bool>,
+    /// A yt-dlp config file to load. yt-dlp applies command-line options
+    /// after config files, so arguments the app passes itself (output
+    /// folder, template, merge format, cookies, progress output) override
+    /// the same options in this file.
+    config_file: Option<String>,
+    /// Skip yt-dlp's system and user config files so only `config_file` (if
+    /// any) and the app's own arguments apply.
+    ignore_config: bool,
+    /// Attach the full info JSON to the media file. yt-dlp only supports
+    /// this for Matroska (mkv/mka) output.
+    embed_info_json: bool,
+    /// Skip videos shorter than this many seconds (e.g. Shorts).
+    min_duration: Option<u64>,
+    /// Skip videos longer than this many seconds.
+    max_duration: Option<u64>,
+    /// Download only from this point on (seconds, `MM:SS` or `HH:MM:SS`).
+    start_time: Option<String>,
+    /// Download only up to this point (seconds, `MM:SS` or `HH:MM:SS`).
+    end_time: Option<String>,
+    /// Download only these chapters, by the `label` from `list_chapters`.
+    download_chapters: Vec<String>,
+    /// SponsorBlock categories to mark as chapters.
+    sponsorblock_mark: Vec<String>,
+    /// SponsorBlock categories to cut out of the file.
+    sponsorblock_remove: Vec<String>,
+    /// A SponsorBlock API mirror to query instead of sponsor.ajay.app.
+    sponsorblock_api: Option<String>,
+    /// Save only the captions as an `.srt` transcript, without the video.
+    transcript_only: bool,
+    /// Caption language for `transcript_only`; English when unset.
+    transcript_language: Option<String>,
+    /// Subtitle languages to download next to the video, as listed by
+    /// `list_subtitles`.
+    subtitle_langs: Vec<String>,
+    /// Languages of automatic captions to download, for the tracks
+    /// `list_subtitles` marks as `automatic`. Uploaded subtitles in the same
+    /// language are taken instead when there are some.
+    auto_subtitle_langs: Vec<String>,
+    /// Embed the `subtitle_langs` into the video instead of keeping them as
+    /// separate files.
+    embed_subs: bool,
+    /// Download over several connections with aria2c. Without aria2c the
+    /// download goes ahead with yt-dlp's own downloader.
+    use_aria2: bool,
+    /// Embed the thumbnail as cover art.
+    embed_thumbnail: bool,
+    /// Write the title, uploader, date and description into the file's tags.
+    embed_metadata: bool,
+    /// A JPEG or PNG to embed as cover art instead of the video's own
+    /// thumbnail. Meant for single videos: in a playlist only the last file
+    /// gets it.
+    custom_thumbnail: Option<String>,
+    /// Crop the embedded thumbnail to a centered square, as music players
+    /// expect for album art. Implies `embed_thumbnail`.
+    crop_thumbnail_square: bool,
+    /// Write a `playlist.json` listing every downloaded item once a
+    /// playlist finishes.
+    write_playlist_manifest: bool,
+    /// Decode the finished file with ffmpeg to catch corrupt or truncated
+    /// downloads. Reads the whole file, so it takes a while for big ones.
+    verify_on_complete: bool,
+    /// Keep the video, or only extract its audio into `audio_format`.
+    download_kind: DownloadKind,
+    /// Extract the audio into this format (`mp3`, `m4a`, `opus`, `flac`, …).
+    /// Setting it extracts audio even when `download_kind` is left as video.
+    audio_format: Option<String>,
+    /// Target bitrate for lossy `audio_format`s, such as `192K`.
+    audio_bitrate: Option<String>,
+    /// Download timed lyrics as `.lrc` and embed them into the extracted
+    /// audio. Needs an `audio_format` whose container holds lyrics.
+    embed_lyrics: bool,
+    /// Lyrics language for `embed_lyrics`; English when unset.
+    lyrics_language: Option<String>,
+    /// Containers to merge into, in order of preference; yt-dlp uses the
+    /// first one that can hold the selected codecs. Defaults to `mp4, mkv`
+    /// (or `mkv` when preferring quality).
+    merge_format_fallback: Vec<String>,
+    log_level: LogLevel,
+    /// Most bandwidth this download may use, such as `2M` or `500K` (bytes
+    /// per second). The global limit from the settings still applies.
+    rate_limit: Option<String>,
+    /// Wait and retry automatically when the site answers HTTP 429.
+    retry_when_rate_limited: bool,
+    /// YouTube player client to extract with (e.g. `android`), which can
+    /// avoid throttling of the default web client.
+    player_client: Option<String>,
+    /// Restart a throttled YouTube download with the `android` player
+    /// client, which YouTube rarely slows down.
+    switch_client_when_throttled: bool,
+    /// Save the comments into an info JSON next to the video.
+    write_comments: bool,
+    /// Stop after this many YouTube comments; all of them when unset.
+    max_comments: Option<u32>,
+    /// What to do with partial files an earlier, unfinished download of the
+    /// same video left in the output folder.
+    on_partial: PartialAction,
+    /// A Netscape-format cookies file to use instead of a browser's cookies,
+    /// for setups without a browser or while its cookie store is locked.
+    /// yt-dlp saves updated cookies back into it.
+    cookie_file: Option<String>,
+    /// Page to send as `Referer`, which embedded videos often require
+    /// before the host serves the media.
+    referer: Option<String>,
+    /// Download a live stream from its beginning instead of the live edge.
+    live_from_start: bool,
+    /// Write straight into the output folder, without `.part` files or the
+    /// job's temp folder, for network shares and synced folders that handle
+    /// renames badly. An interrupted download cannot be resumed.
+    no_part: bool,
+    /// Skip playlist items that fail (such as removed or private videos)
+    /// instead of stopping the whole download.
+    ignore_errors: bool,
+}
 
-    // Put yt-dlp in its own process group so that killing it also kills
-    // any child processes it spawns (e.g. ffmpeg for merging).
-    #[cfg(unix)]
-    cmd.process_group(0);
+/// What a download keeps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DownloadKind {
+    #[default]
+    Video,
+    /// Only the audio, extracted with `-x` and no video format sorting.
+    Audio,
+}
 
-    let mut child = cmd
-        .spawn()
-        .map_err(|e| format!("Failed to launch yt-dlp: {}", e))?;
-    let sleep_guard = prevent_sleep(child.id());
+/// Choice for partial files found from an earlier attempt at a download.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PartialAction {
+    /// Continue from the partial files.
+    #[default]
+    Resume,
+    /// Delete them and download from the start.
+    Restart,
+    /// Don't start; report them in `partial-found` so the user can choose.
+    Ask,
+}
 
-    let stdout = child.stdout.take().unwrap();
-    let stderr = child.stderr.take().unwrap();
+/// Payload of `partial-found`.
+#[derive(Clone, Serialize)]
+struct PartialFound {
+    url: String,
+    files: Vec<String>,
+    /// Bytes already downloaded, over all partial files.
+    bytes: u64,
+}
 
-    let (cancel_tx, cancel_rx) = oneshot::channel::<()>();
-    *state.cancel_tx.lock().await = Some(cancel_tx);
+/// How much of yt-dlp's diagnostic output reaches the download log.
+/// Progress and file lines are always shown since the app relies on them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LogLevel {
+    /// Warnings are suppressed; only errors from stderr are shown.
+    Quiet,
+    #[default]
+    Normal,
+    /// Adds yt-dlp's debug output, useful for bug reports.
+    Verbose,
+}
 
-    // Stream stdout → frontend (with progress parsing)
-    let app1 = app.clone();
-    tokio::spawn(async move {
-        let mut lines = tokio::io::BufReader::new(stdout).lines();
-        while let Ok(Some(line)) = lines.next_line().await {
-            app1.emit("download-log", &line).ok();
-            if let Some(pct) = parse_progress(&line) {
-                app1.emit("download-progress", pct / 100.0).ok();
-            }
-        }
-    });
+impl LogLevel {
+    fn shows_stderr_line(self, line: &str) -> bool {
+        self != LogLevel::Quiet || line.starts_with("ERROR:")
+    }
+}
 
-    // Stream stderr → frontend
-    let app2 = app.clone();
-    tokio::spawn(async move {
-        let mut lines = tokio::io::BufReader::new(stderr).lines();
-        while let Ok(Some(line)) = lines.next_line().await {
-            app2.emit("download-log", &line).ok();
+impl DownloadOptions {
+    /// Combinations that are allowed but probably won't do what the user
+    /// expects; reported in the download log before the job starts.
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Some(Err(error)) = self.quality.as_deref().map(quality_sort) {
+            warnings.push(format!(
+                "{error}; downloading the best available quality instead."
+            ));
         }
-    });
-
-    // Wait for exit or cancellation
-    let app3 = app.clone();
-    let cancelled = Arc::new(AtomicBool::new(false));
-    let cancelled_c = cancelled.clone();
-    tokio::spawn(async move {
-        let _sleep_guard = sleep_guard;
-        tokio::select! {
-            result = child.wait() => {
-                if !cancelled.load(Ordering::SeqCst) {
-                    match result {
-                        Ok(status) => {
-                            if status.code() == Some(0) {
-                                app3.emit("download-complete", ()).ok();
-                            } else {
-                                let code = status.code().unwrap_or(-1);
-                                app3.emit("download-error",
-                                    format!("yt-dlp exited with code {}", code)).ok();
-                            }
-                        }
-                        Err(e) => { app3.emit("download-error", e.to_string()).ok(); }
-                    }
-                }
-            }
-            _ = cancel_rx => {
-                cancelled_c.store(true, Ordering::SeqCst);
-                // Kill the whole process group so ffmpeg (spawned by yt-dlp)
-                // is also terminated.
-                #[cfg(unix)]
-                if let Some(pid) = child.id() {
-                    std::process::Command::new("kill")
-                        .args(["-KILL", &format!("-{}", pid)])
-                        .status()
-                        .ok();
-                }
-                #[cfg(windows)]
-                if let Some(pid) = child.id() {
-                    std::process::Command::new("taskkill")
-                        .args(["/F", "/T", "/PID", &pid.to_string()])
-                        .status()
-                        .ok();
-                }
-                child.kill().await.ok();
+        let containers = self.output_containers();
+        let without_info_json = containers
+            .iter()
+            .filter(|container| !INFO_JSON_CONTAINERS.contains(&container.as_str()))
+            .map(|container| container.to_ascii_uppercase())
+            .collect::<Vec<_>>();
+        if self.embed_info_json && !without_info_json.is_empty() {
+            let mut warning = format!(
+                "Info JSON can only be embedded into MKV files; {} output will not contain it.",
+                without_info_json.join(" or ")
+            );
+            if !self.extracts_audio() {
+                warning.push_str(" Merge into MKV only to embed it.");
             }
+            warnings.push(warning);
         }
-        if let Some(cookie_path) = session_cookie_path {
-            secure_cookie_file(&cookie_path);
+        if self.no_part {
+            warnings.push(
+                "Without .part files an interrupted download cannot be resumed, and its incomplete file looks finished. Delete it before downloading again.".to_string(),
+            );
         }
-    });
-
-    Ok(())
-}
+        if self.write_comments {
+            warnings.push(
+                "Fetching comments can take much longer than the download itself for popular videos. Limit the number of comments to speed it up.".to_string(),
+            );
+        }
+        if self.embed_thumbnail && self.log_level == LogLevel::Quiet {
+            warnings.push(
+                "Embedding the thumbnail into MP4 fails for some codecs, and the quiet log level hides the warning when it does. Use the normal log level to see it.".to_string(),
+            );
+        }
+        warnings
+    }
 
-#[tauri::command]
-async fn cancel_download(state: State<'_, AppState>) -> Result<(), ()> {
-    let mut lock = state.cancel_tx.lock().await;
-    if let Some(tx) = lock.take() {
-        let _ = tx.send(());
+    fn cookie_file(&self) -> Option<&str> {
+        self.cookie_file
+            .as_deref()
+            .map(str::trim)
+            .filter(|file| !file.is_empty())
     }
-    Ok(())
-}
 
-#[tauri::command]
-fn open_folder(path: String) {
-    #[cfg(target_os = "macos")]
-    {
-        std::process::Command::new("open").arg(&path).spawn().ok();
+    /// `rate_limit` in bytes per second.
+    fn rate_limit_bytes(&self) -> Result<Option<u64>, String> {
+        self.rate_limit
+            .as_deref()
+            .filter(|limit| !limit.trim().is_empty())
+            .map(settings::parse_rate_limit)
+            .transpose()
     }
-    #[cfg(target_os = "windows")]
-    {
-        std::process::Command::new("explorer")
-            .arg(&path)
-            .spawn()
-            .ok();
+
+    /// Whether only the audio is extracted.
+    fn extracts_audio(&self) -> bool {
+        self.download_kind == DownloadKind::Audio || self.audio_format.is_some()
     }
-    #[cfg(target_os = "linux")]
-    {
-        std::process::Command::new("xdg-open")
-            .arg(&path)
-            .spawn()
-            .ok();
+
+    /// ffmpeg encoders the download's post-processing needs.
+    fn required_encoders(&self) -> Vec<&'static str> {
+        let mut encoders = Vec::new();
+        if let Some(encoder) = self
+            .audio_format
+            .as_deref()
+            .and_then(|format| audio_encoder(&format.trim().to_ascii_lowercase()))
+        {
+            encoders.push(encoder);
+        }
+        if (self.embed_thumbnail || self.crop_thumbnail_square) && self.custom_thumbnail.is_none() {
+            encoders.push("mjpeg");
+        }
+        encoders
     }
-}
 
-// ── Private helpers ───────────────────────────────────────────────────────────
+    /// Containers the download may end up in: the extracted audio's, or the
+    /// video containers `format_preference_args` merges into.
+    fn output_containers(&self) -> Vec<String> {
+        if let Some(format) = self.audio_format.as_deref() {
+            return vec![
+                extracted_audio_extension(&format.trim().to_ascii_lowercase()).to_string(),
+            ];
+        }
+        if !self.merge_format_fallback.is_empty() {
+            return self
+                .merge_format_fallback
+                .iter()
+                .map(|container| container.trim().to_ascii_lowercase())
+                .collect();
+        }
+        let defaults: &[&str] = if self.prefer_quality_over_compatibility {
+            &["mkv"]
+        } else {
+            &["mp4", "mkv"]
+        };
+        defaults
+            .iter()
+            .map(|container| container.to_string())
+            .collect()
+    }
 
-fn cookie_path_for_browser(
-    cookie_dir: &std::path::Path,
-    browser: &str,
-) -> Result<Option<PathBuf>, String> {
-    match browser {
-        "" | "none" => Ok(None),
-        "safari" | "chrome" | "firefox" | "edge" | "brave" => {
-            Ok(Some(cookie_dir.join(format!("{browser}.txt"))))
+    /// Checks that `image` can be embedded into the files this download
+    /// produces.
+    fn check_custom_thumbnail(&self, image: &str) -> Result<(), String> {
+        let path = std::path::Path::new(image);
+        if !path.is_file() {
+            return Err(format!("Thumbnail image not found: {image}"));
+        }
+        if cover_mime_type(path).is_none() {
+            return Err("Choose a .jpg or .png image as the thumbnail".to_string());
         }
-        _ => Err("Unsupported cookie browser".to_string()),
+        if let Some(container) = self
+            .output_containers()
+            .into_iter()
+            .find(|container| !COVER_CONTAINERS.contains(&container.as_str()))
+        {
+            return Err(format!(
+                "A custom thumbnail cannot be embedded into {container} files. It works with: {}",
+                COVER_CONTAINERS.join(", ")
+            ));
+        }
+        Ok(())
     }
-}
 
-fn is_direct_instagram_story_url(url: &str) -> bool {
-    let Some((_, story_path)) = url.split_once("instagram.com/stories/") else {
-        return false;
-    };
-    let mut segments = story_path
-        .split(['/', '?', '#'])
-        .filter(|segment| !segment.is_empty());
-    let _username = segments.next();
-    segments
-        .next()
-        .map(|story_id| story_id.chars().all(|character| character.is_ascii_digit()))
-        .unwrap_or(false)
-}
+    /// Validates the options and turns them into yt-dlp arguments.
+    fn yt_dlp_args(&self) -> Result<Vec<String>, String> {
+        let merge_formats = self
+            .merge_format_fallback
+            .iter()
+            .map(|container| container.trim().to_ascii_lowercase())
+            .collect::<Vec<_>>();
+        if let Some(container) = merge_formats
+            .iter()
+            .find(|container| !MERGE_CONTAINERS.contains(&container.as_str()))
+        {
+            return Err(format!(
+                "Cannot merge into \"{container}\". Choose from: {}",
+                MERGE_CONTAINERS.join(", ")
+            ));
+        }
+        if self.download_kind == DownloadKind::Audio && self.audio_format.is_none() {
+            return Err(format!(
+                "Choose the format to extract the audio into: {}",
+                AUDIO_FORMATS.join(", ")
+            ));
+        }
+        // Extracted audio is never merged or remuxed, and the video sort
+        // would pick a video format to extract it from.
+        let extracting_audio = self.extracts_audio();
+        let mut args = if extracting_audio {
+            Vec::new()
+        } else {
+            format_preference_args(self.prefer_quality_over_compatibility, &merge_formats)
+        };
+        if let Some(quality) = self.quality.as_deref().filter(|_| !extracting_audio) {
+            // yt-dlp ranks by the last -S first, ahead of the preference above.
+            args.extend(["-f".to_string(), "bv*+ba/b".to_string()]);
+            if let Ok(sort) = quality_sort(quality) {
+                args.extend(["-S".to_string(), sort]);
+            }
+        }
+        match self.max_height {
+            Some(0) => return Err("Maximum height must be greater than 0".to_string()),
+            // Coming last, the cap outranks a taller `quality`.
+            Some(height) if !extracting_audio => {
+                args.extend(["-S".to_string(), format!("res:{height}")]);
+            }
+            _ => {}
+        }
 
-fn cleanup_stale_cookie_dirs() {
-    let temp_dir = std::env::temp_dir();
-    let current_pid = std::process::id();
-    let Ok(entries) = std::fs::read_dir(&temp_dir) else {
-        return;
-    };
+        let fragments = self
+            .concurrent_fragments
+            .unwrap_or(DEFAULT_CONCURRENT_FRAGMENTS);
+        if !(1..=MAX_CONCURRENT_FRAGMENTS).contains(&fragments) {
+            return Err(format!(
+                "Concurrent fragments must be between 1 and {MAX_CONCURRENT_FRAGMENTS}, not {fragments}"
+            ));
+        }
+        args.extend(["--concurrent-fragments".to_string(), fragments.to_string()]);
 
-    for entry in entries.flatten() {
-        let name = entry.file_name();
-        let Some(name) = name.to_str() else {
-            continue;
-        };
-        let Some(pid) = name
-            .strip_prefix("video-downloader-cookies-")
-            .and_then(|value| value.parse::<u32>().ok())
-        else {
-            continue;
-        };
+        let date_after = self
+            .date_after
+            .as_deref()
+            .map(|value| parse_upload_date("Upload date from", value))
+            .transpose()?;
+        let date_before = self
+            .date_before
+            .as_deref()
+            .map(|value| parse_upload_date("Upload date until", value))
+            .transpose()?;
+        if let (Some((after, after_day)), Some((before, before_day))) = (&date_after, &date_before)
+        {
+            if after_day > before_day {
+                return Err(format!(
+                    "Upload date from ({after}) must not be later than upload date until ({before})"
+                ));
+            }
+        }
+        if let Some((date, _)) = date_after {
+            args.extend(["--dateafter".to_string(), date]);
+        }
+        if let Some((date, _)) = date_before {
+            args.extend(["--datebefore".to_string(), date]);
+        }
 
-        if pid != current_pid && !process_is_running(pid) {
-            std::fs::remove_dir_all(entry.path()).ok();
+        if let Some(filter) = duration_match_filter(self.min_duration, self.max_duration)? {
+            args.extend(["--match-filter".to_string(), filter]);
         }
-    }
-}
 
-#[cfg(unix)]
-fn process_is_running(pid: u32) -> bool {
-    std::process::Command::new("kill")
-        .args(["-0", &pid.to_string()])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .status()
-        .map(|status| status.success())
-        .unwrap_or(false)
-}
+        let section = clip_section(self.start_time.as_deref(), self.end_time.as_deref())?;
+        if section.is_some() && !self.download_chapters.is_empty() {
+            return Err("Choose either chapters or a start and end time, not both".to_string());
+        }
+        if let Some(section) = section {
+            // Without keyframes at the cuts the clip starts at the keyframe
+            // before `start_time`, often seconds early.
+            args.extend([
+                "--download-sections".to_string(),
+                section,
+                "--force-keyframes-at-cuts".to_string(),
+            ]);
+        }
 
-#[cfg(windows)]
-fn process_is_running(pid: u32) -> bool {
-    std::process::Command::new("tasklist")
-        .args(["/FI", &format!("PID eq {pid}"), "/FO", "CSV", "/NH"])
-        .output()
-        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&format!("\"{pid}\"")))
-        .unwrap_or(false)
-}
+        if let Some(items) = self.playlist_items.as_deref().map(str::trim) {
+            let valid = !items.is_empty()
+                && items
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || matches!(c, ',' | '-' | ':'));
+            if !valid {
+                return Err(format!(
+                    "Playlist items must be numbers and ranges such as 1-5,8, not \"{items}\""
+                ));
+            }
+            args.extend(["--playlist-items".to_string(), items.to_string()]);
+        }
 
-#[cfg(not(any(unix, windows)))]
-fn process_is_running(_pid: u32) -> bool {
-    false
-}
+        match self.max_downloads {
+            Some(0) => return Err("Maximum downloads must be at least 1".to_string()),
+            Some(limit) => args.extend(["--max-downloads".to_string(), limit.to_string()]),
+            None => {}
+        }
 
-struct SleepGuard {
-    helper: Option<std::process::Child>,
-}
+        if let Some(template) = self.output_template.as_deref() {
+            check_output_template(template)?;
+        }
+        self.rate_limit_bytes()?;
 
-impl Drop for SleepGuard {
-    fn drop(&mut self) {
-        if let Some(helper) = self.helper.as_mut() {
-            helper.kill().ok();
-            helper.wait().ok();
+        if self.restrict_filenames {
+            args.push("--restrict-filenames".to_string());
+        }
+        if self.windows_filenames == Some(true) {
+            args.push("--windows-filenames".to_string());
         }
-    }
-}
 
-fn prevent_sleep(pid: Option<u32>) -> SleepGuard {
-    let Some(pid) = pid else {
-        return SleepGuard { helper: None };
-    };
+        if self.ignore_config {
+            args.push("--ignore-config".to_string());
+        }
+        if let Some(config_file) = self.config_file.as_deref().map(str::trim) {
+            if !std::path::Path::new(config_file).is_file() {
+                return Err(format!("yt-dlp config file not found: {config_file}"));
+            }
+            args.extend(["--config-location".to_string(), config_file.to_string()]);
+        }
 
-    #[cfg(target_os = "macos")]
-    let helper = std::process::Command::new("/usr/bin/caffeinate")
-        .args(["-i", "-w", &pid.to_string()])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .spawn()
-        .ok();
+        if self.embed_info_json {
+            args.push("--embed-info-json".to_string());
+        }
 
-    #[cfg(windows)]
-    let helper = {
-        let script = format!(
-            r#"
-Add-Type -TypeDefinition @'
-using System;
-using System.Runtime.InteropServices;
-public static class SleepControl {{
-    [DllImport("kernel32.dll")]
-    public static extern uint SetThreadExecutionState(uint esFlags);
-}}
-'@
-[SleepControl]::SetThreadExecutionState(0x80000001) | Out-Null
-try {{ Wait-Process -Id {pid} -ErrorAction SilentlyContinue }}
-finally {{ [SleepControl]::SetThreadExecutionState(0x80000000) | Out-Null }}
-"#
-        );
-        std::process::Command::new("powershell.exe")
-            .args([
-                "-NoProfile",
-                "-NonInteractive",
-                "-WindowStyle",
-                "Hidden",
-                "-Command",
-                &script,
-            ])
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .spawn()
-            .ok()
-    };
+        if self.live_from_start {
+            args.push("--live-from-start".to_string());
+        }
 
-    #[cfg(not(any(target_os = "macos", windows)))]
-    let helper = None;
+        if self.no_part {
+            args.push("--no-part".to_string());
+        }
 
-    SleepGuard { helper }
+        if self.transcript_only {
+            let language = subtitle_language("transcript", self.transcript_language.as_deref())?;
+            // Automatic captions are requested alongside uploaded ones so
+            // videos without automatic captions still get a transcript. When
+            // both exist yt-dlp keeps the uploaded subtitles.
+            args.extend(
+                [
+                    "--skip-download",
+                    "--write-auto-subs",
+                    "--write-subs",
+                    "--sub-langs",
+                    language,
+                    "--convert-subs",
+                    "srt",
+                ]
+                .map(str::to_string),
+            );
+        }
+
+        if !self.subtitle_langs.is_empty() || !self.auto_subtitle_langs.is_empty() {
+            // The transcript and lyrics pick their own --sub-langs, which
+            // would replace these.
+            if self.transcript_only || self.embed_lyrics {
+                return Err(
+                    "Subtitles cannot be downloaded together with a transcript or lyrics"
+                        .to_string(),
+                );
+            }
+            let mut languages = Vec::new();
+            for language in self.subtitle_langs.iter().chain(&self.auto_subtitle_langs) {
+                let language = subtitle_language("subtitle", Some(language))?;
+                if !languages.contains(&language) {
+                    languages.push(language);
+                }
+            }
+            args.push("--write-subs".to_string());
+            if !self.auto_subtitle_langs.is_empty() {
+                args.push("--write-auto-subs".to_string());
+            }
+            args.extend(["--sub-langs".to_string(), languages.join(",")]);
+            if self.embed_subs {
+                args.push("--embed-subs".to_string());
+            }
+        } else if self.embed_subs {
+            return Err("Choose the subtitle languages to embed".to_string());
+        }
+
+        args.extend(audio_extraction_args(
+            self.audio_format.as_deref(),
+            self.audio_bitrate.as_deref(),
+        )?);
+
+        if self.embed_lyrics {
+            let format = self.audio_format.as_deref().map(str::trim).ok_or_else(|| {
+                "Lyrics can only be embedded when extracting audio. Choose an audio format first."
+                    .to_string()
+            })?;
+            if !LYRICS_AUDIO_FORMATS.contains(&format.to_ascii_lowercase().as_str()) {
+                return Err(format!(
+                    "Lyrics cannot be embedded into {format} files. Choose one of: {}",
+                    LYRICS_AUDIO_FORMATS.join(", ")
+                ));
+            }
+            if self.transcript_only {
+                return Err("Lyrics cannot be embedded when saving only a transcript".to_string());
+            }
+            let language = subtitle_language("lyrics", self.lyrics_language.as_deref())?;
+            args.extend(
+                [
+                    "--write-subs",
+                    "--sub-langs",
+                    language,
+                    "--convert-subs",
+                    "lrc",
+                ]
+                .map(str::to_string),
+            );
+        }
+
+        match self.log_level {
+            LogLevel::Quiet => args.push("--no-warnings".to_string()),
+            LogLevel::Normal => {}
+            LogLevel::Verbose => args.push("--verbose".to_string()),
+        }
+
+        if let Some(client) = self.player_client.as_deref().map(str::trim) {
+            if client.is_empty()
+                || !client
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ',')
+            {
+                return Err(format!("Invalid YouTube player client: {client}"));
+            }
+            args.extend([
+                "--extractor-args".to_string(),
+                format!("youtube:player_client={client}"),
+            ]);
+        }
+
+        if self.write_comments {
+            args.extend(["--write-info-json", "--write-comments"].map(str::to_string));
+        }
+        match self.max_comments {
+            Some(_) if !self.write_comments => {
+                return Err("Turn on saving comments to limit how many are saved".to_string())
+            }
+            Some(0) => return Err("Maximum comments must be at least 1".to_string()),
+            Some(limit) => args.extend([
+                "--extractor-args".to_string(),
+                format!("youtube:max_comments={limit}"),
+            ]),
+            None => {}
+        }
+
+        if let Some(referer) = self
+            .referer
+            .as_deref()
+            .map(str::trim)
+            .filter(|referer| !referer.is_empty())
+        {
+            let valid = reqwest::Url::parse(referer)
+                .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host());
+            if !valid {
+                return Err(format!("Referer must be an http(s) URL: {referer}"));
+            }
+            args.extend(["--referer".to_string(), referer.to_string()]);
+        }
+
+        if let Some(file) = self.cookie_file() {
+            if !std::path::Path::new(file).is_file() {
+                return Err(format!("Cookie file not found: {file}"));
+            }
+            args.extend(["--cookies".to_string(), file.to_string()]);
+        }
+
+        if let Some(image) = self.custom_thumbnail.as_deref() {
+            self.check_custom_thumbnail(image)?;
+        } else if self.embed_thumbnail || self.crop_thumbnail_square {
+            args.push("--embed-thumbnail".to_string());
+        }
+        if self.crop_thumbnail_square && self.custom_thumbnail.is_none() {
+            // Converting to JPEG with a crop filter runs before embedding.
+            args.extend(
+                [
+                    "--convert-thumbnails",
+                    "jpg",
+                    "--ppa",
+                    "ThumbnailsConvertor+ffmpeg_o:-c:v mjpeg -qmin 1 -qscale:v 1 -vf crop=\"'min(iw,ih)':'min(iw,ih)'\"",
+                ]
+                .map(str::to_string),
+            );
+        } else if self.embed_thumbnail && self.custom_thumbnail.is_none() {
+            // MP4 and M4A cannot hold the WebP thumbnails many sites serve.
+            args.extend(["--convert-thumbnails".to_string(), "jpg".to_string()]);
+        }
+        if self.embed_metadata {
+            args.push("--embed-metadata".to_string());
+        }
+
+        if !self.sponsorblock_mark.is_empty() {
+            let categories = sponsorblock_categories(&self.sponsorblock_mark, true)?;
+            args.extend(["--sponsorblock-mark".to_string(), categories]);
+        }
+        if !self.sponsorblock_remove.is_empty() {
+            let categories = sponsorblock_categories(&self.sponsorblock_remove, false)?;
+            args.extend(["--sponsorblock-remove".to_string(), categories]);
+        }
+        if let Some(api) = self.sponsorblock_api.as_deref().map(str::trim) {
+            if !(api.starts_with("https://") || api.starts_with("http://")) {
+                return Err(format!("SponsorBlock API must be an http(s) URL: {api}"));
+            }
+            args.extend(["--sponsorblock-api".to_string(), api.to_string()]);
+        }
+
+        Ok(args)
+    }
 }
 
-fn cookie_jar_has_entries(path: &std::path::Path) -> bool {
-    std::fs::read_to_string(path)
-        .map(|contents| {
-            contents
-                .lines()
-                .any(|line| !line.is_empty() && !line.starts_with('#'))
-        })
-        .unwrap_or(false)
+/// Builds a `--match-filter` expression keeping videos whose duration lies
+/// within the given bounds (in seconds). Videos without a known duration,
+/// such as live streams, are kept.
+fn duration_match_filter(min: Option<u64>, max: Option<u64>) -> Result<Option<String>, String> {
+    if let (Some(min), Some(max)) = (min, max) {
+        if min >= max {
+            return Err(format!(
+                "Minimum duration ({min}s) must be shorter than maximum duration ({max}s)"
+            ));
+        }
+    }
+    let conditions: Vec<String> = [
+        min.map(|min| format!("duration >=? {min}")),
+        max.map(|max| format!("duration <=? {max}")),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    Ok((!conditions.is_empty()).then(|| conditions.join(" & ")))
 }
 
-#[cfg(unix)]
-fn secure_cookie_dir(path: &std::path::Path) {
-    use std::os::unix::fs::PermissionsExt;
-    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o700)).ok();
+/// Fragments yt-dlp fetches at once when a download does not choose.
+const DEFAULT_CONCURRENT_FRAGMENTS: u32 = 4;
+/// More parallel requests than this tend to get throttled by sites.
+const MAX_CONCURRENT_FRAGMENTS: u32 = 16;
+
+/// Builds a `--download-sections` range for the part of the video between
+/// `start` and `end` (read like `parse_timestamp`). Either may be left
+/// out to keep the beginning or the end.
+fn clip_section(start: Option<&str>, end: Option<&str>) -> Result<Option<String>, String> {
+    let start = start.map(str::trim).filter(|value| !value.is_empty());
+    let end = end.map(str::trim).filter(|value| !value.is_empty());
+    let parse = |label: &str, value: &str| {
+        parse_timestamp(value)
+            .ok_or_else(|| format!("{label} must be a time such as 01:02:03, not \"{value}\""))
+    };
+    let start_secs = start.map(|value| parse("Start time", value)).transpose()?;
+    let end_secs = end.map(|value| parse("End time", value)).transpose()?;
+    if let (Some(start_secs), Some(end_secs)) = (start_secs, end_secs) {
+        if start_secs >= end_secs {
+            return Err(format!(
+                "Start time ({}) must be before end time ({})",
+                start.unwrap_or_default(),
+                end.unwrap_or_default()
+            ));
+        }
+    }
+    Ok(match (start, end) {
+        (None, None) => None,
+        (start, end) => Some(format!(
+            "*{}-{}",
+            start.unwrap_or("0"),
+            end.unwrap_or("inf")
+        )),
+    })
 }
 
-#[cfg(not(unix))]
-fn secure_cookie_dir(_path: &std::path::Path) {}
+/// The `-o` template of a download. Several chosen chapters are saved as
+/// separate files, so the section goes into their names after the title
+/// (or before the extension, for a template without one).
+fn output_template(options: &DownloadOptions, audio_only: bool) -> String {
+    const SECTION: &str = " - %(section_number)s %(section_title)s";
+    let template = match options.output_template.as_deref() {
+        Some(template) => template.trim(),
+        None if audio_only => "%(title)s [%(id)s].%(ext)s",
+        None => "%(title)s [%(id)s] [%(height)sp].%(ext)s",
+    };
+    if options.download_chapters.len() < 2 || template.contains("%(section_") {
+        return template.to_string();
+    }
+    let at = match template.find("%(title)s") {
+        Some(at) => at + "%(title)s".len(),
+        None => template.rfind(".%(ext)s").unwrap_or(template.len()),
+    };
+    format!("{}{SECTION}{}", &template[..at], &template[at..])
+}
 
-#[cfg(unix)]
-fn secure_cookie_file(path: &std::path::Path) {
-    use std::os::unix::fs::PermissionsExt;
-    if path.exists() {
-        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).ok();
+/// Makes sure a custom output template names a file inside the output
+/// folder: subfolders are fine, absolute paths and `..` are not.
+fn check_output_template(template: &str) -> Result<(), String> {
+    let template = template.trim();
+    if template.is_empty() {
+        return Err("The output template is empty".to_string());
+    }
+    let escapes = template.starts_with(['/', '\\'])
+        || template.as_bytes().get(1) == Some(&b':')
+        || template
+            .split(['/', '\\'])
+            .any(|segment| segment.trim() == "..");
+    if escapes {
+        return Err(format!(
+            "The output template must stay inside the output folder: {template}"
+        ));
+    }
+    if !template.contains("%(ext)s") {
+        return Err(format!(
+            "The output template must end in .%(ext)s so files keep the right extension: {template}"
+        ));
     }
+    Ok(())
 }
 
-#[cfg(not(unix))]
-fn secure_cookie_file(_path: &std::path::Path) {}
+/// yt-dlp exits with this code once `--max-downloads` stops it early.
+const MAX_DOWNLOADS_REACHED_EXIT_CODE: i32 = 101;
 
-async fn read_ytdlp_version(path: &std::path::Path) -> Result<String, String> {
-    let mut command = tokio::process::Command::new(path);
-    command.arg("--version").kill_on_drop(true);
-    let output = tokio::time::timeout(std::time::Duration::from_secs(10), command.output())
+/// Everything needed to launch a download, kept so a job can be run again.
+#[derive(Clone, Serialize, Deserialize)]
+struct DownloadParams {
+    url: String,
+    /// Further URLs fetched by the same yt-dlp process after `url`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    more_urls: Vec<String>,
+    format_args: Vec<String>,
+    output_path: String,
+    cookie_browser: CookieBrowser,
+    #[serde(default)]
+    options: DownloadOptions,
+    /// Set when `quick_download` picked the settings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preset: Option<QuickPreset>,
+}
+
+impl DownloadParams {
+    fn is_audio_only(&self) -> bool {
+        self.options.extracts_audio()
+            || self
+                .format_args
+                .iter()
+                .any(|argument| argument == "-x" || argument == "bestaudio/best")
+    }
+}
+
+/// Downloads `url`, or all of `urls` with a single yt-dlp process so they
+/// share its start-up and cookie extraction. `batch-item-started` reports
+/// which of several URLs is being downloaded.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn start_download(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: Option<String>,
+    urls: Option<Vec<String>>,
+    format_args: Vec<String>,
+    output_path: String,
+    cookie_browser: CookieBrowser,
+    options: Option<DownloadOptions>,
+) -> Result<String, String> {
+    let mut urls: Vec<String> = url
+        .into_iter()
+        .chain(urls.unwrap_or_default())
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+        .collect();
+    let mut seen = std::collections::HashSet::new();
+    urls.retain(|url| seen.insert(url.clone()));
+    if urls.is_empty() {
+        return Err("Enter a URL to download".to_string());
+    }
+    for url in &urls {
+        validate_url(url)?;
+    }
+    let url = urls.remove(0);
+    let params = DownloadParams {
+        url,
+        more_urls: urls,
+        format_args,
+        output_path,
+        cookie_browser,
+        options: options.unwrap_or_default(),
+        preset: None,
+    };
+    let job_id = next_job_id();
+    launch_download(&app, &state, job_id.clone(), params).await?;
+    Ok(job_id)
+}
+
+/// Checks that `url` is an http(s) URL before yt-dlp is given it, so a typo
+/// fails with a clear message instead of a yt-dlp error.
+fn validate_url(url: &str) -> Result<(), String> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Err("Enter a URL to download".to_string());
+    }
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => Ok(()),
+        _ => Err(format!(
+            "\"{url}\" is not a web address. Paste a link starting with http:// or https://."
+        )),
+    }
+}
+
+/// Whether yt-dlp can download from `url`, found with a quick `--simulate`
+/// so the UI can disable the download button for unsupported links. Other
+/// failures, such as a private video, are reported as `Err`.
+#[tauri::command]
+async fn is_supported_url(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+) -> Result<bool, String> {
+    if validate_url(&url).is_err() {
+        return Ok(false);
+    }
+    let (yt_dlp, _) = resolve_ytdlp(&app)
         .await
-        .map_err(|_| "yt-dlp version check timed out".to_string())?
+        .ok_or_else(|| "yt-dlp is not installed".to_string())?;
+    let mut command = tokio::process::Command::new(yt_dlp);
+    command
+        .args(["--simulate", "--no-playlist", "--no-warnings"])
+        .args(proxy_args(&state).await)
+        .args(["--", url.trim()])
+        .kill_on_drop(true);
+    let output = tokio::time::timeout(std::time::Duration::from_secs(60), command.output())
+        .await
+        .map_err(|_| "Checking the URL timed out".to_string())?
         .map_err(|e| format!("Could not run yt-dlp: {e}"))?;
+    if output.status.success() {
+        return Ok(true);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    let error = DownloadError::from_exit(output.status.code().unwrap_or(-1), stderr);
+    match error.kind {
+        ErrorKind::UnsupportedUrl => Ok(false),
+        _ => Err(error.message),
+    }
+}
+
+/// Settings `quick_download` can choose for a URL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum QuickPreset {
+    /// Best quality MP4 video.
+    Video,
+    /// Best audio only, for music platforms.
+    Audio,
+    /// A live stream recorded from its start.
+    Live,
+}
+
+/// Extractors of sites that are mostly about music.
+const MUSIC_EXTRACTORS: [&str; 4] = ["soundcloud", "bandcamp", "mixcloud", "audiomack"];
+
+/// Picks the preset from the URL and its info JSON.
+fn quick_preset(url: &str, info: &serde_json::Value) -> QuickPreset {
+    let live_status = info["live_status"].as_str();
+    if info["is_live"].as_bool() == Some(true)
+        || matches!(live_status, Some("is_live" | "is_upcoming"))
+    {
+        return QuickPreset::Live;
+    }
+    let extractor = info["extractor_key"]
+        .as_str()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let host = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let music = MUSIC_EXTRACTORS
+        .iter()
+        .any(|name| extractor.starts_with(name))
+        || host.starts_with("music.");
+    if music {
+        QuickPreset::Audio
+    } else {
+        QuickPreset::Video
+    }
+}
+
+fn quick_download_params(url: String, output_path: String, preset: QuickPreset) -> DownloadParams {
+    let mut options = DownloadOptions::default();
+    let format = match preset {
+        QuickPreset::Video => "bv*[ext=mp4]+ba[ext=m4a]/b[ext=mp4]/bv*+ba/b",
+        QuickPreset::Audio => "bestaudio/best",
+        QuickPreset::Live => {
+            options.live_from_start = true;
+            "bv*+ba/b"
+        }
+    };
+    DownloadParams {
+        url,
+        more_urls: Vec::new(),
+        format_args: vec!["-f".to_string(), format.to_string()],
+        output_path,
+        cookie_browser: CookieBrowser::None,
+        options,
+        preset: Some(preset),
+    }
+}
+
+/// Downloads a URL with settings chosen for it: MP4 video for regular
+/// videos, audio for music sites and recording from the start for live
+/// streams. The choice is included in `download-started`.
+#[tauri::command]
+async fn quick_download(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+) -> Result<String, String> {
+    let info = fetch_video_info(&app, &state, &url, CookieBrowser::None).await?;
+    let preset = quick_preset(&url, &info);
+    let output_path = get_default_output_path(app.clone()).await;
+    let params = quick_download_params(url, output_path, preset);
+    let job_id = next_job_id();
+    launch_download(&app, &state, job_id.clone(), params).await?;
+    Ok(job_id)
+}
+
+/// Runs the most recent download again with identical parameters.
+#[tauri::command]
+async fn repeat_last_download(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let params = state
+        .last_download
+        .lock()
+        .await
+        .clone()
+        .ok_or_else(|| "There is no previous download to repeat".to_string())?;
+    let job_id = next_job_id();
+    launch_download(&app, &state, job_id.clone(), params).await?;
+    Ok(job_id)
+}
+
+/// Adds a download to the queue; it starts once earlier jobs are done.
+#[tauri::command]
+async fn enqueue_download(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+    format_args: Vec<String>,
+    output_path: String,
+    cookie_browser: CookieBrowser,
+    options: Option<DownloadOptions>,
+) -> Result<String, String> {
+    let params = DownloadParams {
+        url,
+        more_urls: Vec::new(),
+        format_args,
+        output_path,
+        cookie_browser,
+        options: options.unwrap_or_default(),
+        preset: None,
+    };
+    validate_url(&params.url)?;
+    params.options.yt_dlp_args()?;
+
+    let job_id = next_job_id();
+    state
+        .queue
+        .lock()
+        .await
+        .push(job_id.clone(), params.clone());
+    queue::emit_queue_updated(&app, &state).await;
+    state.queue_changed.notify_one();
+    spawn_size_probe(&app, job_id.clone(), params);
+
+    Ok(job_id)
+}
+
+/// Queues every URL listed in the text file `file_path`, one per line;
+/// blank lines and lines starting with `#` are skipped. The jobs run in
+/// order and report `batch-progress` as each one ends. Unless
+/// `continue_on_error` is set, the first failure cancels the rest. Returns
+/// the batch id.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn start_batch_download(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    file_path: String,
+    format_args: Vec<String>,
+    output_path: String,
+    cookie_browser: CookieBrowser,
+    options: Option<DownloadOptions>,
+    continue_on_error: Option<bool>,
+) -> Result<String, String> {
+    let text = tokio::fs::read_to_string(&file_path)
+        .await
+        .map_err(|e| format!("Could not read the list of URLs: {e}"))?;
+    let urls = batch_file_urls(&text)?;
+    if urls.is_empty() {
+        return Err("The file does not list any URLs".to_string());
+    }
+    let options = options.unwrap_or_default();
+    options.yt_dlp_args()?;
+
+    let jobs: Vec<(String, DownloadParams)> = urls
+        .into_iter()
+        .map(|url| {
+            let params = DownloadParams {
+                url,
+                more_urls: Vec::new(),
+                format_args: format_args.clone(),
+                output_path: output_path.clone(),
+                cookie_browser,
+                options: options.clone(),
+                preset: None,
+            };
+            (next_job_id(), params)
+        })
+        .collect();
+    let batch_id = next_job_id();
+    let progress = state.queue.lock().await.push_batch(
+        batch_id.clone(),
+        jobs.clone(),
+        continue_on_error.unwrap_or(false),
+    );
+    app.emit("batch-progress", progress).ok();
+    queue::emit_queue_updated(&app, &state).await;
+    state.queue_changed.notify_one();
+    for (job_id, params) in jobs {
+        spawn_size_probe(&app, job_id, params);
+    }
+    Ok(batch_id)
+}
+
+/// The URLs of a batch file, in order. Fails on the first line that is not a
+/// URL, naming its line number.
+fn batch_file_urls(text: &str) -> Result<Vec<String>, String> {
+    let mut urls = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        validate_url(line).map_err(|error| format!("Line {}: {error}", index + 1))?;
+        urls.push(line.to_string());
+    }
+    Ok(urls)
+}
+
+/// How many queued jobs are sized at once. A long batch file probed all at
+/// once would get the site answering with HTTP 429.
+const MAX_SIZE_PROBES: usize = 2;
+
+/// Sizes a queued job in the background so the queue ETA can include it.
+/// Only cookies already cached this session are used so the probe never
+/// triggers a browser keychain prompt.
+fn spawn_size_probe(app: &AppHandle, job_id: String, params: DownloadParams) {
+    let app = app.clone();
+    tokio::spawn(async move {
+        let state = app.state::<AppState>();
+        let Ok(_permit) = state.size_probes.acquire().await else {
+            return;
+        };
+        // The job may have been removed while it waited for its turn.
+        if !state.queue.lock().await.contains(&job_id) {
+            return;
+        }
+        let mut args = params.format_args.clone();
+        args.extend(params.options.yt_dlp_args().unwrap_or_default());
+        if let Some(cookie_path) = cookie_path_for_browser(&state.cookie_dir, params.cookie_browser)
+        {
+            if cookie_jar_has_entries(&cookie_path) {
+                args.extend([
+                    "--cookies".to_string(),
+                    cookie_path.to_string_lossy().into_owned(),
+                ]);
+            }
+        }
+        args.extend(proxy_args(&state).await);
+        let estimate = probe_download_size(&app, &args, &params.url).await;
+        state.queue.lock().await.set_estimate(&job_id, estimate);
+        app.emit("queue-eta", queue::queue_eta(&state).await).ok();
+    });
+}
+
+/// Records `export_data` and `import_data` can move between machines.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DataKind {
+    Queue,
+    History,
+}
+
+/// Saves the queue or the download history to `path` as JSON or CSV, going
+/// by its extension. Returns how many records were written.
+#[tauri::command]
+async fn export_data(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    kind: DataKind,
+    path: String,
+) -> Result<usize, String> {
+    let path = std::path::Path::new(&path);
+    let (contents, count) = match kind {
+        DataKind::Queue => {
+            let jobs = state.queue.lock().await.jobs().to_vec();
+            (queue::export_jobs(&jobs, path)?, jobs.len())
+        }
+        DataKind::History => history::export(&app, &state, path).await?,
+    };
+    tokio::fs::write(path, contents)
+        .await
+        .map_err(|e| format!("Could not write {}: {e}", path.display()))?;
+    Ok(count)
+}
+
+/// Adds the unfinished jobs of an exported queue to the queue, or the
+/// entries of an exported history missing from the history. Returns how
+/// many were added. The queue is paused first, so imported jobs only run
+/// once the user resumes it.
+#[tauri::command]
+async fn import_data(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    kind: DataKind,
+    path: String,
+) -> Result<usize, String> {
+    let path = std::path::Path::new(&path);
+    let text = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+    let jobs = match kind {
+        DataKind::Queue => queue::import_jobs(&text, path)?,
+        DataKind::History => return history::import(&app, &state, &text, path).await,
+    };
+    for params in &jobs {
+        for url in std::iter::once(&params.url).chain(&params.more_urls) {
+            validate_url(url)?;
+        }
+        params.options.yt_dlp_args()?;
+    }
+    let count = jobs.len();
+    if count > 0 {
+        set_queue_paused(&app, &state, true).await?;
+    }
+    for params in jobs {
+        let job_id = next_job_id();
+        state
+            .queue
+            .lock()
+            .await
+            .push(job_id.clone(), params.clone());
+        spawn_size_probe(&app, job_id, params);
+    }
+    queue::emit_queue_updated(&app, &state).await;
+    state.queue_changed.notify_one();
+    Ok(count)
+}
+
+/// Moves a waiting job ahead of (or behind) others; higher runs sooner.
+#[tauri::command]
+async fn set_job_priority(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    job_id: String,
+    priority: i32,
+) -> Result<(), String> {
+    state.queue.lock().await.set_priority(&job_id, priority)?;
+    queue::emit_queue_updated(&app, &state).await;
+    Ok(())
+}
+
+/// Moves a waiting job's download into another folder, creating it first.
+#[tauri::command]
+async fn update_job_output(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    job_id: String,
+    new_output_path: String,
+) -> Result<(), String> {
+    let output_path = new_output_path.trim();
+    if output_path.is_empty() {
+        return Err("Choose a folder to save the download in".to_string());
+    }
+    std::fs::create_dir_all(output_path)
+        .map_err(|e| format!("Cannot create output folder: {}", e))?;
+    state
+        .queue
+        .lock()
+        .await
+        .set_output_path(&job_id, output_path.to_string())?;
+    queue::emit_queue_updated(&app, &state).await;
+    Ok(())
+}
+
+/// Puts a failed queue job back in line with its original parameters. Its
+/// partial files are kept, so the download resumes instead of starting over.
+#[tauri::command]
+async fn retry_job(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    job_id: String,
+) -> Result<(), String> {
+    state.queue.lock().await.retry(&job_id)?;
+    queue::emit_queue_updated(&app, &state).await;
+    state.queue_changed.notify_one();
+    Ok(())
+}
+
+/// Stops the queue from starting new jobs; running downloads finish.
+/// The pause survives restarts until `resume_queue`.
+#[tauri::command]
+async fn pause_queue(app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    set_queue_paused(&app, &state, true).await
+}
+
+#[tauri::command]
+async fn resume_queue(app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    set_queue_paused(&app, &state, false).await
+}
+
+async fn set_queue_paused(app: &AppHandle, state: &AppState, paused: bool) -> Result<(), String> {
+    settings::save_queue_paused(app, paused).await?;
+    state.queue_paused.store(paused, Ordering::SeqCst);
+    if paused {
+        app.emit("queue-paused", ()).ok();
+    } else {
+        app.emit("queue-resumed", ()).ok();
+        state.queue_changed.notify_one();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_queue(state: State<'_, AppState>) -> Result<Vec<queue::QueueJob>, ()> {
+    Ok(state.queue.lock().await.jobs().to_vec())
+}
+
+/// Live status of the running downloads, so the frontend can pick up where
+/// it left off after a reload.
+#[tauri::command]
+async fn get_active_jobs(state: State<'_, AppState>) -> Result<Vec<JobSnapshot>, ()> {
+    let active = state.active_jobs.lock().await;
+    Ok(active.values().map(|job| job.snapshot.clone()).collect())
+}
+
+/// Estimated seconds until the whole queue is done, from this session's
+/// average speed and the probed sizes of outstanding jobs.
+#[tauri::command]
+async fn get_queue_eta(state: State<'_, AppState>) -> Result<Option<u64>, ()> {
+    Ok(queue::queue_eta(&state).await)
+}
+
+/// Registers `job_id` as running, next to any other running downloads, and
+/// spawns yt-dlp. It is unregistered again if launching fails.
+async fn launch_download(
+    app: &AppHandle,
+    state: &AppState,
+    job_id: String,
+    params: DownloadParams,
+) -> Result<(), String> {
+    // Every way into the queue or a download ends up here.
+    for url in std::iter::once(&params.url).chain(&params.more_urls) {
+        validate_url(url)?;
+    }
+    let mut option_args = params.options.yt_dlp_args()?;
+    if params.options.cookie_file().is_some() && params.cookie_browser != CookieBrowser::None {
+        return Err(
+            "Use either a browser's cookies or a cookie file, not both. Set the cookie browser to none to use the file."
+                .to_string(),
+        );
+    }
+
+    let output_path = if state.datestamp_folders.load(Ordering::SeqCst) {
+        dated_folder(&params.output_path, chrono::Local::now().date_naive())
+    } else {
+        params.output_path.clone()
+    };
+    option_args.extend(chapter_args(app, state, &params).await?);
+    run_download(app, state, job_id, params, output_path, option_args, true).await
+}
+
+/// Registers `job_id` as running and spawns yt-dlp. `check_space` sizes the
+/// download first; jobs that are resumed or restarted skip it.
+async fn run_download(
+    app: &AppHandle,
+    state: &AppState,
+    job_id: String,
+    params: DownloadParams,
+    output_path: String,
+    mut option_args: Vec<String>,
+    check_space: bool,
+) -> Result<(), String> {
+    let global_limit = *state.global_rate_limit.lock().await;
+    let (cancel_tx, cancel_rx) = oneshot::channel::<()>();
+    let rate_limit = {
+        let mut active = state.active_jobs.lock().await;
+        let previous = active.insert(
+            job_id.clone(),
+            ActiveJob {
+                cancel_tx: Some(cancel_tx),
+                snapshot: JobSnapshot::new(job_id.clone(), params.url.clone()),
+                params: params.clone(),
+                output_path: output_path.clone(),
+                rate_limit: None,
+            },
+        );
+        // A queue job restarted before its last run finished cleaning up.
+        if let Some(mut previous) = previous {
+            previous.cancel();
+        }
+        let restarts = state.bandwidth_restarts.lock().await;
+        let share = bandwidth_share(global_limit, bandwidth_sharers(&active, &restarts));
+        let rate_limit = job_rate_limit(&params.options, share);
+        if let Some(job) = active.get_mut(&job_id) {
+            job.rate_limit = rate_limit;
+        }
+        rate_limit
+    };
+    if let Some(limit) = rate_limit {
+        option_args.extend(["--limit-rate".to_string(), limit.to_string()]);
+    }
+
+    let result = spawn_download(
+        app,
+        state,
+        &job_id,
+        params,
+        output_path,
+        option_args,
+        cancel_rx,
+        check_space,
+    )
+    .await;
+    if result.is_err() {
+        release_active_job(state, &job_id).await;
+    }
+    state.bandwidth_changed.notify_one();
+    result
+}
+
+/// How many downloads split the bandwidth limit: the running ones and those
+/// about to restart with their new share.
+fn bandwidth_sharers(
+    active: &HashMap<String, ActiveJob>,
+    restarts: &HashMap<String, BandwidthRestart>,
+) -> usize {
+    let running = active
+        .iter()
+        .filter(|(job_id, job)| job.cancel_tx.is_some() && !restarts.contains_key(*job_id))
+        .count();
+    running + restarts.len()
+}
+
+/// Each download's part of the global bandwidth `limit`.
+fn bandwidth_share(limit: Option<u64>, sharers: usize) -> Option<u64> {
+    limit.map(|limit| (limit / sharers.max(1) as u64).max(1))
+}
+
+/// The `--limit-rate` of a download: its own limit, or its share of the
+/// global one when that is lower.
+fn job_rate_limit(options: &DownloadOptions, share: Option<u64>) -> Option<u64> {
+    match (options.rate_limit_bytes().ok().flatten(), share) {
+        (Some(own), Some(share)) => Some(own.min(share)),
+        (own, share) => own.or(share),
+    }
+}
+
+/// Keeps the downloads together under `global_rate_limit`. yt-dlp cannot
+/// change the limit of a running process, so whenever downloads start or
+/// stop, those whose share changed are stopped and continued from their
+/// partial files with the new limit.
+async fn run_bandwidth_worker(app: AppHandle) {
+    let state = app.state::<AppState>();
+    loop {
+        state.bandwidth_changed.notified().await;
+        if state.shutting_down.load(Ordering::SeqCst) {
+            return;
+        }
+        let global_limit = *state.global_rate_limit.lock().await;
+        let stopping: Vec<String> = {
+            let mut active = state.active_jobs.lock().await;
+            let mut restarts = state.bandwidth_restarts.lock().await;
+            let share = bandwidth_share(global_limit, bandwidth_sharers(&active, &restarts));
+            let mut stopping = Vec::new();
+            for (job_id, job) in active.iter_mut() {
+                if job.cancel_tx.is_none()
+                    || job_rate_limit(&job.params.options, share) == job.rate_limit
+                {
+                    continue;
+                }
+                let job_to_restart = PausedJob {
+                    params: job.params.clone(),
+                    output_path: job.output_path.clone(),
+                };
+                restarts.insert(
+                    job_id.clone(),
+                    BandwidthRestart {
+                        job: job_to_restart,
+                        stopped: false,
+                    },
+                );
+                job.cancel();
+                stopping.push(job_id.clone());
+            }
+            stopping
+        };
+        if stopping.is_empty() {
+            continue;
+        }
+        for job_id in &stopping {
+            emit_log(
+                &app,
+                Some(job_id),
+                "Restarting to share the bandwidth limit with other downloads",
+            );
+        }
+        // The processes are killed outright, so this does not take long.
+        while !wait_for_jobs_to_stop(&state, &stopping).await {}
+
+        // Each restart runs on its own, so one slow start holds up no other.
+        for job_id in stopping {
+            tauri::async_runtime::spawn(restart_for_bandwidth(app.clone(), job_id));
+        }
+    }
+}
+
+/// Starts a job stopped by `run_bandwidth_worker` again with its new share.
+async fn restart_for_bandwidth(app: AppHandle, job_id: String) {
+    let state = app.state::<AppState>();
+    // Cancelled while it was stopping.
+    let Some(restart) = state.bandwidth_restarts.lock().await.remove(&job_id) else {
+        return;
+    };
+    let PausedJob {
+        params,
+        output_path,
+    } = restart.job;
+    let temp_dir = job_temp_dir(std::path::Path::new(&output_path), &job_id);
+    let result = match restart_args(&app, &state, &params).await {
+        Ok(option_args) => {
+            run_download(
+                &app,
+                &state,
+                job_id.clone(),
+                params,
+                output_path,
+                option_args,
+                false,
+            )
+            .await
+        }
+        Err(error) => Err(error),
+    };
+    if let Err(error) = result {
+        if !state.queue.lock().await.contains(&job_id) {
+            tokio::fs::remove_dir_all(&temp_dir).await.ok();
+            state.kept_temp_dirs.lock().await.remove(&job_id);
+        }
+        app.emit(
+            "download-error",
+            JobEvent::new(&job_id, DownloadError::unknown(error)),
+        )
+        .ok();
+        queue::job_finished(&app, &state, &job_id, JobStatus::Failed).await;
+    }
+}
+
+async fn release_active_job(state: &AppState, job_id: &str) {
+    let removed = state.active_jobs.lock().await.remove(job_id).is_some();
+    if removed {
+        write_status_file(state).await;
+    }
+}
+
+/// How often the status file is rewritten while the phase stays the same.
+const STATUS_FILE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Writes the snapshots of all running jobs, keyed by job id, to the status
+/// file as JSON (`{}` when no download is running). The file is replaced in
+/// one step so other programs polling it never read half of it. Writers
+/// hold the `status_file` lock, so concurrent jobs never share the temp file
+/// and the last write always has every job. Failures are ignored; the
+/// status file is only informational.
+async fn write_status_file(state: &AppState) {
+    let path = state.status_file.lock().await;
+    let Some(path) = path.as_deref() else {
+        return;
+    };
+    let Some(file_name) = path.file_name() else {
+        return;
+    };
+    let snapshots = state
+        .active_jobs
+        .lock()
+        .await
+        .iter()
+        .map(|(job_id, job)| (job_id.clone(), job.snapshot.clone()))
+        .collect::<std::collections::BTreeMap<_, _>>();
+    let Ok(json) = serde_json::to_string_pretty(&snapshots) else {
+        return;
+    };
+    let temp = path.with_file_name(format!("{}.tmp", file_name.to_string_lossy()));
+    if tokio::fs::write(&temp, json).await.is_ok() && tokio::fs::rename(&temp, path).await.is_err()
+    {
+        tokio::fs::remove_file(&temp).await.ok();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn spawn_download(
+    app: &AppHandle,
+    state: &AppState,
+    job_id: &str,
+    params: DownloadParams,
+    output_path: String,
+    option_args: Vec<String>,
+    mut cancel_rx: oneshot::Receiver<()>,
+    check_space: bool,
+) -> Result<(), String> {
+    use tokio::io::AsyncBufReadExt;
+    let DownloadParams {
+        url,
+        more_urls,
+        format_args,
+        cookie_browser,
+        ..
+    } = params.clone();
+    let batch_urls: Vec<String> = if more_urls.is_empty() {
+        Vec::new()
+    } else {
+        std::iter::once(url.clone()).chain(more_urls).collect()
+    };
+    let max_downloads = params.options.max_downloads;
+    let transcript_only = params.options.transcript_only;
+    let write_playlist_manifest = params.options.write_playlist_manifest;
+    let playlist_url = url.clone();
+    let log_level = params.options.log_level;
+    let retry_when_rate_limited = params.options.retry_when_rate_limited;
+    let retry_params = params.clone();
+    let history_params = params.clone();
+    let watch_throttling =
+        params.options.switch_client_when_throttled && params.options.player_client.is_none();
+    let windows_filenames = params.options.windows_filenames;
+    let ignore_errors = params.options.ignore_errors;
+    let mut sponsorblock = SponsorBlockResult {
+        marked: params.options.sponsorblock_mark.clone(),
+        removed: params.options.sponsorblock_remove.clone(),
+        ..Default::default()
+    };
+    let uses_sponsorblock = !(sponsorblock.marked.is_empty() && sponsorblock.removed.is_empty());
+
+    // Build yt-dlp argument list
+    let (yt_dlp, _) = resolve_ytdlp(app)
+        .await
+        .ok_or_else(|| "yt-dlp is not installed".to_string())?;
+    let ffmpeg = resolve_ffmpeg(app).await.map(|(path, _)| path);
+    if let Some(ffmpeg) = &ffmpeg {
+        require_encoders(ffmpeg, &params.options.required_encoders()).await?;
+    }
+    let verify_with = ffmpeg.clone().filter(|_| params.options.verify_on_complete);
+    let lyrics_with = ffmpeg.clone().filter(|_| params.options.embed_lyrics);
+    let custom_thumbnail = ffmpeg.clone().zip(params.options.custom_thumbnail.clone());
+    let audio_extension =
+        params.options.audio_format.as_deref().map(|format| {
+            extracted_audio_extension(&format.trim().to_ascii_lowercase()).to_string()
+        });
+    let audio_only = params.is_audio_only();
+    let output_template = output_template(&params.options, audio_only);
+    let mut args: Vec<String> = format_args;
+    args.push(
+        if ignore_errors {
+            "--ignore-errors"
+        } else {
+            "--no-ignore-errors"
+        }
+        .to_string(),
+    );
+    if is_direct_instagram_story_url(&url) {
+        args.push("--no-playlist".to_string());
+    }
+    let session_cookie_path = cookie_path_for_browser(&state.cookie_dir, cookie_browser);
+    args.extend(session_cookie_args(
+        session_cookie_path.as_deref(),
+        cookie_browser,
+    ));
+    args.extend(option_args);
+    args.extend(proxy_args(state).await);
+    if params.options.use_aria2 {
+        match ensure_aria2c(app, job_id).await {
+            Some(aria2c) => args.extend([
+                "--downloader".to_string(),
+                aria2c.to_string_lossy().into_owned(),
+                "--downloader-args".to_string(),
+                ARIA2C_ARGS.to_string(),
+            ]),
+            None => emit_log(
+                app,
+                Some(job_id),
+                "Warning: aria2c was not found, so this download uses yt-dlp's own downloader.",
+            ),
+        }
+    }
+    let args_without_ffmpeg = ffmpeg.is_none();
+    match &ffmpeg {
+        Some(ffmpeg) => args.extend([
+            "--ffmpeg-location".to_string(),
+            ffmpeg.to_string_lossy().into_owned(),
+        ]),
+        None => args = without_ffmpeg(args)?,
+    }
+    args.extend([
+        "--newline".to_string(),
+        "--continue".to_string(),
+        "-P".to_string(),
+        output_path.clone(),
+    ]);
+
+    std::fs::create_dir_all(&output_path)
+        .map_err(|e| format!("Cannot create output folder: {}", e))?;
+
+    if windows_filenames.is_none() && is_fat_volume(std::path::Path::new(&output_path)) {
+        args.push("--windows-filenames".to_string());
+    }
+
+    // Each job keeps its .part/fragment files in a private folder so two jobs
+    // writing into the same output folder cannot clobber each other. yt-dlp
+    // moves finished files up into the output folder.
+    let temp_dir = job_temp_dir(std::path::Path::new(&output_path), job_id);
+    std::fs::create_dir_all(&temp_dir)
+        .map_err(|e| format!("Cannot create temporary download folder: {}", e))?;
+    let mut busy_jobs = vec![job_id.to_string()];
+    busy_jobs.extend(state.active_jobs.lock().await.keys().cloned());
+    busy_jobs.extend(state.paused_jobs.lock().await.keys().cloned());
+    let partials = likely_video_id(&url)
+        .map(|id| find_partial_files(std::path::Path::new(&output_path), &id, &busy_jobs))
+        .unwrap_or_default();
+    if !partials.is_empty() {
+        let found = PartialFound {
+            url: url.clone(),
+            files: partials
+                .iter()
+                .map(|(path, _)| path.to_string_lossy().into_owned())
+                .collect(),
+            bytes: partials.iter().map(|(_, bytes)| bytes).sum(),
+        };
+        app.emit("partial-found", &found).ok();
+        match params.options.on_partial {
+            PartialAction::Ask => {
+                std::fs::remove_dir_all(&temp_dir).ok();
+                return Err(
+                    "This video was partly downloaded before. Choose whether to resume or restart it."
+                        .to_string(),
+                );
+            }
+            // yt-dlp only continues from files in the job's own temp folder.
+            PartialAction::Resume => {
+                for (path, _) in &partials {
+                    if let Some(name) = path.file_name() {
+                        std::fs::rename(path, temp_dir.join(name)).ok();
+                    }
+                }
+            }
+            PartialAction::Restart => {
+                for (path, _) in &partials {
+                    std::fs::remove_file(path).ok();
+                }
+                args.push("--no-continue".to_string());
+            }
+        }
+    }
+    // Only a single URL can be sized up front; a batch is left to the
+    // low-disk watch while it runs. yt-dlp reports the size of the whole
+    // video, so a clip or chapters may well fit when it does not.
+    if check_space && batch_urls.is_empty() && !transcript_only {
+        // The job id reaches the caller only once this returns, so the
+        // frontend learns it here to be able to cancel a slow probe.
+        app.emit("download-preparing", JobEvent::new(job_id, ()))
+            .ok();
+        let whole_video = !args.iter().any(|arg| arg == "--download-sections");
+        let needed = tokio::select! {
+            needed = download_size(app, state, job_id, &args, &url) => needed,
+            _ = &mut cancel_rx => {
+                stop_before_launch(app, state, job_id, &temp_dir).await;
+                return Ok(());
+            }
+        };
+        // Partial files continued from need no more room.
+        let needed = needed.map(|bytes| bytes.saturating_sub(files_size(&temp_dir)));
+        match needed {
+            Some(needed) if !whole_video => {
+                let available = free_space(PathBuf::from(&output_path)).await;
+                if available.is_some_and(|bytes| !has_room_for(needed, bytes)) {
+                    emit_log(
+                        app,
+                        Some(job_id),
+                        "Warning: the whole video would not fit on the disk; the chosen part may.",
+                    );
+                }
+            }
+            Some(needed) => {
+                let available = free_space(PathBuf::from(&output_path)).await;
+                if let Some(available) = available.filter(|bytes| !has_room_for(needed, *bytes)) {
+                    // Partial files stay for when there is room again.
+                    if files_size(&temp_dir) == 0 {
+                        std::fs::remove_dir_all(&temp_dir).ok();
+                    }
+                    let error = DownloadError::not_enough_space(&output_path, needed, available);
+                    let message = error.message.clone();
+                    app.emit("download-error", JobEvent::new(job_id, error))
+                        .ok();
+                    return Err(message);
+                }
+            }
+            None => emit_log(
+                app,
+                Some(job_id),
+                "Warning: the download size is unknown, so free disk space was not checked.",
+            ),
+        }
+    }
+
+    // yt-dlp appends one JSON line per item once it sits at its final path.
+    let manifest_items = temp_dir.join("items.jsonl");
+    if write_playlist_manifest {
+        args.extend([
+            "--print-to-file".to_string(),
+            format!("after_move:{PLAYLIST_ITEM_TEMPLATE}"),
+            manifest_items.to_string_lossy().into_owned(),
+        ]);
+    }
+    // The final path of every item, so each item of a playlist or batch
+    // gets the custom cover and not just the last one.
+    let moved_files = temp_dir.join("moved.txt");
+    if custom_thumbnail.is_some() {
+        args.extend([
+            "--print-to-file".to_string(),
+            "after_move:filepath".to_string(),
+            moved_files.to_string_lossy().into_owned(),
+        ]);
+    }
+    // Without .part files the streams are written where they end up, so
+    // nothing has to be moved there from the temp folder afterwards.
+    if !params.options.no_part {
+        args.extend([
+            "-P".to_string(),
+            format!("temp:{}", temp_dir.to_string_lossy()),
+        ]);
+    }
+    args.extend(["-o".to_string(), output_template]);
+    if batch_urls.is_empty() {
+        // Nothing after `--` is read as an option, whatever the URL holds.
+        args.extend(["--".to_string(), url]);
+    } else {
+        // A batch file keeps long URL lists clear of command-line length
+        // limits (about 32K characters on Windows).
+        let batch_file = temp_dir.join("urls.txt");
+        if let Err(error) = std::fs::write(&batch_file, batch_urls.join("\n")) {
+            std::fs::remove_dir_all(&temp_dir).ok();
+            return Err(format!("Cannot write the list of URLs: {error}"));
+        }
+        args.extend([
+            "--batch-file".to_string(),
+            batch_file.to_string_lossy().into_owned(),
+        ]);
+    }
+
+    let mut cmd = tokio::process::Command::new(yt_dlp);
+    cmd.args(&args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+
+    // Put yt-dlp in its own process group so that killing it also kills
+    // any child processes it spawns (e.g. ffmpeg for merging).
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(error) => {
+            std::fs::remove_dir_all(&temp_dir).ok();
+            return Err(format!("Failed to launch yt-dlp: {}", error));
+        }
+    };
+    let sleep_guard = prevent_sleep(child.id());
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    app.emit("download-started", JobEvent::new(job_id, &params))
+        .ok();
+    for warning in params.options.warnings() {
+        emit_log(app, Some(job_id), &format!("Warning: {warning}"));
+    }
+    if args_without_ffmpeg {
+        emit_log(
+            app,
+            Some(job_id),
+            "Warning: ffmpeg is not installed, so only formats that need no merging or conversion are downloaded.",
+        );
+    }
+    *state.last_download.lock().await = Some(params);
+
+    let started_at = std::time::Instant::now();
+    let tracker = Arc::new(std::sync::Mutex::new(TransferTracker::default()));
+
+    // Stream stdout → frontend (with progress parsing)
+    let app1 = app.clone();
+    let tracker1 = tracker.clone();
+    let job_id1 = job_id.to_string();
+    let output_dir1 = PathBuf::from(&output_path);
+    let temp_dir1 = temp_dir.clone();
+    let (throttle_tx, throttle_rx) = oneshot::channel::<u64>();
+    let stdout_task = tokio::spawn(async move {
+        let mut throttle_tx = Some(throttle_tx).filter(|_| watch_throttling);
+        let mut throttle = ThrottleDetector::default();
+        let mut is_youtube = false;
+        let mut last_status_write: Option<(JobPhase, std::time::Instant)> = None;
+        let mut playlist = PlaylistTracker::default();
+        let mut overall = OverallProgress::default();
+        let mut lines = tokio::io::BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            emit_log(&app1, Some(&job_id1), &line);
+            if let Some(index) = extracted_url(&line).and_then(|url| batch_index(url, &batch_urls))
+            {
+                let started = BatchItemStarted {
+                    job_id: job_id1.clone(),
+                    url: batch_urls[index].clone(),
+                    index,
+                    total: batch_urls.len(),
+                };
+                app1.emit("batch-item-started", started).ok();
+            }
+            if let Some(progress) = playlist.observe(&line) {
+                app1.emit("playlist-progress", JobEvent::new(&job_id1, progress))
+                    .ok();
+            }
+            is_youtube |= line.starts_with("[youtube]");
+            if let Some(speed) = parse_speed(&line).filter(|_| is_youtube && throttle_tx.is_some())
+            {
+                if throttle.observe(speed, std::time::Instant::now()) {
+                    app1.emit(
+                        "throttle-detected",
+                        ThrottleDetected {
+                            job_id: job_id1.clone(),
+                            speed,
+                        },
+                    )
+                    .ok();
+                    if let Some(tx) = throttle_tx.take() {
+                        tx.send(speed).ok();
+                    }
+                }
+            }
+            if let Some(pct) = overall.observe(&line) {
+                let progress = JobProgress {
+                    job_id: &job_id1,
+                    progress: pct / 100.0,
+                };
+                app1.emit("download-progress", progress).ok();
+                if let Some(stats) = parse_progress_line(&line) {
+                    app1.emit("download-stats", JobEvent::new(&job_id1, stats))
+                        .ok();
+                }
+                let state = app1.state::<AppState>();
+                state.queue.lock().await.set_progress(&job_id1, pct / 100.0);
+            }
+            if let Some(path) = parse_output_file(&line) {
+                let resolved = FileResolved {
+                    path: final_path(std::path::Path::new(path), &temp_dir1, &output_dir1)
+                        .to_string_lossy()
+                        .into_owned(),
+                };
+                app1.emit("download-file-resolved", JobEvent::new(&job_id1, resolved))
+                    .ok();
+            }
+            tracker1.lock().unwrap().observe(&line);
+            if line.starts_with('[') {
+                let state = app1.state::<AppState>();
+                let snapshot = {
+                    let mut active = state.active_jobs.lock().await;
+                    active.get_mut(&job_id1).map(|job| {
+                        job.snapshot.observe(&line);
+                        job.snapshot.clone()
+                    })
+                };
+                if let Some(snapshot) = snapshot {
+                    let due = last_status_write.is_none_or(|(phase, at)| {
+                        phase != snapshot.phase || at.elapsed() >= STATUS_FILE_INTERVAL
+                    });
+                    if due {
+                        write_status_file(&state).await;
+                        last_status_write = Some((snapshot.phase, std::time::Instant::now()));
+                    }
+                }
+            }
+            sponsorblock.observe(&line);
+        }
+        sponsorblock
+    });
+
+    // Stream stderr → frontend, keeping the tail to explain failures
+    let app2 = app.clone();
+    let job_id2 = job_id.to_string();
+    let stderr_task = tokio::spawn(async move {
+        let mut tail = std::collections::VecDeque::new();
+        let mut skipped = Vec::new();
+        let mut other_errors = false;
+        let mut lines = tokio::io::BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if log_level.shows_stderr_line(&line) {
+                emit_log(&app2, Some(&job_id2), &line);
+            }
+            match unavailable_item(&line) {
+                Some(item) => skipped.push(item),
+                None => other_errors |= line.starts_with("ERROR:"),
+            }
+            if tail.len() == STDERR_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+        (Vec::from(tail).join("\n"), skipped, other_errors)
+    });
+
+    // Wait for exit or cancellation
+    let app3 = app.clone();
+    let job_id3 = job_id.to_string();
+    tokio::spawn(async move {
+        let _sleep_guard = sleep_guard;
+        let outcome = tokio::select! {
+            result = child.wait() => {
+                match result {
+                    Ok(status) => {
+                        let limit_reached = max_downloads.is_some()
+                            && status.code() == Some(MAX_DOWNLOADS_REACHED_EXIT_CODE);
+                        let (stderr, skipped, other_errors) = stderr_task.await.unwrap_or_default();
+                        if !skipped.is_empty() {
+                            app3.emit("skipped-items", &skipped).ok();
+                        }
+                        // With --ignore-errors yt-dlp still exits with 1 when
+                        // it skipped something.
+                        let only_skipped = ignore_errors
+                            && status.code() == Some(1)
+                            && !skipped.is_empty()
+                            && !other_errors;
+                        if status.code() == Some(0) || limit_reached || only_skipped {
+                            // Let the reader drain the final lines so the
+                            // summary sees the last size and destination.
+                            let sponsorblock = stdout_task.await.ok();
+                            if let Some(result) = sponsorblock.filter(|_| uses_sponsorblock) {
+                                app3.emit("sponsorblock-result", result).ok();
+                            }
+                            if limit_reached {
+                                app3.emit("download-limit-reached", max_downloads).ok();
+                            }
+                            let (summary, transcripts, lyrics) = {
+                                let tracker = tracker.lock().unwrap();
+                                let output_dir = std::path::Path::new(&output_path);
+                                (
+                                    tracker.summary(started_at.elapsed()),
+                                    tracker.converted_subtitles(output_dir, "srt"),
+                                    tracker.converted_subtitles(output_dir, "lrc"),
+                                )
+                            };
+                            if transcript_only && transcripts.is_empty() {
+                                let message = "No captions are available for this video in the chosen language";
+                                app3.emit("download-error", JobEvent::new(&job_id3, DownloadError::unknown(message.to_string()))).ok();
+                                JobStatus::Failed
+                            } else {
+                                if transcript_only {
+                                    app3.emit("transcript-saved", transcripts).ok();
+                                }
+                                if write_playlist_manifest {
+                                    let output_dir = std::path::Path::new(&output_path);
+                                    match write_manifest(&manifest_items, output_dir, &playlist_url).await {
+                                        Ok(Some(path)) => {
+                                            app3.emit("playlist-manifest-written", path).ok();
+                                        }
+                                        Ok(None) => {}
+                                        Err(error) => {
+                                            emit_log(&app3, Some(&job_id3), &format!("Warning: {error}"));
+                                        }
+                                    }
+                                }
+                                if let (Some(ffmpeg), Some(extension)) = (&lyrics_with, audio_extension.as_deref()) {
+                                    for lyrics in &lyrics {
+                                        let lyrics = std::path::Path::new(lyrics);
+                                        if let Err(error) = embed_lyrics(ffmpeg, lyrics, extension).await {
+                                            emit_log(&app3, Some(&job_id3), &format!("Warning: {error}"));
+                                        }
+                                    }
+                                }
+                                if let Some((ffmpeg, image)) = &custom_thumbnail {
+                                    let moved = tokio::fs::read_to_string(&moved_files).await.unwrap_or_default();
+                                    for file in moved.lines().map(str::trim).filter(|file| !file.is_empty()) {
+                                        let (file, image) = (std::path::Path::new(file), std::path::Path::new(image));
+                                        if let Err(error) = embed_cover(ffmpeg, file, image).await {
+                                            emit_log(&app3, Some(&job_id3), &format!("Warning: {error}"));
+                                        }
+                                    }
+                                }
+                                app3.state::<AppState>().session_stats.lock().await.record(&summary);
+                                if let (Some(ffmpeg), Some(file)) = (verify_with, summary.output_file.clone()) {
+                                    let app = app3.clone();
+                                    tokio::spawn(async move {
+                                        let result = verify_media(&ffmpeg, &file).await;
+                                        app.emit("verification-result", result).ok();
+                                    });
+                                }
+                                let post_hook = app3.state::<AppState>().post_hook.lock().await.clone();
+                                if let (Some(hook), Some(file)) = (post_hook, summary.output_file.clone()) {
+                                    let app = app3.clone();
+                                    tokio::spawn(async move {
+                                        let result = run_post_hook(&hook, &file).await;
+                                        app.emit("post-hook-result", result).ok();
+                                    });
+                                }
+                                app3.emit("download-complete", JobEvent::new(&job_id3, summary)).ok();
+                                JobStatus::Completed
+                            }
+                        } else {
+                            let code = status.code().unwrap_or(-1);
+                            let error = DownloadError::from_exit(code, stderr);
+                            let state = app3.state::<AppState>();
+                            let rate_limit_retry = if error.kind == ErrorKind::RateLimited && retry_when_rate_limited {
+                                queue::retry_rate_limited(&state, &job_id3, retry_params.clone()).await
+                            } else {
+                                None
+                            };
+                            let retry = if rate_limit_retry.is_none() && error.kind.is_temporary() {
+                                queue::retry_failed(&state, &job_id3, retry_params).await
+                            } else {
+                                None
+                            };
+                            match (rate_limit_retry, retry) {
+                                (Some(retry), _) => {
+                                    app3.emit("download-rate-limited", retry).ok();
+                                    JobStatus::Pending
+                                }
+                                (None, Some(retry)) => {
+                                    app3.emit("job-retrying", retry).ok();
+                                    JobStatus::Retrying
+                                }
+                                (None, None) => {
+                                    let gave_up = if error.kind.is_temporary() {
+                                        queue::gave_up(&state, &job_id3).await
+                                    } else {
+                                        None
+                                    };
+                                    app3.emit("download-error", JobEvent::new(&job_id3, error)).ok();
+                                    if let Some(gave_up) = gave_up {
+                                        app3.emit("job-gave-up", gave_up).ok();
+                                    }
+                                    JobStatus::Failed
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        app3.emit("download-error", JobEvent::new(&job_id3, DownloadError::unknown(e.to_string()))).ok();
+                        JobStatus::Failed
+                    }
+                }
+            }
+            _ = cancel_rx => {
+                kill_process_tree(&mut child).await;
+                let state = app3.state::<AppState>();
+                let paused = state.paused_jobs.lock().await.contains_key(&job_id3);
+                let restarting = state
+                    .bandwidth_restarts
+                    .lock()
+                    .await
+                    .get_mut(&job_id3)
+                    .map(|restart| restart.stopped = true)
+                    .is_some();
+                if paused {
+                    JobStatus::Paused
+                } else if restarting {
+                    // It continues with a new bandwidth share.
+                    JobStatus::Running
+                } else {
+                    JobStatus::Cancelled
+                }
+            }
+            Ok(_) = throttle_rx => {
+                kill_process_tree(&mut child).await;
+                let state = app3.state::<AppState>();
+                let switch = queue::switch_player_client(&state, &job_id3, retry_params).await;
+                app3.emit("switching-client", switch).ok();
+                // The new client may pick different formats, so the partial
+                // files are of no use to the restarted job.
+                tokio::fs::remove_dir_all(&temp_dir).await.ok();
+                JobStatus::Pending
+            }
+            available = wait_for_low_disk(PathBuf::from(&output_path)) => {
+                kill_process_tree(&mut child).await;
+                app3.emit("download-error", JobEvent::new(&job_id3, DownloadError::disk_full(&output_path, available))).ok();
+                JobStatus::Failed
+            }
+        };
+        if let Some(cookie_path) = session_cookie_path {
+            secure_cookie_file(&cookie_path);
+        }
+        let state = app3.state::<AppState>();
+        let history_status = match outcome {
+            JobStatus::Completed => Some(history::HistoryStatus::Completed),
+            JobStatus::Failed => Some(history::HistoryStatus::Failed),
+            _ => None,
+        };
+        if let Some(status) = history_status {
+            let output_file = tracker.lock().unwrap().output_file.clone();
+            let entry = history::HistoryEntry::new(&history_params, output_file, status);
+            match history::record(&app3, &state, entry).await {
+                Ok(Some(backup)) => emit_log(
+                    &app3,
+                    Some(&job_id3),
+                    &format!(
+                        "Warning: the download history could not be read, so it was moved to {} and a new one started.",
+                        backup.display()
+                    ),
+                ),
+                Ok(None) => {}
+                Err(error) => emit_log(&app3, Some(&job_id3), &format!("Warning: {error}")),
+            }
+        }
+        // A failed queue job keeps its partial files so `retry_job` can
+        // continue where it stopped, a paused job so it can be resumed, and
+        // one stopped for a new bandwidth share (`Running`) so it continues.
+        let retryable = matches!(
+            outcome,
+            JobStatus::Failed | JobStatus::Pending | JobStatus::Retrying
+        ) && state.queue.lock().await.contains(&job_id3)
+            || outcome == JobStatus::Running;
+        if outcome != JobStatus::Paused {
+            // The job ended on its own just as it was paused.
+            state.paused_jobs.lock().await.remove(&job_id3);
+            if !retryable {
+                tokio::fs::remove_dir_all(&temp_dir).await.ok();
+            }
+        }
+        {
+            let mut kept = state.kept_temp_dirs.lock().await;
+            if outcome == JobStatus::Paused || retryable {
+                kept.insert(job_id3.clone(), temp_dir.clone());
+            } else {
+                kept.remove(&job_id3);
+            }
+        }
+
+        release_active_job(&state, &job_id3).await;
+        queue::job_finished(&app3, &state, &job_id3, outcome).await;
+        state.download_finished.notify_waiters();
+        state.bandwidth_changed.notify_one();
+    });
+
+    Ok(())
+}
+
+/// How many trailing output lines of a post-download program are reported.
+const POST_HOOK_OUTPUT_LINES: usize = 20;
+
+/// Payload of `post-hook-result`.
+#[derive(Clone, Serialize)]
+struct PostHookResult {
+    hook: String,
+    file: String,
+    /// `None` when the program could not be started or was killed.
+    exit_code: Option<i32>,
+    /// The last lines the program printed, stdout and stderr combined.
+    output: String,
+}
+
+/// Runs the user's post-download program on `file`. The program is started
+/// directly rather than through a shell, so the file name cannot inject
+/// commands.
+async fn run_post_hook(hook: &std::path::Path, file: &str) -> PostHookResult {
+    let output = tokio::process::Command::new(hook)
+        .arg(file)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await;
+    let (exit_code, output) = match output {
+        Ok(output) => {
+            let text = format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+            let lines: Vec<&str> = text.lines().collect();
+            let tail = lines[lines.len().saturating_sub(POST_HOOK_OUTPUT_LINES)..].join("\n");
+            (output.status.code(), tail)
+        }
+        Err(error) => (None, format!("Could not run {}: {error}", hook.display())),
+    };
+    PostHookResult {
+        hook: hook.to_string_lossy().into_owned(),
+        file: file.to_string(),
+        exit_code,
+        output,
+    }
+}
+
+/// Kills yt-dlp along with its whole process group so ffmpeg (spawned by
+/// yt-dlp) is also terminated.
+async fn kill_process_tree(child: &mut tokio::process::Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        std::process::Command::new("kill")
+            .args(["-KILL", &format!("-{}", pid)])
+            .status()
+            .ok();
+    }
+    #[cfg(windows)]
+    if let Some(pid) = child.id() {
+        std::process::Command::new("taskkill")
+            .args(["/F", "/T", "/PID", &pid.to_string()])
+            .status()
+            .ok();
+    }
+    child.kill().await.ok();
+}
+
+/// Runs a quick `--simulate` of `test_url` with cookies from `browser`.
+/// `Ok(false)` means the site rejected the cookies; network trouble and an
+/// unreadable cookie store are reported as `Err`.
+#[tauri::command]
+async fn check_cookies(
+    app: AppHandle,
+    browser: CookieBrowser,
+    test_url: String,
+) -> Result<bool, String> {
+    let Some(browser) = browser.yt_dlp_keyword() else {
+        return Err("Choose a browser to check its cookies".to_string());
+    };
+    let (yt_dlp, _) = resolve_ytdlp(&app)
+        .await
+        .ok_or_else(|| "yt-dlp is not installed".to_string())?;
+
+    let mut command = tokio::process::Command::new(yt_dlp);
+    command
+        .args([
+            "--simulate",
+            "--no-playlist",
+            "--no-warnings",
+            "--cookies-from-browser",
+            browser,
+            &test_url,
+        ])
+        .args(proxy_args(&app.state::<AppState>()).await)
+        .kill_on_drop(true);
+    let output = tokio::time::timeout(std::time::Duration::from_secs(60), command.output())
+        .await
+        .map_err(|_| "Cookie check timed out".to_string())?
+        .map_err(|e| format!("Could not run yt-dlp: {e}"))?;
+
+    if output.status.success() {
+        Ok(true)
+    } else {
+        cookie_check_failure(&String::from_utf8_lossy(&output.stderr))
+    }
+}
+
+/// A single downloadable format as offered in the format picker.
+#[derive(Clone, Serialize)]
+struct FormatOption {
+    format_id: String,
+    ext: String,
+    /// As yt-dlp describes it, such as `1920x1080` or `audio only`.
+    resolution: Option<String>,
+    height: Option<u64>,
+    fps: Option<f64>,
+    vcodec: Option<String>,
+    acodec: Option<String>,
+    /// Average bitrate in KiB/s.
+    tbr: Option<f64>,
+    filesize: Option<u64>,
+    /// `false` when `filesize` is yt-dlp's estimate.
+    filesize_exact: bool,
+    note: Option<String>,
+    /// Direct media URL for plain HTTP(S) formats; used to look up missing
+    /// sizes.
+    #[serde(skip)]
+    direct_url: Option<String>,
+    #[serde(skip)]
+    http_headers: Vec<(String, String)>,
+}
+
+impl FormatOption {
+    fn from_info(format: &serde_json::Value) -> Option<Self> {
+        let codec = |key: &str| {
+            format[key]
+                .as_str()
+                .filter(|codec| *codec != "none")
+                .map(str::to_string)
+        };
+        Some(Self {
+            format_id: format["format_id"].as_str()?.to_string(),
+            ext: format["ext"].as_str().unwrap_or_default().to_string(),
+            resolution: format["resolution"].as_str().map(str::to_string),
+            height: format["height"].as_u64(),
+            fps: format["fps"].as_f64(),
+            vcodec: codec("vcodec"),
+            acodec: codec("acodec"),
+            tbr: format["tbr"].as_f64(),
+            filesize: format["filesize"]
+                .as_u64()
+                .or_else(|| format["filesize_approx"].as_f64().map(|size| size as u64)),
+            filesize_exact: format["filesize"].is_u64(),
+            note: format["format_note"].as_str().map(str::to_string),
+            direct_url: matches!(format["protocol"].as_str(), Some("http" | "https"))
+                .then(|| format["url"].as_str().map(str::to_string))
+                .flatten(),
+            http_headers: format["http_headers"]
+                .as_object()
+                .map(|headers| {
+                    headers
+                        .iter()
+                        .filter_map(|(name, value)| {
+                            Some((name.clone(), value.as_str()?.to_string()))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Orders better formats first: resolution, then frame rate, then bitrate.
+    fn quality_key(&self) -> (u64, u64, u64) {
+        (
+            self.height.unwrap_or(0),
+            self.fps.unwrap_or(0.0) as u64,
+            (self.tbr.unwrap_or(0.0) * 1000.0) as u64,
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct FormatGroups {
+    video_only: Vec<FormatOption>,
+    audio_only: Vec<FormatOption>,
+    combined: Vec<FormatOption>,
+    /// Format selector for the best choice: a combined format, or the best
+    /// video and audio streams merged (`137+140`) when that beats every
+    /// combined format, as is usual on YouTube.
+    recommended: Option<String>,
+}
+
+/// Payload of `format-size`: the exact size of a format of `url`.
+#[derive(Clone, Serialize)]
+struct FormatSize {
+    url: String,
+    format_id: String,
+    filesize: u64,
+}
+
+/// Lists the formats of `url` grouped into video-only, audio-only and
+/// combined streams, each sorted from best to worst.
+///
+/// With `fetch_sizes`, formats without an exact size are looked up with
+/// HEAD requests in the background and reported as `format-size` events,
+/// so the picker can show the list right away.
+#[tauri::command]
+async fn list_formats_grouped(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+    cookie_browser: CookieBrowser,
+    fetch_sizes: Option<bool>,
+) -> Result<FormatGroups, String> {
+    let info = fetch_video_info(&app, &state, &url, cookie_browser).await?;
+    let groups = group_formats(&info);
+    if fetch_sizes.unwrap_or(false) {
+        let unsized_formats: Vec<FormatOption> =
+            [&groups.video_only, &groups.audio_only, &groups.combined]
+                .into_iter()
+                .flatten()
+                .filter(|format| !format.filesize_exact && format.direct_url.is_some())
+                .cloned()
+                .collect();
+        tokio::spawn(fetch_format_sizes(app, url, unsized_formats));
+    }
+    Ok(groups)
+}
+
+/// What a video is, for a preview before downloading it.
+#[derive(Serialize)]
+struct VideoInfo {
+    title: Option<String>,
+    duration_seconds: Option<f64>,
+    thumbnail_url: Option<String>,
+    uploader: Option<String>,
+    /// In yt-dlp's order, from worst to best.
+    formats: Vec<FormatOption>,
+}
+
+impl VideoInfo {
+    fn from_info(info: &serde_json::Value) -> Self {
+        let text = |key: &str| info[key].as_str().map(str::to_string);
+        Self {
+            title: text("title"),
+            duration_seconds: info["duration"].as_f64(),
+            thumbnail_url: text("thumbnail"),
+            uploader: text("uploader").or_else(|| text("channel")),
+            formats: info["formats"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(FormatOption::from_info)
+                .collect(),
+        }
+    }
+}
+
+/// Reads the title, duration, thumbnail, uploader and formats of a single
+/// video, for a preview card and format picker.
+#[tauri::command]
+async fn get_video_info(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+    cookie_browser: CookieBrowser,
+) -> Result<VideoInfo, String> {
+    let info = fetch_video_info(&app, &state, &url, cookie_browser).await?;
+    Ok(VideoInfo::from_info(&info))
+}
+
+/// A subtitle language a video offers.
+#[derive(Debug, PartialEq, Serialize)]
+struct SubtitleTrack {
+    /// Code to pass in `subtitle_langs`, or in `auto_subtitle_langs` for
+    /// automatic captions, such as `en` or `pt-BR`.
+    language: String,
+    name: Option<String>,
+    /// Captions the site generated by speech recognition (or translated
+    /// from them), rather than ones the uploader provided.
+    automatic: bool,
+}
+
+/// Lists the subtitle languages of a video, uploaded ones first.
+#[tauri::command]
+async fn list_subtitles(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+    cookie_browser: CookieBrowser,
+) -> Result<Vec<SubtitleTrack>, String> {
+    let info = fetch_video_info(&app, &state, &url, cookie_browser).await?;
+    Ok(subtitle_tracks(&info))
+}
+
+/// A chapter of a video.
+#[derive(Debug, PartialEq, Serialize)]
+struct Chapter {
+    /// Position among the video's chapters, from 1.
+    index: usize,
+    title: String,
+    /// What to pass in `download_chapters`: the title, followed by the
+    /// index when another chapter has the same title.
+    label: String,
+    /// Seconds from the start of the video.
+    start_time: f64,
+    /// `None` when the site does not say where the last chapter ends.
+    end_time: Option<f64>,
+}
+
+/// Lists the chapters of a video; empty when it has none.
+#[tauri::command]
+async fn list_chapters(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+    cookie_browser: CookieBrowser,
+) -> Result<Vec<Chapter>, String> {
+    let info = fetch_video_info(&app, &state, &url, cookie_browser).await?;
+    Ok(chapters(&info))
+}
+
+fn chapters(info: &serde_json::Value) -> Vec<Chapter> {
+    let Some(entries) = info["chapters"].as_array() else {
+        return Vec::new();
+    };
+    let mut chapters = entries
+        .iter()
+        .enumerate()
+        .map(|(at, entry)| {
+            let index = at + 1;
+            let title = entry["title"]
+                .as_str()
+                .map(str::trim)
+                .filter(|title| !title.is_empty())
+                .map_or_else(|| format!("Chapter {index}"), str::to_string);
+            Chapter {
+                index,
+                label: title.clone(),
+                title,
+                start_time: entry["start_time"].as_f64().unwrap_or_default(),
+                end_time: entry["end_time"].as_f64(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let titles = chapters
+        .iter()
+        .map(|chapter| chapter.title.clone())
+        .collect::<Vec<_>>();
+    for chapter in &mut chapters {
+        if titles
+            .iter()
+            .filter(|title| **title == chapter.title)
+            .count()
+            > 1
+        {
+            chapter.label = format!("{} ({})", chapter.title, chapter.index);
+        }
+    }
+    chapters
+}
+
+/// Turns the chosen chapter labels into `--download-sections` ranges, in
+/// the order the chapters appear.
+fn chapter_sections(chapters: &[Chapter], selected: &[String]) -> Result<Vec<String>, String> {
+    if chapters.is_empty() {
+        return Err("This video has no chapters to choose from".to_string());
+    }
+    if let Some(missing) = selected
+        .iter()
+        .find(|label| !chapters.iter().any(|chapter| chapter.label == **label))
+    {
+        return Err(format!("This video has no chapter \"{missing}\""));
+    }
+    Ok(chapters
+        .iter()
+        .filter(|chapter| selected.contains(&chapter.label))
+        .map(|chapter| match chapter.end_time {
+            Some(end) => format!("*{}-{end}", chapter.start_time),
+            None => format!("*{}-inf", chapter.start_time),
+        })
+        .collect())
+}
+
+/// yt-dlp arguments for the chapters chosen in `download_chapters`. Titles
+/// can repeat, so they are matched to time ranges here rather than passed
+/// to yt-dlp as title patterns.
+async fn chapter_args(
+    app: &AppHandle,
+    state: &AppState,
+    params: &DownloadParams,
+) -> Result<Vec<String>, String> {
+    let selected = &params.options.download_chapters;
+    if selected.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !params.more_urls.is_empty() {
+        return Err("Chapters can only be chosen when downloading a single video".to_string());
+    }
+    let info = fetch_video_info(app, state, &params.url, params.cookie_browser).await?;
+    let mut args = Vec::new();
+    for section in chapter_sections(&chapters(&info), selected)? {
+        args.extend(["--download-sections".to_string(), section]);
+    }
+    args.push("--force-keyframes-at-cuts".to_string());
+    Ok(args)
+}
+
+fn subtitle_tracks(info: &serde_json::Value) -> Vec<SubtitleTrack> {
+    let mut tracks = Vec::new();
+    for (key, automatic) in [("subtitles", false), ("automatic_captions", true)] {
+        let Some(languages) = info[key].as_object() else {
+            continue;
+        };
+        for (language, formats) in languages {
+            // YouTube lists the chat replay of a stream as a subtitle.
+            if language == "live_chat" {
+                continue;
+            }
+            let name = formats[0]["name"].as_str().map(str::to_string);
+            tracks.push(SubtitleTrack {
+                language: language.clone(),
+                name,
+                automatic,
+            });
+        }
+    }
+    tracks
+}
+
+/// Per-request limit for format size lookups.
+const FORMAT_SIZE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+async fn fetch_format_sizes(app: AppHandle, url: String, formats: Vec<FormatOption>) {
+    use futures_util::StreamExt;
+
+    let Ok(HttpClient { client, .. }) = HttpClient::from_builder(
+        &app,
+        reqwest::Client::builder().timeout(FORMAT_SIZE_TIMEOUT),
+    )
+    .await
+    else {
+        return;
+    };
+    let lookups = formats.into_iter().map(|format| {
+        let client = client.clone();
+        async move {
+            let mut request = client.head(format.direct_url.as_deref()?);
+            for (name, value) in &format.http_headers {
+                request = request.header(name, value);
+            }
+            let response = request.send().await.ok()?.error_for_status().ok()?;
+            let filesize = response
+                .headers()
+                .get(reqwest::header::CONTENT_LENGTH)?
+                .to_str()
+                .ok()?
+                .parse()
+                .ok()?;
+            Some((format.format_id, filesize))
+        }
+    });
+    // Report each size as soon as it arrives.
+    let mut lookups: futures_util::stream::FuturesUnordered<_> = lookups.collect();
+    while let Some(result) = lookups.next().await {
+        if let Some((format_id, filesize)) = result {
+            let size = FormatSize {
+                url: url.clone(),
+                format_id,
+                filesize,
+            };
+            app.emit("format-size", size).ok();
+        }
+    }
+}
+
+/// One item of a playlist or channel, as listed without resolving it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct EntryStub {
+    id: String,
+    title: Option<String>,
+    url: Option<String>,
+    duration_secs: Option<f64>,
+}
+
+/// Lists the items of a playlist or channel quickly, without reading each
+/// video's formats, so the user can pick which ones to download. Large
+/// channels can be listed a page at a time: `count` items starting at the
+/// 1-based `start`.
+#[tauri::command]
+async fn list_playlist_entries(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+    cookie_browser: CookieBrowser,
+    start: Option<u32>,
+    count: Option<u32>,
+) -> Result<Vec<EntryStub>, String> {
+    let items = playlist_items_range(start, count)?;
+    let (yt_dlp, _) = resolve_ytdlp(&app)
+        .await
+        .ok_or_else(|| "yt-dlp is not installed".to_string())?;
+    let cookie_path = cookie_path_for_browser(&state.cookie_dir, cookie_browser);
+    let mut command = tokio::process::Command::new(yt_dlp);
+    command
+        .args(["-J", "--flat-playlist", "--no-warnings"])
+        .args(session_cookie_args(cookie_path.as_deref(), cookie_browser))
+        .args(proxy_args(&state).await);
+    if let Some(items) = items {
+        command.arg("--playlist-items").arg(items);
+    }
+    command.args(["--", &url]).kill_on_drop(true);
+    let output = tokio::time::timeout(std::time::Duration::from_secs(120), command.output())
+        .await
+        .map_err(|_| "Listing the playlist timed out".to_string())?
+        .map_err(|e| format!("Could not run yt-dlp: {e}"))?;
+    if let Some(cookie_path) = &cookie_path {
+        secure_cookie_file(cookie_path);
+    }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        let code = output.status.code().unwrap_or(-1);
+        return Err(DownloadError::from_exit(code, stderr).message);
+    }
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("yt-dlp returned an unreadable playlist: {e}"))?;
+    Ok(playlist_entries(&info))
+}
+
+/// Builds the `--playlist-items` range for a page of a playlist.
+fn playlist_items_range(start: Option<u32>, count: Option<u32>) -> Result<Option<String>, String> {
+    if start == Some(0) {
+        return Err("Playlist items are counted from 1".to_string());
+    }
+    if count == Some(0) {
+        return Err("List at least one playlist item".to_string());
+    }
+    Ok(match (start, count) {
+        (None, None) => None,
+        (start, None) => Some(format!("{}:", start.unwrap_or(1))),
+        (start, Some(count)) => {
+            let start = start.unwrap_or(1);
+            Some(format!("{start}:{}", start.saturating_add(count - 1)))
+        }
+    })
+}
+
+fn playlist_entries(info: &serde_json::Value) -> Vec<EntryStub> {
+    let text = |value: &serde_json::Value| value.as_str().map(str::to_string);
+    let entries = match info["entries"].as_array() {
+        Some(entries) => entries.as_slice(),
+        // A single video has no entries; list it on its own.
+        None => std::slice::from_ref(info),
+    };
+    entries
+        .iter()
+        .filter_map(|entry| {
+            Some(EntryStub {
+                id: entry["id"].as_str()?.to_string(),
+                title: text(&entry["title"]),
+                url: text(&entry["url"]).or_else(|| text(&entry["webpage_url"])),
+                duration_secs: entry["duration"].as_f64(),
+            })
+        })
+        .collect()
+}
+
+/// Saves only the thumbnail of `url` as a JPEG into `output_path` and
+/// returns the image path.
+#[tauri::command]
+async fn download_thumbnail_only(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+    output_path: String,
+    cookie_browser: CookieBrowser,
+) -> Result<String, String> {
+    let (yt_dlp, _) = resolve_ytdlp(&app)
+        .await
+        .ok_or_else(|| "yt-dlp is not installed".to_string())?;
+    let ffmpeg = resolve_ffmpeg(&app).await.map(|(path, _)| path);
+    let cookie_path = cookie_path_for_browser(&state.cookie_dir, cookie_browser);
+    std::fs::create_dir_all(&output_path)
+        .map_err(|e| format!("Cannot create output folder: {}", e))?;
+
+    let mut command = tokio::process::Command::new(yt_dlp);
+    command
+        .args([
+            "--skip-download",
+            "--write-thumbnail",
+            "--convert-thumbnails",
+            "jpg",
+            "--no-playlist",
+            "-P",
+            &output_path,
+            "-o",
+            "thumbnail:%(title)s [%(id)s].%(ext)s",
+        ])
+        .args(session_cookie_args(cookie_path.as_deref(), cookie_browser));
+    if let Some(ffmpeg) = ffmpeg {
+        command.arg("--ffmpeg-location").arg(ffmpeg);
+    }
+    command
+        .args(proxy_args(&state).await)
+        .args(["--", &url])
+        .kill_on_drop(true);
+
+    let output = tokio::time::timeout(std::time::Duration::from_secs(120), command.output())
+        .await
+        .map_err(|_| "Thumbnail download timed out".to_string())?
+        .map_err(|e| format!("Could not run yt-dlp: {e}"))?;
+    if let Some(cookie_path) = &cookie_path {
+        secure_cookie_file(cookie_path);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for line in stdout.lines() {
+        emit_log(&app, None, line);
+    }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        let code = output.status.code().unwrap_or(-1);
+        return Err(DownloadError::from_exit(code, stderr).message);
+    }
+
+    let image = stdout
+        .lines()
+        .rev()
+        .find_map(parse_thumbnail_path)
+        .ok_or_else(|| "This video has no thumbnail to download".to_string())?;
+    app.emit("thumbnail-saved", &image).ok();
+    Ok(image)
+}
+
+#[derive(Clone, Serialize)]
+struct DownloadSummary {
+    output_file: Option<String>,
+    total_bytes: u64,
+    elapsed_secs: f64,
+    /// Bytes per second over the whole job, including post-processing.
+    average_speed: f64,
+}
+
+/// How many trailing stderr lines are kept for error reports.
+const STDERR_TAIL_LINES: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+enum ErrorKind {
+    MembershipRequired,
+    RateLimited,
+    DiskFull,
+    /// The video needs a logged-in account old enough to watch it.
+    AgeRestricted,
+    /// The video is not offered in the user's country.
+    GeoBlocked,
+    Private,
+    Unavailable,
+    /// The page or a file behind it returned HTTP 404.
+    NotFound,
+    LoginRequired,
+    /// No extractor handles the URL.
+    UnsupportedUrl,
+    /// The site could not be reached or failed on its side.
+    Network,
+    Unknown,
+}
+
+impl ErrorKind {
+    /// Failures that may well go away if the job simply runs again later.
+    fn is_temporary(self) -> bool {
+        matches!(self, ErrorKind::RateLimited | ErrorKind::Network)
+    }
+}
+
+/// Payload of `download-error`: a kind the UI can turn into advice, a
+/// readable message, and the raw yt-dlp output for troubleshooting.
+#[derive(Clone, Serialize)]
+struct DownloadError {
+    kind: ErrorKind,
+    message: String,
+    raw: String,
+}
+
+impl DownloadError {
+    fn unknown(message: String) -> Self {
+        Self {
+            kind: ErrorKind::Unknown,
+            raw: message.clone(),
+            message,
+        }
+    }
+
+    fn disk_full(output_path: &str, available: u64) -> Self {
+        Self {
+            kind: ErrorKind::DiskFull,
+            message: format!(
+                "The download was stopped because the disk holding {output_path} is almost full. Free up some space and try again."
+            ),
+            raw: format!("{available} bytes left on the output volume"),
+        }
+    }
+
+    fn not_enough_space(output_path: &str, needed: u64, available: u64) -> Self {
+        let megabytes = |bytes: u64| bytes.div_ceil(1_000_000);
+        Self {
+            kind: ErrorKind::DiskFull,
+            message: format!(
+                "Not enough disk space: the download needs about {} MB but the disk holding {output_path} has {} MB free. Free up some space and try again.",
+                megabytes(needed),
+                megabytes(available)
+            ),
+            raw: format!("{needed} bytes needed, {available} bytes left on the output volume"),
+        }
+    }
+
+    fn from_exit(code: i32, stderr: String) -> Self {
+        let kind = classify_failure(&stderr);
+        let message = match kind {
+            ErrorKind::MembershipRequired => "This video is only available to channel members or paying subscribers. Choose a browser where you are logged in with access under \"Use cookies from browser\" and try again.".to_string(),
+            ErrorKind::RateLimited => "The site is limiting how fast videos can be downloaded (HTTP 429). Wait a while before trying again, or turn on automatic retries.".to_string(),
+            ErrorKind::Unavailable => {
+                let reason = stderr
+                    .lines()
+                    .rev()
+                    .find_map(unavailable_item)
+                    .map(|item| item.reason)
+                    .unwrap_or_default();
+                format!("This video can't be downloaded: {reason}")
+            }
+            ErrorKind::AgeRestricted => "This video is age-restricted. Choose a browser where you are logged in to an adult account under \"Use cookies from browser\" and try again.".to_string(),
+            ErrorKind::GeoBlocked => "This video is not available in your country. A proxy in a country where it is offered may work.".to_string(),
+            ErrorKind::Private => "This video is private. If you have been given access, choose a browser where you are logged in under \"Use cookies from browser\" and try again.".to_string(),
+            ErrorKind::NotFound => "The site returned \"not found\" (HTTP 404). Check that the URL is correct and the video still exists.".to_string(),
+            ErrorKind::LoginRequired => "The site requires you to log in to download this. Choose a browser where you are logged in under \"Use cookies from browser\" and try again.".to_string(),
+            ErrorKind::UnsupportedUrl => "This URL is not supported. Check that it links to a video page rather than a search or home page.".to_string(),
+            ErrorKind::Network => "Could not reach the site. Check your internet connection and try again.".to_string(),
+            ErrorKind::DiskFull | ErrorKind::Unknown => format!("yt-dlp exited with code {code}"),
+        };
+        Self {
+            kind,
+            message,
+            raw: stderr,
+        }
+    }
+}
+
+/// Lowercased messages yt-dlp reports for videos that are gone or hidden.
+const UNAVAILABLE_ERRORS: &[&str] = &[
+    "video unavailable",
+    "this video has been removed",
+    "private video",
+];
+
+/// A playlist item skipped because it is unavailable; part of
+/// `skipped-items`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct SkippedItem {
+    id: Option<String>,
+    reason: String,
+}
+
+/// Reads an unavailability error such as
+/// `ERROR: [youtube] abc: Private video. Sign in if you've been granted access`.
+fn unavailable_item(line: &str) -> Option<SkippedItem> {
+    let message = line.strip_prefix("ERROR:")?.trim();
+    let lower = message.to_ascii_lowercase();
+    if !UNAVAILABLE_ERRORS
+        .iter()
+        .any(|pattern| lower.contains(pattern))
+    {
+        return None;
+    }
+    // The message is prefixed by `[extractor] id: ` when yt-dlp knows the item.
+    let (id, reason) = match message
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once("] "))
+        .and_then(|(_, rest)| rest.split_once(": "))
+    {
+        Some((id, reason)) => (Some(id.to_string()), reason),
+        None => (None, message),
+    };
+    Some(SkippedItem {
+        id,
+        reason: reason.trim().to_string(),
+    })
+}
+
+fn classify_failure(stderr: &str) -> ErrorKind {
+    const MEMBERSHIP_ERRORS: &[&str] = &[
+        "members-only",
+        "members only",
+        "available to this channel's members",
+        "join this channel",
+        "premium members",
+        "requires payment",
+        "requires purchase",
+        "purchase this video",
+        "subscription required",
+        "requires a subscription",
+    ];
+    const AGE_ERRORS: &[&str] = &[
+        "confirm your age",
+        "age-restricted",
+        "age restricted",
+        "inappropriate for some users",
+    ];
+    const GEO_ERRORS: &[&str] = &[
+        "not available in your country",
+        "not made this video available in your country",
+        "geo restriction",
+        "geo-restricted",
+        "geo restricted",
+    ];
+    const LOGIN_ERRORS: &[&str] = &[
+        "login required",
+        "requires authentication",
+        "sign in",
+        "log in",
+        "logged-in",
+        "registered users",
+        "--cookies",
+    ];
+
+    let errors = stderr
+        .lines()
+        .filter(|line| line.starts_with("ERROR:"))
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>();
+    let mentions = |patterns: &[&str]| {
+        errors
+            .iter()
+            .any(|line| patterns.iter().any(|pattern| line.contains(pattern)))
+    };
+
+    if mentions(MEMBERSHIP_ERRORS) {
+        ErrorKind::MembershipRequired
+    } else if mentions(&["http error 429", "too many requests"]) {
+        ErrorKind::RateLimited
+    } else if mentions(AGE_ERRORS) {
+        ErrorKind::AgeRestricted
+    } else if mentions(GEO_ERRORS) {
+        ErrorKind::GeoBlocked
+    } else if mentions(&["private video"]) {
+        ErrorKind::Private
+    } else if mentions(UNAVAILABLE_ERRORS) {
+        ErrorKind::Unavailable
+    } else if mentions(&["http error 404", "404: not found"]) {
+        ErrorKind::NotFound
+    } else if mentions(LOGIN_ERRORS) {
+        ErrorKind::LoginRequired
+    } else if mentions(&["unsupported url"]) {
+        ErrorKind::UnsupportedUrl
+    } else if mentions(NETWORK_ERRORS) {
+        ErrorKind::Network
+    } else {
+        ErrorKind::Unknown
+    }
+}
+
+/// Totals over every download completed in this session.
+#[derive(Default)]
+struct SessionStats {
+    total_bytes: u64,
+    total_secs: f64,
+}
+
+impl SessionStats {
+    fn record(&mut self, summary: &DownloadSummary) {
+        self.total_bytes += summary.total_bytes;
+        self.total_secs += summary.elapsed_secs;
+    }
+
+    fn average_speed(&self) -> Option<f64> {
+        (self.total_bytes > 0 && self.total_secs > 0.0)
+            .then(|| self.total_bytes as f64 / self.total_secs)
+    }
+}
+
+/// Stops the download `job_id`, leaving other running downloads alone. A
+/// paused download is dropped along with its partial files.
+#[tauri::command]
+async fn cancel_download(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    job_id: String,
+) -> Result<(), ()> {
+    if let Some(job) = state.active_jobs.lock().await.get_mut(&job_id) {
+        job.cancel();
+    }
+    let paused = state.paused_jobs.lock().await.remove(&job_id);
+    if let Some(paused) = paused {
+        drop_paused_job(&app, &state, &job_id, paused).await;
+    }
+    // A job stopped to be restarted with a new bandwidth share is not
+    // restarted, and ends here instead.
+    let restart = state.bandwidth_restarts.lock().await.remove(&job_id);
+    if let Some(restart) = restart.filter(|restart| restart.stopped) {
+        wait_for_jobs_to_stop(&state, std::slice::from_ref(&job_id)).await;
+        drop_paused_job(&app, &state, &job_id, restart.job).await;
+    }
+    Ok(())
+}
+
+async fn drop_paused_job(app: &AppHandle, state: &AppState, job_id: &str, paused: PausedJob) {
+    let temp_dir = job_temp_dir(std::path::Path::new(&paused.output_path), job_id);
+    tokio::fs::remove_dir_all(&temp_dir).await.ok();
+    state.kept_temp_dirs.lock().await.remove(job_id);
+    queue::job_finished(app, state, job_id, JobStatus::Cancelled).await;
+}
+
+/// Stops every running and paused download, waiting briefly for their
+/// processes to be killed. `download-cancelled` is emitted for each job so
+/// the UI can clear them all at once.
+#[tauri::command]
+async fn cancel_all_downloads(app: AppHandle, state: State<'_, AppState>) -> Result<(), ()> {
+    // Otherwise the queue starts its next job as soon as the running one
+    // is cancelled.
+    let batches = {
+        let mut queue = state.queue.lock().await;
+        let waiting = queue.cancel_waiting();
+        waiting
+            .iter()
+            .filter_map(|job_id| queue.finish_batch_job(job_id, JobStatus::Cancelled))
+            .collect::<Vec<_>>()
+    };
+    for batch in batches {
+        app.emit("batch-progress", batch).ok();
+    }
+    queue::emit_queue_updated(&app, &state).await;
+
+    let running: Vec<String> = {
+        let mut active = state.active_jobs.lock().await;
+        for job in active.values_mut() {
+            job.cancel();
+        }
+        active.keys().cloned().collect()
+    };
+    let mut paused: Vec<(String, PausedJob)> = state.paused_jobs.lock().await.drain().collect();
+    let restarts: Vec<(String, BandwidthRestart)> =
+        state.bandwidth_restarts.lock().await.drain().collect();
+    paused.extend(
+        restarts
+            .into_iter()
+            .filter(|(_, restart)| restart.stopped)
+            .map(|(job_id, restart)| (job_id, restart.job)),
+    );
+    wait_for_jobs_to_stop(&state, &running).await;
+    for (job_id, paused) in &paused {
+        drop_paused_job(&app, &state, job_id, paused.clone()).await;
+    }
+    // A job paused while it was still stopping is in both lists.
+    let mut cancelled = running;
+    for (job_id, _) in paused {
+        if !cancelled.contains(&job_id) {
+            cancelled.push(job_id);
+        }
+    }
+    for job_id in &cancelled {
+        app.emit("download-cancelled", JobEvent::new(job_id, ()))
+            .ok();
+    }
+    Ok(())
+}
+
+/// Waits up to `SHUTDOWN_GRACE_PERIOD` for the jobs `job_ids` to stop and
+/// clean up. Returns whether they all did.
+async fn wait_for_jobs_to_stop(state: &AppState, job_ids: &[String]) -> bool {
+    let all_stopped = async {
+        loop {
+            let finished = state.download_finished.notified();
+            tokio::pin!(finished);
+            finished.as_mut().enable();
+            let active = state.active_jobs.lock().await;
+            if !job_ids.iter().any(|job_id| active.contains_key(job_id)) {
+                return;
+            }
+            drop(active);
+            finished.await;
+        }
+    };
+    tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, all_stopped)
+        .await
+        .is_ok()
+}
+
+/// Stops the download `job_id` but keeps its partial files, so
+/// `resume_download` can continue it from where it stopped.
+#[tauri::command]
+async fn pause_download(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    job_id: String,
+) -> Result<(), String> {
+    if state.bandwidth_restarts.lock().await.contains_key(&job_id) {
+        return Err(
+            "The download is restarting with a new bandwidth limit. Try again in a moment."
+                .to_string(),
+        );
+    }
+    let mut active = state.active_jobs.lock().await;
+    let job = active
+        .get_mut(&job_id)
+        .ok_or_else(|| "This download is not running".to_string())?;
+    let paused = PausedJob {
+        params: job.params.clone(),
+        output_path: job.output_path.clone(),
+    };
+    state
+        .paused_jobs
+        .lock()
+        .await
+        .insert(job_id.clone(), paused);
+    job.cancel();
+    drop(active);
+    app.emit("download-paused", JobEvent::new(&job_id, ())).ok();
+    Ok(())
+}
+
+/// Restarts a paused download in the same folder with the same settings.
+/// yt-dlp continues its partial files and skips files already finished.
+#[tauri::command]
+async fn resume_download(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    job_id: String,
+) -> Result<(), String> {
+    // The paused run may still be cleaning up.
+    if !wait_for_jobs_to_stop(&state, std::slice::from_ref(&job_id)).await {
+        return Err("The download is still stopping. Try again in a moment.".to_string());
+    }
+    let PausedJob {
+        params,
+        output_path,
+    } = state
+        .paused_jobs
+        .lock()
+        .await
+        .remove(&job_id)
+        .ok_or_else(|| "This download is not paused".to_string())?;
+    let option_args = restart_args(&app, &state, &params).await?;
+    if state
+        .queue
+        .lock()
+        .await
+        .set_status(&job_id, JobStatus::Running)
+    {
+        queue::emit_queue_updated(&app, &state).await;
+    }
+    run_download(
+        &app,
+        &state,
+        job_id,
+        params,
+        output_path,
+        option_args,
+        false,
+    )
+    .await
+}
+
+/// Arguments for running a stopped download again: yt-dlp continues its
+/// partial files and skips the files already finished.
+async fn restart_args(
+    app: &AppHandle,
+    state: &AppState,
+    params: &DownloadParams,
+) -> Result<Vec<String>, String> {
+    let mut option_args = params.options.yt_dlp_args()?;
+    option_args.extend(chapter_args(app, state, params).await?);
+    option_args.push("--no-overwrites".to_string());
+    Ok(option_args)
+}
+
+/// How long quitting or cancelling waits for a download to stop its processes.
+const SHUTDOWN_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Cancels the running downloads when the app quits and waits briefly for
+/// yt-dlp and its ffmpeg children to be killed so none outlive the app.
+/// The temp folders kept for retrying or resuming jobs are removed too.
+async fn shutdown_downloads(state: &AppState) {
+    state.shutting_down.store(true, Ordering::SeqCst);
+    let running: Vec<String> = {
+        let mut active = state.active_jobs.lock().await;
+        for job in active.values_mut() {
+            job.cancel();
+        }
+        active.keys().cloned().collect()
+    };
+    wait_for_jobs_to_stop(state, &running).await;
+    let kept: Vec<PathBuf> = state
+        .kept_temp_dirs
+        .lock()
+        .await
+        .drain()
+        .map(|(_, dir)| dir)
+        .collect();
+    for dir in kept {
+        tokio::fs::remove_dir_all(&dir).await.ok();
+    }
+}
+
+/// Temp folders left by a crash are kept this long, so `find_partial_files`
+/// can still resume their downloads, and then removed at startup.
+const STALE_TEMP_DIR_AGE: std::time::Duration = std::time::Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Whether the entry `name`, last changed `age` ago, is a job temp folder
+/// old enough to remove.
+fn is_stale_temp_dir(name: &str, age: std::time::Duration) -> bool {
+    name.starts_with(".video-downloader-tmp-") && age >= STALE_TEMP_DIR_AGE
+}
+
+/// Removes old job temp folders from the default output folder and the
+/// folders inside it, such as the dated ones. At startup no job owns any.
+async fn sweep_stale_temp_dirs(app: AppHandle) {
+    let output_path = PathBuf::from(get_default_output_path(app).await);
+    let mut dirs = vec![output_path.clone()];
+    if let Ok(mut entries) = tokio::fs::read_dir(&output_path).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_dir = entry.file_type().await.is_ok_and(|kind| kind.is_dir());
+            if is_dir && !name.starts_with(".video-downloader-tmp-") {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    for dir in dirs {
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let age = entry
+                .metadata()
+                .await
+                .ok()
+                .filter(|meta| meta.is_dir())
+                .and_then(|meta| meta.modified().ok())
+                .and_then(|modified| modified.elapsed().ok());
+            if age.is_some_and(|age| is_stale_temp_dir(&name, age)) {
+                tokio::fs::remove_dir_all(entry.path()).await.ok();
+            }
+        }
+    }
+}
+
+/// Terminates yt-dlp, ffmpeg, ffprobe and aria2c processes started from the
+/// app-managed binaries by an earlier instance that crashed. Processes this
+/// instance started are left alone. Returns how many were killed.
+#[tauri::command]
+async fn kill_orphan_processes(app: AppHandle) -> Result<usize, String> {
+    let managed_ffmpeg = managed_ffmpeg_path(&app);
+    let binaries = [
+        managed_ytdlp_path(&app),
+        ffprobe_beside(&managed_ffmpeg),
+        managed_ffmpeg,
+        managed_aria2c_path(&app),
+    ];
+    let killed = tokio::task::spawn_blocking(move || kill_stray_processes(&binaries))
+        .await
+        .map_err(|e| e.to_string())?;
+    app.emit("orphan-processes-killed", killed).ok();
+    Ok(killed)
+}
+
+#[derive(Default, Debug, PartialEq, Serialize)]
+struct MediaInfo {
+    duration_secs: Option<f64>,
+    width: Option<u32>,
+    height: Option<u32>,
+    video_codec: Option<String>,
+    audio_codec: Option<String>,
+    /// Overall bitrate.
+    bitrate_kbps: Option<u32>,
+}
+
+/// Describes a downloaded file: duration, resolution, codecs and bitrate.
+#[tauri::command]
+async fn probe_file(app: AppHandle, path: String) -> Result<MediaInfo, String> {
+    let path = std::path::Path::new(&path);
+    if !path.is_file() {
+        return Err(format!("File not found: {}", path.display()));
+    }
+    let (ffmpeg, _) = resolve_ffmpeg(&app)
+        .await
+        .ok_or_else(|| "ffmpeg is not installed".to_string())?;
+    let description = describe_media(&ffmpeg, path)
+        .await
+        .ok_or_else(|| "Could not run ffmpeg to read the file".to_string())?;
+    let info = parse_media_info(&description);
+    if info.duration_secs.is_none() && info.video_codec.is_none() && info.audio_codec.is_none() {
+        return Err(format!(
+            "ffmpeg could not read the file: {}",
+            last_line(&description)
+        ));
+    }
+    Ok(info)
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum VerificationStatus {
+    Ok,
+    Corrupt,
+}
+
+/// Payload of `verification-result`.
+#[derive(Clone, Serialize)]
+struct VerificationResult {
+    file: String,
+    status: VerificationStatus,
+    /// The last errors ffmpeg reported while decoding, if any.
+    errors: String,
+}
+
+/// Decodes `path` completely to check that it is not corrupt or truncated.
+#[tauri::command]
+async fn verify_file(app: AppHandle, path: String) -> Result<VerificationResult, String> {
+    if !std::path::Path::new(&path).is_file() {
+        return Err(format!("File not found: {path}"));
+    }
+    let (ffmpeg, _) = resolve_ffmpeg(&app)
+        .await
+        .ok_or_else(|| "ffmpeg is not installed".to_string())?;
+    let result = verify_media(&ffmpeg, &path).await;
+    app.emit("verification-result", result.clone()).ok();
+    Ok(result)
+}
+
+/// How many lines of decoder errors a verification result keeps.
+const VERIFICATION_ERROR_LINES: usize = 10;
+
+async fn verify_media(ffmpeg: &std::path::Path, file: &str) -> VerificationResult {
+    let output = tokio::process::Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostdin", "-v", "error", "-i"])
+        .arg(file)
+        .args(["-f", "null", "-"])
+        .kill_on_drop(true)
+        .output()
+        .await;
+    let (status, errors) = match output {
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let lines: Vec<&str> = stderr
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .collect();
+            let tail = lines[lines.len().saturating_sub(VERIFICATION_ERROR_LINES)..].join("\n");
+            // ffmpeg keeps decoding past damaged packets, so any reported
+            // error marks the file as corrupt even when it exits with 0.
+            if output.status.success() && lines.is_empty() {
+                (VerificationStatus::Ok, tail)
+            } else {
+                (VerificationStatus::Corrupt, tail)
+            }
+        }
+        Err(error) => (
+            VerificationStatus::Corrupt,
+            format!("Could not run ffmpeg: {error}"),
+        ),
+    };
+    VerificationResult {
+        file: file.to_string(),
+        status,
+        errors,
+    }
+}
+
+/// Saves the video frame at `timestamp` (seconds, `MM:SS` or `HH:MM:SS`)
+/// of `input_file` as an image. The format follows the extension of
+/// `output_image`.
+#[tauri::command]
+async fn capture_frame(
+    app: AppHandle,
+    input_file: String,
+    timestamp: String,
+    output_image: String,
+) -> Result<String, String> {
+    let input = std::path::Path::new(&input_file);
+    if !input.is_file() {
+        return Err(format!("File not found: {input_file}"));
+    }
+    let extension = std::path::Path::new(&output_image)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    if !matches!(extension.as_deref(), Some("jpg" | "jpeg" | "png" | "webp")) {
+        return Err("Save the frame as a .jpg, .png or .webp image".to_string());
+    }
+    let seconds = parse_timestamp(&timestamp).ok_or_else(|| {
+        format!("Invalid timestamp \"{timestamp}\". Use seconds, MM:SS or HH:MM:SS")
+    })?;
+    let (ffmpeg, _) = resolve_ffmpeg(&app)
+        .await
+        .ok_or_else(|| "ffmpeg is not installed".to_string())?;
+    if let Some(duration) = probe_media_duration(&ffmpeg, input).await {
+        if seconds >= duration {
+            return Err(format!(
+                "The timestamp is past the end of the video ({duration:.1} seconds long)"
+            ));
+        }
+    }
+
+    let mut command = tokio::process::Command::new(ffmpeg);
+    command
+        .args(["-hide_banner", "-v", "error", "-y", "-ss"])
+        .arg(format!("{seconds:.3}"))
+        .arg("-i")
+        .arg(input)
+        .args(["-frames:v", "1", "-q:v", "2"])
+        .arg(&output_image)
+        .kill_on_drop(true);
+    let output = tokio::time::timeout(std::time::Duration::from_secs(60), command.output())
+        .await
+        .map_err(|_| "Capturing the frame timed out".to_string())?
+        .map_err(|e| format!("Could not run ffmpeg: {e}"))?;
+    if !output.status.success() || !std::path::Path::new(&output_image).is_file() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "ffmpeg could not capture the frame: {}",
+            last_line(&stderr)
+        ));
+    }
+    Ok(output_image)
+}
+
+/// Reads `90`, `1:30` or `00:01:30.5` as seconds.
+fn parse_timestamp(timestamp: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    let parts: Vec<&str> = timestamp.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    for part in parts {
+        let value: f64 = part.parse().ok()?;
+        if !value.is_finite() || value < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some(seconds)
+}
+
+/// Containers `remux_file` can write.
+const REMUX_CONTAINERS: &[&str] = &["mp4", "mkv", "mov", "webm"];
+
+#[derive(Serialize)]
+struct RemuxResult {
+    output_file: String,
+    /// The streams could not be copied into the target container as they
+    /// are and had to be converted.
+    reencoded: bool,
+}
+
+/// Writes `input` into a new file with the `target_container` format next
+/// to it. Streams are copied untouched when the container accepts their
+/// codecs and converted otherwise.
+#[tauri::command]
+async fn remux_file(
+    app: AppHandle,
+    input: String,
+    target_container: String,
+) -> Result<RemuxResult, String> {
+    let container = target_container.trim().to_ascii_lowercase();
+    if !REMUX_CONTAINERS.contains(&container.as_str()) {
+        return Err(format!(
+            "Unsupported container \"{target_container}\". Choose one of: {}",
+            REMUX_CONTAINERS.join(", ")
+        ));
+    }
+    let input_path = std::path::Path::new(&input);
+    if !input_path.is_file() {
+        return Err(format!("File not found: {input}"));
+    }
+    let output_path = input_path.with_extension(&container);
+    if output_path == input_path {
+        return Err(format!("The file is already a .{container} file"));
+    }
+    if output_path.exists() {
+        return Err(format!("{} already exists", output_path.display()));
+    }
+    let (ffmpeg, _) = resolve_ffmpeg(&app)
+        .await
+        .ok_or_else(|| "ffmpeg is not installed".to_string())?;
+    let duration = probe_media_duration(&ffmpeg, input_path).await;
+
+    let copy_error = match run_ffmpeg_with_progress(
+        &app,
+        &ffmpeg,
+        input_path,
+        &output_path,
+        &remux_codec_args(&container, false),
+        duration,
+    )
+    .await
+    {
+        Ok(()) => {
+            return Ok(RemuxResult {
+                output_file: output_path.to_string_lossy().into_owned(),
+                reencoded: false,
+            })
+        }
+        Err(stderr) => stderr,
+    };
+    tokio::fs::remove_file(&output_path).await.ok();
+    if !is_codec_incompatibility(&copy_error) {
+        return Err(format!(
+            "ffmpeg could not remux the file: {}",
+            last_line(&copy_error)
+        ));
+    }
+
+    let codec_args = remux_codec_args(&container, true);
+    let encoders: Vec<&str> = codec_args
+        .windows(2)
+        .filter(|pair| pair[0].starts_with("-c:"))
+        .map(|pair| pair[1])
+        .collect();
+    require_encoders(&ffmpeg, &encoders).await?;
+    app.emit(
+        "remux-warning",
+        format!(
+            "Some streams cannot be stored in .{container} as they are; converting them instead. This takes longer and may reduce quality slightly."
+        ),
+    )
+    .ok();
+    if let Err(stderr) = run_ffmpeg_with_progress(
+        &app,
+        &ffmpeg,
+        input_path,
+        &output_path,
+        &codec_args,
+        duration,
+    )
+    .await
+    {
+        tokio::fs::remove_file(&output_path).await.ok();
+        return Err(format!(
+            "ffmpeg could not convert the file: {}",
+            last_line(&stderr)
+        ));
+    }
+    Ok(RemuxResult {
+        output_file: output_path.to_string_lossy().into_owned(),
+        reencoded: true,
+    })
+}
+
+#[tauri::command]
+fn open_folder(path: String) {
+    #[cfg(target_os = "macos")]
+    {
+        std::process::Command::new("open").arg(&path).spawn().ok();
+    }
+    #[cfg(target_os = "windows")]
+    {
+        std::process::Command::new("explorer")
+            .arg(&path)
+            .spawn()
+            .ok();
+    }
+    #[cfg(target_os = "linux")]
+    {
+        std::process::Command::new("xdg-open")
+            .arg(&path)
+            .spawn()
+            .ok();
+    }
+}
+
+// ── Private helpers ───────────────────────────────────────────────────────────
+
+/// Codec arguments for `remux_file`: stream copy, or a conversion to codecs
+/// the target container accepts.
+fn remux_codec_args(container: &str, reencode: bool) -> Vec<&'static str> {
+    let mut args = vec!["-map", "0:v?", "-map", "0:a?", "-map", "0:s?"];
+    if !reencode {
+        args.extend(["-c", "copy"]);
+        return args;
+    }
+    args.extend(match container {
+        "webm" => ["-c:v", "libvpx-vp9", "-c:a", "libopus", "-c:s", "webvtt"],
+        "mkv" => ["-c:v", "libx264", "-c:a", "aac", "-c:s", "copy"],
+        _ => ["-c:v", "libx264", "-c:a", "aac", "-c:s", "mov_text"],
+    });
+    args
+}
+
+/// ffmpeg errors meaning a stream's codec cannot go into the container.
+fn is_codec_incompatibility(stderr: &str) -> bool {
+    [
+        "codec not currently supported in container",
+        "Could not find tag for codec",
+        "Only VP8 or VP9 or AV1 video",
+        "Only Opus, Vorbis",
+        "Could not write header",
+    ]
+    .iter()
+    .any(|needle| stderr.contains(needle))
+}
+
+fn last_line(text: &str) -> &str {
+    text.lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("unknown error")
+        .trim()
+}
+
+/// Returns the description ffmpeg prints for its input.
+async fn describe_media(ffmpeg: &std::path::Path, input: &std::path::Path) -> Option<String> {
+    let mut command = tokio::process::Command::new(ffmpeg);
+    command
+        .arg("-hide_banner")
+        .arg("-i")
+        .arg(input)
+        .kill_on_drop(true);
+    // Without an output ffmpeg exits with an error after describing the input.
+    let output = tokio::time::timeout(std::time::Duration::from_secs(30), command.output())
+        .await
+        .ok()?
+        .ok()?;
+    Some(String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+/// Reads the media duration ffmpeg prints for its input, in seconds.
+async fn probe_media_duration(ffmpeg: &std::path::Path, input: &std::path::Path) -> Option<f64> {
+    parse_ffmpeg_duration(&describe_media(ffmpeg, input).await?)
+}
+
+/// Reads the first video and audio stream out of ffmpeg's description of
+/// its input, such as
+/// `Stream #0:0(und): Video: h264 (High) (avc1 / 0x31637661), yuv420p, 1920x1080 [SAR 1:1 DAR 16:9], 25 fps`.
+fn parse_media_info(description: &str) -> MediaInfo {
+    let mut info = MediaInfo {
+        duration_secs: parse_ffmpeg_duration(description),
+        bitrate_kbps: description
+            .split_once("bitrate: ")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .and_then(|bitrate| bitrate.parse().ok()),
+        ..Default::default()
+    };
+    let codec_name = |details: &str| {
+        details
+            .split_whitespace()
+            .next()
+            .map(|codec| codec.trim_end_matches(',').to_string())
+    };
+    for line in description.lines().map(str::trim) {
+        if !line.starts_with("Stream #") {
+            continue;
+        }
+        if let Some((_, details)) = line.split_once(": Video: ") {
+            if info.video_codec.is_some() {
+                continue;
+            }
+            info.video_codec = codec_name(details);
+            let resolution = details.split(", ").find_map(|part| {
+                let (width, height) = part.split_whitespace().next()?.split_once('x')?;
+                Some((width.parse().ok()?, height.parse().ok()?))
+            });
+            if let Some((width, height)) = resolution {
+                info.width = Some(width);
+                info.height = Some(height);
+            }
+        } else if let Some((_, details)) = line.split_once(": Audio: ") {
+            if info.audio_codec.is_none() {
+                info.audio_codec = codec_name(details);
+            }
+        }
+    }
+    info
+}
+
+/// Finds `Duration: HH:MM:SS.ss` in ffmpeg's input description.
+fn parse_ffmpeg_duration(stderr: &str) -> Option<f64> {
+    let rest = stderr.split_once("Duration: ")?.1;
+    let timestamp = rest.split(',').next()?.trim();
+    let mut parts = timestamp.split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Runs ffmpeg from `input` to `output`, emitting `remux-progress` (0–1)
+/// when the duration is known. Returns ffmpeg's error output on failure.
+async fn run_ffmpeg_with_progress(
+    app: &AppHandle,
+    ffmpeg: &std::path::Path,
+    input: &std::path::Path,
+    output: &std::path::Path,
+    codec_args: &[&str],
+    duration: Option<f64>,
+) -> Result<(), String> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+    let mut child = tokio::process::Command::new(ffmpeg)
+        .args([
+            "-hide_banner",
+            "-nostats",
+            "-n",
+            "-progress",
+            "pipe:1",
+            "-i",
+        ])
+        .arg(input)
+        .args(codec_args)
+        .arg(output)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Could not run ffmpeg: {e}"))?;
+
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr_task = tokio::spawn(async move {
+        let mut text = String::new();
+        stderr.read_to_string(&mut text).await.ok();
+        text
+    });
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let (Some(duration), Some(micros)) = (duration, line.strip_prefix("out_time_us=")) else {
+            continue;
+        };
+        if let Ok(micros) = micros.trim().parse::<f64>() {
+            let fraction = (micros / 1_000_000.0 / duration).clamp(0.0, 1.0);
+            app.emit("remux-progress", fraction).ok();
+        }
+    }
+
+    let status = child.wait().await.map_err(|e| e.to_string())?;
+    let stderr = stderr_task.await.unwrap_or_default();
+    if status.success() {
+        app.emit("remux-progress", 1.0_f64).ok();
+        Ok(())
+    } else {
+        Err(stderr)
+    }
+}
+
+/// Job ids stay unique across restarts so they can be stored alongside
+/// other records.
+fn next_job_id() -> String {
+    static NEXT_JOB: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+    let job = NEXT_JOB.fetch_add(1, Ordering::Relaxed);
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or(0);
+    format!("job-{millis}-{job}")
+}
+
+/// The `YYYY-MM-DD` folder for `date` inside `output_path`. It is the
+/// outermost folder the app adds, so any folders the output template
+/// creates nest inside it.
+fn dated_folder(output_path: &str, date: chrono::NaiveDate) -> String {
+    std::path::Path::new(output_path)
+        .join(date.format("%Y-%m-%d").to_string())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn job_temp_dir(output_path: &std::path::Path, job_id: &str) -> PathBuf {
+    output_path.join(format!(".video-downloader-tmp-{job_id}"))
+}
+
+/// Guesses the id yt-dlp reports for `url` without asking it: YouTube's `v`
+/// parameter, or else the last path segment (`youtu.be/<id>`,
+/// `vimeo.com/<id>`). Segments that read as words, such as the `videos` of
+/// a channel URL or `playlist`, are no ids.
+fn likely_video_id(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let id = match url.query_pairs().find(|(key, _)| key == "v") {
+        Some((_, id)) => id.into_owned(),
+        None => url
+            .path_segments()?
+            .rfind(|segment| !segment.is_empty())?
+            .to_string(),
+    };
+    let looks_like_id = id.len() >= 5
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && id
+            .chars()
+            .any(|c| c.is_ascii_digit() || c.is_ascii_uppercase());
+    looks_like_id.then_some(id)
+}
+
+/// Lists partial files (with their sizes) of video `id` left in
+/// `output_dir` or in the temp folders of earlier jobs there: `.part`
+/// files, the `.part-FragN` pieces of fragmented downloads and the `.ytdl`
+/// index that lists them, which only work together. The temp
+/// folders of `busy_jobs` are skipped: the job's own, which yt-dlp resumes
+/// by itself, and those of other running or paused jobs, which are still
+/// in use.
+fn find_partial_files(
+    output_dir: &std::path::Path,
+    id: &str,
+    busy_jobs: &[String],
+) -> Vec<(PathBuf, u64)> {
+    let marker = format!("[{id}]");
+    let busy_dirs = busy_jobs
+        .iter()
+        .map(|job_id| job_temp_dir(output_dir, job_id))
+        .collect::<Vec<_>>();
+    let mut dirs = vec![output_dir.to_path_buf()];
+    if let Ok(entries) = std::fs::read_dir(output_dir) {
+        dirs.extend(entries.flatten().map(|entry| entry.path()).filter(|path| {
+            path.is_dir()
+                && !busy_dirs.contains(path)
+                && path.file_name().is_some_and(|name| {
+                    name.to_string_lossy().starts_with(".video-downloader-tmp-")
+                })
+        }));
+    }
+    let mut partials = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // The title comes before the id and may contain anything.
+            let is_partial = name
+                .split_once(&marker)
+                .is_some_and(|(_, rest)| rest.contains(".part") || rest.ends_with(".ytdl"));
+            if is_partial {
+                let bytes = entry.metadata().map(|meta| meta.len()).unwrap_or(0);
+                partials.push((entry.path(), bytes));
+            }
+        }
+    }
+    partials
+}
+
+/// Size of the download: the estimate made while the job waited in the
+/// queue, or else a probe that takes a `size_probes` permit like those.
+async fn download_size(
+    app: &AppHandle,
+    state: &AppState,
+    job_id: &str,
+    args: &[String],
+    url: &str,
+) -> Option<u64> {
+    if let Some(estimate) = state.queue.lock().await.estimated_bytes(job_id) {
+        return Some(estimate);
+    }
+    let _permit = state.size_probes.acquire().await.ok()?;
+    probe_download_size(app, args, url).await
+}
+
+/// Total size of the files directly in `dir`.
+fn files_size(dir: &std::path::Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.metadata().ok())
+                .filter(|meta| meta.is_file())
+                .map(|meta| meta.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Ends a job stopped while its size was being checked, before yt-dlp ran,
+/// the way a stopped download ends: a paused job waits for
+/// `resume_download`, and one stopped for a new bandwidth share is left for
+/// `run_bandwidth_worker` to start again.
+async fn stop_before_launch(
+    app: &AppHandle,
+    state: &AppState,
+    job_id: &str,
+    temp_dir: &std::path::Path,
+) {
+    let paused = state.paused_jobs.lock().await.contains_key(job_id);
+    let restarting = state
+        .bandwidth_restarts
+        .lock()
+        .await
+        .get_mut(job_id)
+        .map(|restart| restart.stopped = true)
+        .is_some();
+    release_active_job(state, job_id).await;
+    if paused {
+        state
+            .kept_temp_dirs
+            .lock()
+            .await
+            .insert(job_id.to_string(), temp_dir.to_path_buf());
+        queue::job_finished(app, state, job_id, JobStatus::Paused).await;
+    } else if !restarting {
+        tokio::fs::remove_dir_all(temp_dir).await.ok();
+        queue::job_finished(app, state, job_id, JobStatus::Cancelled).await;
+    }
+    state.download_finished.notify_waiters();
+}
+
+/// Reads the info JSON of a single video with `yt-dlp -J`.
+async fn fetch_video_info(
+    app: &AppHandle,
+    state: &AppState,
+    url: &str,
+    cookie_browser: CookieBrowser,
+) -> Result<serde_json::Value, String> {
+    let (yt_dlp, _) = resolve_ytdlp(app)
+        .await
+        .ok_or_else(|| "yt-dlp is not installed".to_string())?;
+    let cookie_path = cookie_path_for_browser(&state.cookie_dir, cookie_browser);
+    let mut command = tokio::process::Command::new(yt_dlp);
+    command
+        .args(["-J", "--no-playlist", "--no-warnings"])
+        .args(session_cookie_args(cookie_path.as_deref(), cookie_browser))
+        .args(proxy_args(state).await)
+        .args(["--", url])
+        .kill_on_drop(true);
+    let output = tokio::time::timeout(std::time::Duration::from_secs(60), command.output())
+        .await
+        .map_err(|_| "Reading the video information timed out".to_string())?
+        .map_err(|e| format!("Could not run yt-dlp: {e}"))?;
+    if let Some(cookie_path) = &cookie_path {
+        secure_cookie_file(cookie_path);
+    }
+    let info = serde_json::from_slice(&output.stdout);
+    if !output.status.success() {
+        // yt-dlp can exit with an error after a non-fatal problem, such as
+        // one format failing, and still print complete information.
+        if let Ok(info) = info {
+            return Ok(info);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        let code = output.status.code().unwrap_or(-1);
+        return Err(DownloadError::from_exit(code, stderr).message);
+    }
+    info.map_err(|e| format!("yt-dlp returned unreadable video information: {e}"))
+}
+
+fn group_formats(info: &serde_json::Value) -> FormatGroups {
+    let mut groups = FormatGroups {
+        video_only: Vec::new(),
+        audio_only: Vec::new(),
+        combined: Vec::new(),
+        recommended: None,
+    };
+    let formats = info["formats"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for format in formats.iter().filter_map(FormatOption::from_info) {
+        match (&format.vcodec, &format.acodec) {
+            (Some(_), Some(_)) => groups.combined.push(format),
+            (Some(_), None) => groups.video_only.push(format),
+            (None, Some(_)) => groups.audio_only.push(format),
+            // Storyboards and other images
+            (None, None) => {}
+        }
+    }
+    for group in [
+        &mut groups.video_only,
+        &mut groups.audio_only,
+        &mut groups.combined,
+    ] {
+        // Stable, so yt-dlp's own order breaks ties.
+        group.sort_by_key(|format| std::cmp::Reverse(format.quality_key()));
+    }
+
+    let best_combined = groups.combined.first();
+    let best_merge = groups.video_only.first().zip(groups.audio_only.first());
+    groups.recommended = match (best_combined, best_merge) {
+        (Some(combined), Some((video, _))) if combined.height >= video.height => {
+            Some(combined.format_id.clone())
+        }
+        (_, Some((video, audio))) => Some(format!("{}+{}", video.format_id, audio.format_id)),
+        (Some(combined), None) => Some(combined.format_id.clone()),
+        (None, None) => None,
+    };
+    groups
+}
+
+/// Estimates how many bytes a download of `url` will fetch by asking yt-dlp
+/// which formats `args`, the download's own arguments, select, without
+/// downloading.
+async fn probe_download_size(app: &AppHandle, args: &[String], url: &str) -> Option<u64> {
+    let (yt_dlp, _) = resolve_ytdlp(app).await?;
+    let mut command = tokio::process::Command::new(yt_dlp);
+    command
+        .args(args)
+        .args(["-J", "--no-playlist", "--no-warnings", "--", url])
+        .kill_on_drop(true);
+
+    let output = tokio::time::timeout(std::time::Duration::from_secs(60), command.output())
+        .await
+        .ok()?
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    estimated_size(&info)
+}
+
+fn estimated_size(info: &serde_json::Value) -> Option<u64> {
+    let size_of = |value: &serde_json::Value| {
+        value["filesize"]
+            .as_u64()
+            .or_else(|| value["filesize_approx"].as_f64().map(|size| size as u64))
+    };
+    if let Some(formats) = info["requested_formats"].as_array() {
+        return formats.iter().map(size_of).sum();
+    }
+    size_of(info)
+}
+
+/// Fields yt-dlp records for each item of a playlist manifest.
+const PLAYLIST_ITEM_TEMPLATE: &str =
+    "%(.{id,title,filepath,playlist_index,playlist_id,playlist_title})j";
+
+/// Writes `playlist.json` into `output_dir` from the items yt-dlp recorded.
+/// Returns `None` when the download was not a playlist.
+async fn write_manifest(
+    items_file: &std::path::Path,
+    output_dir: &std::path::Path,
+    url: &str,
+) -> Result<Option<String>, String> {
+    let items = tokio::fs::read_to_string(items_file)
+        .await
+        .unwrap_or_default();
+    let Some(manifest) = playlist_manifest(&items, url) else {
+        return Ok(None);
+    };
+    let path = output_dir.join("playlist.json");
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    tokio::fs::write(&path, json)
+        .await
+        .map_err(|e| format!("Could not write the playlist manifest: {e}"))?;
+    Ok(Some(path.to_string_lossy().into_owned()))
+}
+
+fn playlist_manifest(items: &str, url: &str) -> Option<serde_json::Value> {
+    let items: Vec<serde_json::Value> = items
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let first = items.iter().find(|item| item["playlist_id"].is_string())?;
+    let entries: Vec<serde_json::Value> = items
+        .iter()
+        .map(|item| {
+            serde_json::json!({
+                "index": item["playlist_index"],
+                "id": item["id"],
+                "title": item["title"],
+                "file": item["filepath"],
+            })
+        })
+        .collect();
+    Some(serde_json::json!({
+        "playlist_id": first["playlist_id"],
+        "playlist_title": first["playlist_title"],
+        "url": url,
+        "items": entries,
+    }))
+}
+
+/// Finds the mounted volume holding `path` (the longest matching mount point).
+fn volume_for_path<'a>(
+    disks: &'a sysinfo::Disks,
+    path: &std::path::Path,
+) -> Option<&'a sysinfo::Disk> {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+}
+
+/// A running download is stopped once its output volume has less free space
+/// than this, before the disk fills up completely.
+const LOW_DISK_ABORT_BYTES: u64 = 200 * 1024 * 1024;
+const DISK_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Whether a download of `needed` bytes fits in `available` while leaving
+/// enough room that the low-disk watch does not stop it near the end.
+fn has_room_for(needed: u64, available: u64) -> bool {
+    needed
+        .checked_add(LOW_DISK_ABORT_BYTES)
+        .is_some_and(|total| total <= available)
+}
+
+/// Free space on the volume holding `path`, if the volume can be found.
+async fn free_space(path: PathBuf) -> Option<u64> {
+    tokio::task::spawn_blocking(move || {
+        let disks = sysinfo::Disks::new_with_refreshed_list();
+        volume_for_path(&disks, &path).map(|disk| disk.available_space())
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Polls the free space of the volume holding `path` and returns it once it
+/// drops below `LOW_DISK_ABORT_BYTES`. Never returns if the volume cannot
+/// be found.
+async fn wait_for_low_disk(path: PathBuf) -> u64 {
+    loop {
+        tokio::time::sleep(DISK_CHECK_INTERVAL).await;
+        let available = free_space(path.clone()).await;
+        if let Some(available) = available.filter(|bytes| *bytes < LOW_DISK_ABORT_BYTES) {
+            return available;
+        }
+    }
+}
+
+fn is_fat_volume(path: &std::path::Path) -> bool {
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    volume_for_path(&disks, path)
+        .map(|disk| {
+            let file_system = disk.file_system().to_string_lossy().to_ascii_lowercase();
+            ["fat", "vfat", "fat32", "exfat", "msdos"].contains(&file_system.as_str())
+        })
+        .unwrap_or(false)
+}
+
+/// Browsers yt-dlp can read cookies from. Anything else the frontend sends
+/// is rejected before yt-dlp is started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CookieBrowser {
+    #[default]
+    #[serde(alias = "")]
+    None,
+    Chrome,
+    Firefox,
+    Edge,
+    Brave,
+    Safari,
+    Chromium,
+    Opera,
+    Vivaldi,
+}
+
+impl CookieBrowser {
+    /// The name `--cookies-from-browser` expects, or `None` for no cookies.
+    fn yt_dlp_keyword(self) -> Option<&'static str> {
+        Some(match self {
+            CookieBrowser::None => return None,
+            CookieBrowser::Chrome => "chrome",
+            CookieBrowser::Firefox => "firefox",
+            CookieBrowser::Edge => "edge",
+            CookieBrowser::Brave => "brave",
+            CookieBrowser::Safari => "safari",
+            CookieBrowser::Chromium => "chromium",
+            CookieBrowser::Opera => "opera",
+            CookieBrowser::Vivaldi => "vivaldi",
+        })
+    }
+}
+
+fn cookie_path_for_browser(
+    cookie_dir: &std::path::Path,
+    browser: CookieBrowser,
+) -> Option<PathBuf> {
+    browser
+        .yt_dlp_keyword()
+        .map(|keyword| cookie_dir.join(format!("{keyword}.txt")))
+}
+
+/// Adapts yt-dlp arguments for a setup without ffmpeg: merge and remux
+/// targets are dropped and the format selector keeps only alternatives that
+/// are a single file. Fails when the download cannot work without ffmpeg.
+fn without_ffmpeg(args: Vec<String>) -> Result<Vec<String>, String> {
+    const NEEDS_FFMPEG: &[&str] = &[
+        "-x",
+        "--extract-audio",
+        "--recode-video",
+        "--convert-subs",
+        "--convert-thumbnails",
+        "--embed-subs",
+        "--embed-thumbnail",
+        "--embed-metadata",
+        "--embed-chapters",
+        "--embed-info-json",
+        "--sponsorblock-mark",
+        "--sponsorblock-remove",
+        "--download-sections",
+        "--force-keyframes-at-cuts",
+    ];
+    const INSTALL_HINT: &str =
+        "Install ffmpeg from the setup screen (or restart the app to run setup again) and try again.";
+
+    let mut adapted = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if NEEDS_FFMPEG.contains(&arg.as_str()) {
+            return Err(format!(
+                "{arg} needs ffmpeg, which is not installed. {INSTALL_HINT}"
+            ));
+        }
+        match arg.as_str() {
+            "--merge-output-format" | "--remux-video" => {
+                args.next();
+            }
+            "-f" | "--format" => {
+                let selector = args.next().unwrap_or_default();
+                let single_file: Vec<&str> = selector
+                    .split('/')
+                    .filter(|alternative| !alternative.contains('+'))
+                    .collect();
+                if single_file.is_empty() {
+                    return Err(format!(
+                        "The selected quality needs ffmpeg to merge video and audio, which is not installed. {INSTALL_HINT}"
+                    ));
+                }
+                adapted.extend([arg, single_file.join("/")]);
+            }
+            _ => adapted.push(arg),
+        }
+    }
+    Ok(adapted)
+}
+
+/// ffmpeg encoders behind audio extraction, thumbnail cropping and
+/// re-encoding in `remux_file`.
+const USED_ENCODERS: &[&str] = &[
+    "libmp3lame",
+    "aac",
+    "libopus",
+    "libvorbis",
+    "flac",
+    "alac",
+    "pcm_s16le",
+    "mjpeg",
+    "libx264",
+    "libvpx-vp9",
+];
+
+/// The ffmpeg encoder yt-dlp converts extracted audio with.
+fn audio_encoder(format: &str) -> Option<&'static str> {
+    Some(match format {
+        "mp3" => "libmp3lame",
+        "m4a" | "aac" => "aac",
+        "opus" => "libopus",
+        "vorbis" => "libvorbis",
+        "flac" => "flac",
+        "alac" => "alac",
+        "wav" => "pcm_s16le",
+        _ => return None,
+    })
+}
+
+/// Reads encoder names from `ffmpeg -encoders`, whose list follows a
+/// ` ------` line with entries such as ` A....D libmp3lame  MP3 ...`.
+fn parse_encoders(output: &str) -> std::collections::HashSet<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(str::to_string)
+        .collect()
+}
+
+async fn ffmpeg_encoders(ffmpeg: &std::path::Path) -> Option<std::collections::HashSet<String>> {
+    let output = tokio::process::Command::new(ffmpeg)
+        .args(["-hide_banner", "-encoders"])
+        .kill_on_drop(true)
+        .output()
+        .await
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| parse_encoders(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Fails early when the installed ffmpeg lacks one of `encoders`, instead
+/// of letting post-processing fail halfway with a cryptic error. When the
+/// list cannot be read the job goes ahead.
+async fn require_encoders(ffmpeg: &std::path::Path, encoders: &[&str]) -> Result<(), String> {
+    if encoders.is_empty() {
+        return Ok(());
+    }
+    let Some(available) = ffmpeg_encoders(ffmpeg).await else {
+        return Ok(());
+    };
+    match encoders
+        .iter()
+        .find(|encoder| !available.contains(**encoder))
+    {
+        Some(encoder) => Err(format!(
+            "The installed ffmpeg has no {encoder} encoder. Install a full ffmpeg build, such as the one from the setup screen, and try again."
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Audio formats yt-dlp can extract into; the last three are lossless.
+const AUDIO_FORMATS: &[&str] = &["mp3", "m4a", "aac", "opus", "vorbis", "flac", "wav", "alac"];
+const LOSSLESS_AUDIO_FORMATS: &[&str] = &["flac", "wav", "alac"];
+/// Audio formats whose containers have a lyrics tag ffmpeg can write.
+const LYRICS_AUDIO_FORMATS: &[&str] = &["mp3", "m4a", "alac", "opus", "vorbis", "flac"];
+
+fn audio_extraction_args(
+    format: Option<&str>,
+    bitrate: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let bitrate = bitrate.map(str::trim).filter(|bitrate| !bitrate.is_empty());
+    let Some(format) = format.map(|format| format.trim().to_ascii_lowercase()) else {
+        return match bitrate {
+            Some(_) => Err("Choose an audio format to set an audio bitrate".to_string()),
+            None => Ok(Vec::new()),
+        };
+    };
+    if !AUDIO_FORMATS.contains(&format.as_str()) {
+        return Err(format!(
+            "Unsupported audio format \"{format}\". Choose one of: {}",
+            AUDIO_FORMATS.join(", ")
+        ));
+    }
+    let mut args = vec![
+        "-x".to_string(),
+        "--audio-format".to_string(),
+        format.clone(),
+    ];
+    if let Some(bitrate) = bitrate {
+        if LOSSLESS_AUDIO_FORMATS.contains(&format.as_str()) {
+            return Err(format!(
+                "{} is lossless, so it has no bitrate to choose. Remove the bitrate or pick a lossy format such as mp3.",
+                format.to_ascii_uppercase()
+            ));
+        }
+        let kbps = bitrate
+            .strip_suffix(['K', 'k'])
+            .unwrap_or(bitrate)
+            .parse::<u32>()
+            .ok()
+            .filter(|kbps| (8..=512).contains(kbps))
+            .ok_or_else(|| {
+                format!("Invalid audio bitrate \"{bitrate}\". Use kbit/s between 8K and 512K, such as 192K")
+            })?;
+        args.extend(["--audio-quality".to_string(), format!("{kbps}K")]);
+    }
+    Ok(args)
+}
+
+/// The extension yt-dlp gives audio extracted into `format`.
+fn extracted_audio_extension(format: &str) -> &str {
+    match format {
+        "vorbis" => "ogg",
+        "alac" => "m4a",
+        other => other,
+    }
+}
+
+/// The audio file that `.lrc` lyrics such as `Song [id].en.lrc` belong to.
+fn lyrics_audio_file(lyrics: &std::path::Path, audio_extension: &str) -> PathBuf {
+    lyrics.with_extension("").with_extension(audio_extension)
+}
+
+/// Stores the lyrics file `lyrics` as the lyrics tag of its audio file,
+/// which ffmpeg rewrites without re-encoding.
+async fn embed_lyrics(
+    ffmpeg: &std::path::Path,
+    lyrics: &std::path::Path,
+    audio_extension: &str,
+) -> Result<(), String> {
+    let audio = lyrics_audio_file(lyrics, audio_extension);
+    let text = tokio::fs::read_to_string(lyrics)
+        .await
+        .map_err(|e| format!("Could not read the lyrics in {}: {e}", lyrics.display()))?;
+    let tagged = audio.with_extension(format!("lyrics.{audio_extension}"));
+    let output = tokio::process::Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostdin", "-v", "error", "-y", "-i"])
+        .arg(&audio)
+        .args(["-map", "0", "-c", "copy", "-metadata"])
+        .arg(format!("lyrics={text}"))
+        .arg(&tagged)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Could not run ffmpeg: {e}"))?;
+    if !output.status.success() {
+        tokio::fs::remove_file(&tagged).await.ok();
+        return Err(format!(
+            "ffmpeg could not embed the lyrics into {}: {}",
+            audio.display(),
+            last_line(&String::from_utf8_lossy(&output.stderr))
+        ));
+    }
+    tokio::fs::rename(&tagged, &audio).await.map_err(|e| {
+        format!(
+            "Could not replace {} with the tagged file: {e}",
+            audio.display()
+        )
+    })
+}
+
+/// Containers yt-dlp embeds the info JSON into with `--embed-info-json`.
+const INFO_JSON_CONTAINERS: &[&str] = &["mkv", "mka"];
+
+/// Containers ffmpeg can store a cover image in.
+const COVER_CONTAINERS: &[&str] = &["mp4", "m4a", "mov", "mp3", "flac", "mkv", "mka"];
+
+fn cover_mime_type(image: &std::path::Path) -> Option<&'static str> {
+    let extension = image.extension()?.to_string_lossy().to_ascii_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        _ => None,
+    }
+}
+
+/// ffmpeg arguments that copy `media` with `image` added as its cover.
+/// Matroska keeps covers as attachments, the other containers as an
+/// attached picture stream.
+fn cover_args(media: &std::path::Path, image: &std::path::Path) -> Result<Vec<String>, String> {
+    let extension = media
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if !COVER_CONTAINERS.contains(&extension.as_str()) {
+        return Err(format!(
+            "A custom thumbnail cannot be embedded into .{extension} files"
+        ));
+    }
+    let mime_type = cover_mime_type(image)
+        .ok_or_else(|| "Choose a .jpg or .png image as the thumbnail".to_string())?;
+    let media = media.to_string_lossy().into_owned();
+    let image = image.to_string_lossy().into_owned();
+    let mut args = vec!["-i".to_string(), media];
+    if matches!(extension.as_str(), "mkv" | "mka") {
+        let file_name = if mime_type == "image/png" {
+            "cover.png"
+        } else {
+            "cover.jpg"
+        };
+        args.extend([
+            "-map".to_string(),
+            "0".to_string(),
+            "-c".to_string(),
+            "copy".to_string(),
+            "-attach".to_string(),
+            image,
+            "-metadata:s:t".to_string(),
+            format!("mimetype={mime_type}"),
+            "-metadata:s:t".to_string(),
+            format!("filename={file_name}"),
+        ]);
+        return Ok(args);
+    }
+    // The image goes first so it is output stream 0 whatever the media
+    // holds; an existing cover (`0:v` but not `0:V`) is replaced.
+    args.extend(
+        [
+            "-i",
+            &image,
+            "-map",
+            "1",
+            "-map",
+            "0:V?",
+            "-map",
+            "0:a?",
+            "-map",
+            "0:s?",
+            "-c",
+            "copy",
+            "-disposition:0",
+            "attached_pic",
+        ]
+        .map(str::to_string),
+    );
+    if extension == "mp3" {
+        args.extend(["-id3v2_version".to_string(), "3".to_string()]);
+    }
+    Ok(args)
+}
+
+/// Embeds `image` as the cover of `media`, rewriting it without
+/// re-encoding.
+async fn embed_cover(
+    ffmpeg: &std::path::Path,
+    media: &std::path::Path,
+    image: &std::path::Path,
+) -> Result<(), String> {
+    let args = cover_args(media, image)?;
+    let extension = media
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tagged = media.with_extension(format!("cover.{extension}"));
+    let output = tokio::process::Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostdin", "-v", "error", "-y"])
+        .args(&args)
+        .arg(&tagged)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Could not run ffmpeg: {e}"))?;
+    if !output.status.success() {
+        tokio::fs::remove_file(&tagged).await.ok();
+        return Err(format!(
+            "ffmpeg could not embed the thumbnail into {}: {}",
+            media.display(),
+            last_line(&String::from_utf8_lossy(&output.stderr))
+        ));
+    }
+    tokio::fs::rename(&tagged, media).await.map_err(|e| {
+        format!(
+            "Could not replace {} with the tagged file: {e}",
+            media.display()
+        )
+    })
+}
+
+/// Heights a `quality` may ask for.
+const QUALITY_HEIGHTS: &[u32] = &[144, 240, 360, 480, 720, 1080, 1440, 2160, 4320];
+
+/// Turns a quality such as `1080p60 av1` into a yt-dlp sort order. Each
+/// part is optional: a height with an optional frame rate (`720p`,
+/// `1080p60`) and a video codec (`h264`, `vp9`, `av1`). The best format at
+/// or below the height and frame rate is chosen, preferring the codec.
+fn quality_sort(quality: &str) -> Result<String, String> {
+    let invalid = |reason: String| format!("Could not understand quality \"{quality}\": {reason}");
+    let mut height = None;
+    let mut fps = None;
+    let mut codec = None;
+    for part in quality.split_whitespace().map(str::to_ascii_lowercase) {
+        let part_codec = match part.as_str() {
+            "h264" | "avc" => Some("h264"),
+            "vp9" => Some("vp9"),
+            "av1" => Some("av01"),
+            _ => None,
+        };
+        if let Some(part_codec) = part_codec {
+            if codec.replace(part_codec).is_some() {
+                return Err(invalid("give only one codec".to_string()));
+            }
+            continue;
+        }
+        let Some((part_height, part_fps)) = part.split_once('p') else {
+            return Err(invalid(format!(
+                "\"{part}\" is neither a resolution such as 1080p nor one of h264, vp9, av1"
+            )));
+        };
+        let part_height = part_height
+            .parse::<u32>()
+            .ok()
+            .filter(|height| QUALITY_HEIGHTS.contains(height))
+            .ok_or_else(|| invalid(format!("unsupported resolution {part}")))?;
+        if height.replace(part_height).is_some() {
+            return Err(invalid("give only one resolution".to_string()));
+        }
+        match part_fps {
+            "" => {}
+            "30" | "60" => fps = Some(part_fps.to_string()),
+            _ => {
+                return Err(invalid(format!(
+                    "frame rate must be 30 or 60, not {part_fps}"
+                )))
+            }
+        }
+    }
+    let mut sort = Vec::new();
+    if let Some(height) = height {
+        sort.push(format!("res:{height}"));
+    }
+    if let Some(fps) = fps {
+        sort.push(format!("fps:{fps}"));
+    }
+    if let Some(codec) = codec {
+        sort.push(format!("vcodec:{codec}"));
+    }
+    if sort.is_empty() {
+        return Err(invalid("it is empty".to_string()));
+    }
+    Ok(sort.join(","))
+}
+
+/// The height a `quality` asks for, if it names one.
+fn quality_height(quality: &str) -> Option<u32> {
+    quality
+        .split_whitespace()
+        .find_map(|part| part.split_once(['p', 'P'])?.0.parse().ok())
+}
+
+/// Containers yt-dlp can merge separate video and audio streams into.
+const MERGE_CONTAINERS: &[&str] = &["mp4", "mkv", "webm", "mov", "flv", "avi"];
+
+fn format_preference_args(
+    prefer_quality_over_compatibility: bool,
+    merge_formats: &[String],
+) -> Vec<String> {
+    let containers = |default: &[&str]| {
+        if merge_formats.is_empty() {
+            default
+                .iter()
+                .map(|container| container.to_string())
+                .collect()
+        } else {
+            merge_formats.to_vec()
+        }
+    };
+    if prefer_quality_over_compatibility {
+        // Matroska holds any codec mix, so nothing needs re-encoding.
+        let merge = containers(&["mkv"]);
+        return vec![
+            "-S".to_string(),
+            "res,fps,vcodec:av1,br".to_string(),
+            "--merge-output-format".to_string(),
+            merge.join("/"),
+        ];
+    }
+
+    // Single-file downloads are remuxed into the first container too, but
+    // files already merged into a fallback container are left alone since
+    // their codecs did not fit the preferred one.
+    let merge = containers(&["mp4", "mkv"]);
+    let mut remux_rules: Vec<String> = merge[1..]
+        .iter()
+        .map(|container| format!("{container}>{container}"))
+        .collect();
+    remux_rules.push(merge[0].clone());
+    vec![
+        "-S".to_string(),
+        "res,fps,br".to_string(),
+        "--merge-output-format".to_string(),
+        merge.join("/"),
+        "--remux-video".to_string(),
+        remux_rules.join("/"),
+    ]
+}
+
+/// Categories yt-dlp accepts for SponsorBlock. The last two only describe
+/// points and chapter titles, so they can be marked but not removed.
+const SPONSORBLOCK_CATEGORIES: &[&str] = &[
+    "sponsor",
+    "intro",
+    "outro",
+    "selfpromo",
+    "preview",
+    "filler",
+    "interaction",
+    "music_offtopic",
+    "poi_highlight",
+    "chapter",
+];
+
+/// Validates SponsorBlock categories and joins them for yt-dlp.
+fn sponsorblock_categories(categories: &[String], marking: bool) -> Result<String, String> {
+    let mut valid = Vec::with_capacity(categories.len());
+    for category in categories {
+        let category = category.trim().to_ascii_lowercase();
+        let known = category == "all"
+            || SPONSORBLOCK_CATEGORIES[..SPONSORBLOCK_CATEGORIES.len() - 2]
+                .contains(&category.as_str())
+            || (marking && SPONSORBLOCK_CATEGORIES.contains(&category.as_str()));
+        if !known {
+            return Err(format!(
+                "Unknown SponsorBlock category for {}: {category}",
+                if marking { "marking" } else { "removal" }
+            ));
+        }
+        valid.push(category);
+    }
+    Ok(valid.join(","))
+}
+
+/// What SponsorBlock did to a job, sent as `sponsorblock-result` when it
+/// finishes.
+#[derive(Clone, Default, Serialize)]
+struct SponsorBlockResult {
+    /// Segments found in the database, summed over all downloaded items.
+    segments_found: u32,
+    marked: Vec<String>,
+    removed: Vec<String>,
+    /// Files that had segments cut out.
+    files_cut: u32,
+}
+
+impl SponsorBlockResult {
+    fn observe(&mut self, line: &str) {
+        if let Some(rest) = line.strip_prefix("[SponsorBlock] Found ") {
+            if let Some(count) = rest.split_whitespace().next() {
+                self.segments_found += count.parse::<u32>().unwrap_or(0);
+            }
+        } else if line.starts_with("[ModifyChapters] Removing chapters from ") {
+            self.files_cut += 1;
+        }
+    }
+}
+
+/// Parses a `YYYYMMDD` date, returning it trimmed along with its day number
+/// for ordering checks.
+fn parse_upload_date(label: &str, value: &str) -> Result<(String, i64), String> {
+    let value = value.trim();
+    let invalid = || format!("{label} must be a date in YYYYMMDD format, got \"{value}\"");
+    if value.len() != 8 || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let year = value[..4].parse::<i64>().map_err(|_| invalid())?;
+    let month = value[4..6].parse::<u32>().map_err(|_| invalid())?;
+    let day = value[6..].parse::<u32>().map_err(|_| invalid())?;
+    // Also rules out days a month does not have, such as 20240231.
+    chrono::NaiveDate::from_ymd_opt(year as i32, month, day).ok_or_else(invalid)?;
+    Ok((value.to_string(), days_from_civil(year, month, day)))
+}
+
+/// Validates a subtitle language code such as `en` or `pt-BR`, falling back
+/// to English when none is given.
+fn subtitle_language<'a>(label: &str, language: Option<&'a str>) -> Result<&'a str, String> {
+    let language = language
+        .map(str::trim)
+        .filter(|language| !language.is_empty())
+        .unwrap_or("en");
+    if !language
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("Invalid {label} language: {language}"));
+    }
+    Ok(language)
+}
+
+fn is_direct_instagram_story_url(url: &str) -> bool {
+    let Some((_, story_path)) = url.split_once("instagram.com/stories/") else {
+        return false;
+    };
+    let mut segments = story_path
+        .split(['/', '?', '#'])
+        .filter(|segment| !segment.is_empty());
+    let _username = segments.next();
+    segments
+        .next()
+        .map(|story_id| story_id.chars().all(|character| character.is_ascii_digit()))
+        .unwrap_or(false)
+}
+
+/// Reads cookies from the session jar once it has been filled; until then
+/// extracts them from `browser` and saves them into the jar for next time.
+fn session_cookie_args(
+    cookie_path: Option<&std::path::Path>,
+    browser: CookieBrowser,
+) -> Vec<String> {
+    let (Some(cookie_path), Some(browser)) = (cookie_path, browser.yt_dlp_keyword()) else {
+        return Vec::new();
+    };
+    let jar = cookie_path.to_string_lossy().into_owned();
+    if cookie_jar_has_entries(cookie_path) {
+        vec!["--cookies".to_string(), jar]
+    } else {
+        vec![
+            "--cookies-from-browser".to_string(),
+            browser.to_string(),
+            "--cookies".to_string(),
+            jar,
+        ]
+    }
+}
+
+/// Lowercased yt-dlp error fragments that point at the network or the site's
+/// servers rather than the video.
+const NETWORK_ERRORS: &[&str] = &[
+    "unable to download webpage",
+    "timed out",
+    "connection refused",
+    "connection reset",
+    "name resolution",
+    "getaddrinfo",
+    "network is unreachable",
+    "http error 5",
+];
+
+fn cookie_check_failure(stderr: &str) -> Result<bool, String> {
+    const COOKIE_STORE_ERRORS: &[&str] = &["cookie database", "cookies database", "keyring"];
+    const LOGIN_ERRORS: &[&str] = &[
+        "sign in",
+        "login",
+        "log in",
+        "logged-in",
+        "registered users",
+        "members-only",
+        "members only",
+        "private video",
+        "authentication",
+        "confirm your age",
+        "--cookies",
+    ];
+
+    let error = stderr
+        .lines()
+        .rev()
+        .find(|line| line.starts_with("ERROR:"))
+        .unwrap_or_else(|| stderr.trim());
+    let lower = error.to_ascii_lowercase();
+    let mentions = |patterns: &[&str]| patterns.iter().any(|pattern| lower.contains(pattern));
+
+    if mentions(COOKIE_STORE_ERRORS) {
+        Err(format!("Could not read browser cookies: {error}"))
+    } else if mentions(NETWORK_ERRORS) {
+        Err(format!("Network error while checking cookies: {error}"))
+    } else if mentions(LOGIN_ERRORS) {
+        Ok(false)
+    } else if error.is_empty() {
+        Err("Cookie check failed".to_string())
+    } else {
+        Err(error.to_string())
+    }
+}
+
+fn cleanup_stale_cookie_dirs() {
+    let temp_dir = std::env::temp_dir();
+    let current_pid = std::process::id();
+    let Ok(entries) = std::fs::read_dir(&temp_dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let Some(pid) = name
+            .strip_prefix("video-downloader-cookies-")
+            .and_then(|value| value.parse::<u32>().ok())
+        else {
+            continue;
+        };
+
+        if pid != current_pid && !process_is_running(pid) {
+            std::fs::remove_dir_all(entry.path()).ok();
+        }
+    }
+}
+
+#[cfg(unix)]
+fn process_is_running(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+fn process_is_running(pid: u32) -> bool {
+    std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/FO", "CSV", "/NH"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&format!("\"{pid}\"")))
+        .unwrap_or(false)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn process_is_running(_pid: u32) -> bool {
+    false
+}
+
+fn kill_stray_processes(binaries: &[PathBuf]) -> usize {
+    use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing().with_exe(UpdateKind::OnlyIfNotSet),
+    );
+    let own_pid = Pid::from_u32(std::process::id());
+    let started_by_us = |process: &sysinfo::Process| {
+        let mut parent = process.parent();
+        // Bounded walk in case the process table contains a parent cycle.
+        for _ in 0..64 {
+            match parent {
+                Some(pid) if pid == own_pid => return true,
+                Some(pid) => parent = system.process(pid).and_then(|p| p.parent()),
+                None => return false,
+            }
+        }
+        false
+    };
+
+    system
+        .processes()
+        .values()
+        .filter(|process| {
+            process
+                .exe()
+                .is_some_and(|exe| binaries.iter().any(|binary| binary == exe))
+        })
+        .filter(|process| !started_by_us(process))
+        .filter(|process| process.kill())
+        .count()
+}
+
+struct SleepGuard {
+    helper: Option<std::process::Child>,
+}
+
+impl Drop for SleepGuard {
+    fn drop(&mut self) {
+        if let Some(helper) = self.helper.as_mut() {
+            helper.kill().ok();
+            helper.wait().ok();
+        }
+    }
+}
+
+// Other platforms have no helper to hand the pid to.
+#[cfg_attr(not(any(target_os = "macos", windows)), allow(unused_variables))]
+fn prevent_sleep(pid: Option<u32>) -> SleepGuard {
+    let Some(pid) = pid else {
+        return SleepGuard { helper: None };
+    };
+
+    #[cfg(target_os = "macos")]
+    let helper = std::process::Command::new("/usr/bin/caffeinate")
+        .args(["-i", "-w", &pid.to_string()])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .ok();
+
+    #[cfg(windows)]
+    let helper = {
+        let script = format!(
+            r#"
+Add-Type -TypeDefinition @'
+using System;
+using System.Runtime.InteropServices;
+public static class SleepControl {{
+    [DllImport("kernel32.dll")]
+    public static extern uint SetThreadExecutionState(uint esFlags);
+}}
+'@
+[SleepControl]::SetThreadExecutionState(0x80000001) | Out-Null
+try {{ Wait-Process -Id {pid} -ErrorAction SilentlyContinue }}
+finally {{ [SleepControl]::SetThreadExecutionState(0x80000000) | Out-Null }}
+"#
+        );
+        std::process::Command::new("powershell.exe")
+            .args([
+                "-NoProfile",
+                "-NonInteractive",
+                "-WindowStyle",
+                "Hidden",
+                "-Command",
+                &script,
+            ])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .ok()
+    };
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let helper = None;
+
+    SleepGuard { helper }
+}
+
+fn cookie_jar_has_entries(path: &std::path::Path) -> bool {
+    std::fs::read_to_string(path)
+        .map(|contents| {
+            contents
+                .lines()
+                .any(|line| !line.is_empty() && !line.starts_with('#'))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn secure_cookie_dir(path: &std::path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o700)).ok();
+}
+
+#[cfg(not(unix))]
+fn secure_cookie_dir(_path: &std::path::Path) {}
+
+#[cfg(unix)]
+fn secure_cookie_file(path: &std::path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    if path.exists() {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).ok();
+    }
+}
+
+#[cfg(not(unix))]
+fn secure_cookie_file(_path: &std::path::Path) {}
+
+/// Reads `yt-dlp --list-extractors`, one name per line, skipping the ones
+/// marked `(CURRENTLY BROKEN)`.
+fn parse_extractors(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && !name.ends_with("(CURRENTLY BROKEN)"))
+        .map(str::to_string)
+        .collect()
+}
+
+async fn read_ytdlp_version(path: &std::path::Path) -> Result<String, String> {
+    let mut command = tokio::process::Command::new(path);
+    command.arg("--version").kill_on_drop(true);
+    let output = tokio::time::timeout(std::time::Duration::from_secs(10), command.output())
+        .await
+        .map_err(|_| "yt-dlp version check timed out".to_string())?
+        .map_err(|e| format!("Could not run yt-dlp: {e}"))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(if error.is_empty() {
+            format!("yt-dlp exited with code {:?}", output.status.code())
+        } else {
+            error
+        });
+    }
+
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if version.is_empty() {
+        Err("yt-dlp returned an empty version".to_string())
+    } else {
+        Ok(version)
+    }
+}
+
+async fn ffmpeg_is_working(path: &std::path::Path) -> bool {
+    tool_is_working(path, "-version").await
+}
+
+/// Whether `path` runs and exits successfully when asked for its version.
+async fn tool_is_working(path: &std::path::Path, version_arg: &str) -> bool {
+    let mut command = tokio::process::Command::new(path);
+    command
+        .arg(version_arg)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true);
+    tokio::time::timeout(std::time::Duration::from_secs(10), command.status())
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+async fn fetch_latest_ytdlp_version(client: &HttpClient) -> Result<String, String> {
+    let response = client
+        .get("https://api.github.com/repos/yt-dlp/yt-dlp/releases/latest")
+        .timeout(std::time::Duration::from_secs(8))
+        .header(
+            reqwest::header::USER_AGENT,
+            concat!("VideoDownloader/", env!("CARGO_PKG_VERSION")),
+        )
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await
+        .map_err(|e| format!("Could not check for updates: {}", client.describe(&e)))?
+        .error_for_status()
+        .map_err(|e| format!("Update check failed: {e}"))?;
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Could not read update information: {e}"))?;
+    let release: GithubRelease = serde_json::from_slice(&body)
+        .map_err(|e| format!("Could not parse update information: {e}"))?;
+
+    Ok(release.tag_name.trim_start_matches('v').to_string())
+}
+
+fn version_day(version: &str) -> Option<i64> {
+    let date = version.trim_start_matches('v').get(..10)?;
+    let mut parts = date.split('.');
+    let year = parts.next()?.parse::<i64>().ok()?;
+    let month = parts.next()?.parse::<u32>().ok()?;
+    let day = parts.next()?.parse::<u32>().ok()?;
+    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day))
+}
+
+fn days_from_civil(mut year: i64, month: u32, day: u32) -> i64 {
+    year -= i64::from(month <= 2);
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let shifted_month = month as i64 + if month > 2 { -3 } else { 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era
+}
+
+async fn download_to_path(
+    client: &HttpClient,
+    url: &str,
+    dest: &std::path::Path,
+) -> Result<(), String> {
+    use futures_util::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Could not download yt-dlp: {}", client.describe(&e)))?
+        .error_for_status()
+        .map_err(|e| format!("yt-dlp download failed: {e}"))?;
+    if is_html_response(&response) {
+        return Err(html_download_error(url));
+    }
+
+    let mut file = tokio::fs::File::create(dest)
+        .await
+        .map_err(|e| format!("Cannot create update file: {e}"))?;
+    let mut stream = response.bytes_stream();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| format!("yt-dlp download failed: {e}"))?;
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("Cannot write update file: {e}"))?;
+    }
+
+    file.flush()
+        .await
+        .map_err(|e| format!("Cannot finish update file: {e}"))?;
+    drop(file);
+
+    if file_starts_like_html(dest).await {
+        return Err(html_download_error(url));
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+async fn replace_ytdlp(current: &std::path::Path, update: &std::path::Path) -> Result<(), String> {
+    tokio::fs::rename(update, current)
+        .await
+        .map_err(|e| format!("Could not install yt-dlp update: {e}"))
+}
+
+#[cfg(windows)]
+async fn replace_ytdlp(current: &std::path::Path, update: &std::path::Path) -> Result<(), String> {
+    let backup = current.with_extension("exe.backup");
+    tokio::fs::remove_file(&backup).await.ok();
+    tokio::fs::rename(current, &backup)
+        .await
+        .map_err(|e| format!("Could not prepare yt-dlp update: {e}"))?;
+
+    if let Err(error) = tokio::fs::rename(update, current).await {
+        tokio::fs::rename(&backup, current).await.ok();
+        return Err(format!("Could not install yt-dlp update: {error}"));
+    }
+
+    tokio::fs::remove_file(backup).await.ok();
+    Ok(())
+}
+
+async fn download_file(
+    client: &HttpClient,
+    url: &str,
+    dest: &std::path::Path,
+    progress: &SetupProgress,
+    part: usize,
+) -> Result<(), String> {
+    use futures_util::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    let resp = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| client.describe(&e))?
+        .error_for_status()
+        .map_err(|e| e.to_string())?;
+    if is_html_response(&resp) {
+        return Err(html_download_error(url));
+    }
+    let total = resp.content_length().unwrap_or(0);
+    let mut received: u64 = 0;
+
+    let temp_dest = dest.with_extension("download");
+    tokio::fs::remove_file(&temp_dest).await.ok();
+
+    let mut file = tokio::fs::File::create(&temp_dest)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(error) => {
+                tokio::fs::remove_file(&temp_dest).await.ok();
+                return Err(error.to_string());
+            }
+        };
+        if let Err(error) = file.write_all(&chunk).await {
+            tokio::fs::remove_file(&temp_dest).await.ok();
+            return Err(error.to_string());
+        }
+        received += chunk.len() as u64;
+        if total > 0 {
+            progress.set(part, received as f64 / total as f64);
+        }
+    }
+    file.flush().await.map_err(|e| e.to_string())?;
+    drop(file);
+    if file_starts_like_html(&temp_dest).await {
+        tokio::fs::remove_file(&temp_dest).await.ok();
+        return Err(html_download_error(url));
+    }
+    tokio::fs::remove_file(dest).await.ok();
+    tokio::fs::rename(&temp_dest, dest)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// HTTP client for the app's own downloads, going through the proxy from
+/// the saved settings when one is set.
+struct HttpClient {
+    client: reqwest::Client,
+    proxy: Option<String>,
+}
+
+impl HttpClient {
+    async fn new(app: &AppHandle) -> Result<Self, String> {
+        Self::from_builder(app, reqwest::Client::builder()).await
+    }
+
+    async fn from_builder(
+        app: &AppHandle,
+        builder: reqwest::ClientBuilder,
+    ) -> Result<Self, String> {
+        let proxy = app.state::<AppState>().proxy_url.lock().await.clone();
+        let builder = match &proxy {
+            Some(proxy) => builder.proxy(
+                reqwest::Proxy::all(proxy).map_err(|e| format!("Invalid proxy {proxy}: {e}"))?,
+            ),
+            None => builder,
+        };
+        let client = builder.build().map_err(|e| e.to_string())?;
+        Ok(Self { client, proxy })
+    }
+
+    fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.get(url)
+    }
+
+    /// Explains a failed request. With a proxy, every connection goes to
+    /// it, so failing to connect means the proxy is down or refused.
+    fn describe(&self, error: &reqwest::Error) -> String {
+        match &self.proxy {
+            Some(proxy) if error.is_connect() => {
+                format!(
+                    "Could not connect to the proxy {proxy}. Check the proxy setting. ({error})"
+                )
+            }
+            _ => error.to_string(),
+        }
+    }
+}
+
+/// `--proxy` for yt-dlp when the settings name a proxy.
+async fn proxy_args(state: &AppState) -> Vec<String> {
+    match state.proxy_url.lock().await.clone() {
+        Some(proxy) => vec!["--proxy".to_string(), proxy],
+        None => Vec::new(),
+    }
+}
+
+fn is_html_response(response: &reqwest::Response) -> bool {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase().starts_with("text/html"))
+        .unwrap_or(false)
+}
+
+// GitHub sometimes answers a release download with an HTML error page
+// (rate limits, outages) and a non-HTML content type, so the saved bytes are
+// checked as well.
+fn starts_like_html(bytes: &[u8]) -> bool {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let start = bytes
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let head = bytes[start..]
+        .iter()
+        .take(16)
+        .map(u8::to_ascii_lowercase)
+        .collect::<Vec<_>>();
+    head.starts_with(b"<!doctype") || head.starts_with(b"<html")
+}
+
+async fn file_starts_like_html(path: &std::path::Path) -> bool {
+    use tokio::io::AsyncReadExt;
+
+    let Ok(mut file) = tokio::fs::File::open(path).await else {
+        return false;
+    };
+    let mut head = [0_u8; 512];
+    let read = file.read(&mut head).await.unwrap_or(0);
+    starts_like_html(&head[..read])
+}
+
+fn html_download_error(url: &str) -> String {
+    format!(
+        "{url} returned a web page instead of the expected file. The server may be rate limiting downloads; try again in a few minutes."
+    )
+}
+
+const YTDLP_CHECKSUMS_URL: &str =
+    "https://github.com/yt-dlp/yt-dlp/releases/latest/download/SHA2-256SUMS";
+
+/// The published SHA-256 of the yt-dlp build `ytdlp_download_url` points
+/// at.
+async fn fetch_ytdlp_checksum(client: &HttpClient) -> Result<String, String> {
+    let sums = client
+        .get(YTDLP_CHECKSUMS_URL)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| {
+            format!(
+                "Could not download the yt-dlp checksums: {}",
+                client.describe(&e)
+            )
+        })?
+        .text()
+        .await
+        .map_err(|e| format!("Could not download the yt-dlp checksums: {e}"))?;
+    let asset = ytdlp_download_url().rsplit('/').next().unwrap_or_default();
+    parse_checksum(&sums, asset).ok_or_else(|| format!("The yt-dlp checksums do not list {asset}"))
+}
+
+/// Finds `file_name` in a `sha256sum`-style listing (`<hash>  <name>`, or
+/// `<hash> *<name>` for binary mode).
+fn parse_checksum(sums: &str, file_name: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+        let name = name.trim_start().trim_start_matches('*');
+        (name == file_name).then(|| hash.to_ascii_lowercase())
+    })
+}
+
+fn sha256_file(path: &std::path::Path) -> Result<String, String> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Deletes `path` and fails when its SHA-256 is not `expected`, which is
+/// usually a download cut short by a flaky connection.
+async fn verify_checksum(path: &std::path::Path, expected: &str, tool: &str) -> Result<(), String> {
+    let hashed = path.to_path_buf();
+    let actual = tokio::task::spawn_blocking(move || sha256_file(&hashed))
+        .await
+        .map_err(|e| e.to_string())??;
+    if actual == expected {
+        return Ok(());
+    }
+    tokio::fs::remove_file(path).await.ok();
+    Err(format!(
+        "Downloaded {tool} failed its checksum check (expected SHA-256 {expected}, got {actual}). The download was probably incomplete; try again."
+    ))
+}
+
+async fn file_has_content(path: &std::path::Path) -> bool {
+    tokio::fs::metadata(path)
+        .await
+        .is_ok_and(|meta| meta.len() > 0)
+}
+
+/// Copies each of `binary_names` out of the zip at `zip_path` into
+/// `dest_dir`, in whichever folder of the archive it is.
+fn extract_binaries(
+    zip_path: &std::path::Path,
+    binary_names: &[&str],
+    dest_dir: &std::path::Path,
+) -> Result<(), String> {
+    let file = std::fs::File::open(zip_path).map_err(|e| e.to_string())?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+
+    let mut missing = binary_names.to_vec();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+        let name = entry.name().to_string();
+        let fname = std::path::Path::new(&name)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+
+        if let Some(at) = missing.iter().position(|binary| *binary == fname) {
+            let mut out = std::fs::File::create(dest_dir.join(fname)).map_err(|e| e.to_string())?;
+            std::io::copy(&mut entry, &mut out).map_err(|e| e.to_string())?;
+            missing.remove(at);
+        }
+    }
+    match missing.first() {
+        Some(binary) => Err(format!("{} not found in archive", binary)),
+        None => Ok(()),
+    }
+}
+
+fn extract_binaries_from_tar_xz(
+    archive_path: &std::path::Path,
+    binary_names: &[&str],
+    dest_dir: &std::path::Path,
+) -> Result<(), String> {
+    let file = std::fs::File::open(archive_path).map_err(|e| e.to_string())?;
+    let tar = xz2::read::XzDecoder::new(std::io::BufReader::new(file));
+    extract_binaries_from_tar(tar, binary_names, dest_dir)
+}
+
+/// Walks a tar stream and copies the first regular file called each of
+/// `binary_names`, in whichever folder it is, into `dest_dir`.
+fn extract_binaries_from_tar(
+    tar: impl std::io::Read,
+    binary_names: &[&str],
+    dest_dir: &std::path::Path,
+) -> Result<(), String> {
+    let mut missing = binary_names.to_vec();
+    let mut archive = tar::Archive::new(tar);
+    let entries = archive
+        .entries()
+        .map_err(|e| format!("Could not read archive: {e}"))?;
+    for entry in entries {
+        if missing.is_empty() {
+            break;
+        }
+        let mut entry = entry.map_err(|e| format!("Could not read archive: {e}"))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry
+            .path()
+            .map_err(|e| format!("Could not read archive: {e}"))?;
+        let Some(file_name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        if let Some(at) = missing.iter().position(|binary| *binary == file_name) {
+            let mut out =
+                std::fs::File::create(dest_dir.join(&file_name)).map_err(|e| e.to_string())?;
+            std::io::copy(&mut entry, &mut out).map_err(|e| e.to_string())?;
+            missing.remove(at);
+        }
+    }
+    match missing.first() {
+        Some(binary) => Err(format!("{} not found in archive", binary)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &std::path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Ok(meta) = std::fs::metadata(path) {
+        let mut perms = meta.permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(path, perms).ok();
+    }
+}
+#[cfg(not(unix))]
+fn make_executable(_path: &std::path::Path) {}
+
+/// Collects what the stdout reader learns about a job for its completion
+/// summary. Every stream of a multi-part download (e.g. video + audio)
+/// contributes its size.
+#[derive(Default)]
+struct TransferTracker {
+    output_file: Option<String>,
+    finished_bytes: u64,
+    current_bytes: u64,
+    /// Names of the subtitle files written, before conversion.
+    subtitle_files: Vec<String>,
+}
+
+impl TransferTracker {
+    fn observe(&mut self, line: &str) {
+        if let Some(path) = parse_output_file(line) {
+            if line.starts_with("[download] Destination:") {
+                self.finished_bytes += std::mem::take(&mut self.current_bytes);
+            }
+            self.output_file = Some(path.to_string());
+        }
+        if let Some(size) = parse_total_size(line) {
+            self.current_bytes = size;
+        }
+        if let Some(path) = line.strip_prefix("[info] Writing video subtitles to: ") {
+            if let Some(name) = std::path::Path::new(path.trim()).file_name() {
+                self.subtitle_files
+                    .push(name.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    /// Where the subtitles converted to `extension` end up: written into
+    /// the job's temp folder, converted, then moved into `output_dir`.
+    fn converted_subtitles(&self, output_dir: &std::path::Path, extension: &str) -> Vec<String> {
+        self.subtitle_files
+            .iter()
+            .map(|name| {
+                output_dir
+                    .join(name)
+                    .with_extension(extension)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    fn summary(&self, elapsed: std::time::Duration) -> DownloadSummary {
+        let total_bytes = self.finished_bytes + self.current_bytes;
+        let elapsed_secs = elapsed.as_secs_f64();
+        DownloadSummary {
+            output_file: self.output_file.clone(),
+            total_bytes,
+            elapsed_secs,
+            average_speed: if elapsed_secs > 0.0 {
+                total_bytes as f64 / elapsed_secs
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Returns the file a yt-dlp line says it is writing. Later lines (merge,
+/// audio extraction, remux) name the file that finally remains on disk.
+fn parse_output_file(line: &str) -> Option<&str> {
+    let path = if let Some(rest) = line.strip_prefix("[Merger] Merging formats into ") {
+        rest.trim().trim_matches('"')
+    } else if let Some(rest) = line.strip_prefix("[MoveFiles] Moving file ") {
+        // `"<temp path>" to "<final path>"`
+        rest.rsplit_once(" to ")?.1.trim().trim_matches('"')
+    } else if let Some(rest) = line
+        .strip_prefix("[download] ")
+        .and_then(|rest| rest.strip_suffix(" has already been downloaded"))
+    {
+        rest.trim()
+    } else if line.starts_with('[') {
+        line.split_once("Destination: ")?.1.trim()
+    } else {
+        return None;
+    };
+    (!path.is_empty()).then_some(path)
+}
+
+/// Payload of `download-file-resolved`: the file yt-dlp is writing, merging
+/// into or has finished, as an absolute path. Files of separately downloaded
+/// streams are reported before the merged one.
+#[derive(Clone, Serialize)]
+struct FileResolved {
+    path: String,
+}
+
+/// Where a file yt-dlp reports ends up: files in the job's temp folder are
+/// moved to the same place under `output_dir` once finished, and relative
+/// paths are relative to it.
+fn final_path(
+    path: &std::path::Path,
+    temp_dir: &std::path::Path,
+    output_dir: &std::path::Path,
+) -> PathBuf {
+    match path.strip_prefix(temp_dir) {
+        Ok(relative) => output_dir.join(relative),
+        Err(_) => output_dir.join(path),
+    }
+}
+
+/// Returns the image a thumbnail line refers to, as it ends up on disk.
+fn parse_thumbnail_path(line: &str) -> Option<String> {
+    if let Some(rest) = line.strip_prefix("[ThumbnailsConvertor] Converting thumbnail ") {
+        // `"<original>" to jpg`
+        let original = rest.rsplit_once(" to ")?.0.trim().trim_matches('"');
+        return Some(
+            std::path::Path::new(original)
+                .with_extension("jpg")
+                .to_string_lossy()
+                .into_owned(),
+        );
+    }
+    let (_, path) = line.split_once("thumbnail")?.1.split_once(" to: ")?;
+    line.starts_with("[info] Writing video thumbnail")
+        .then(|| path.trim().to_string())
+}
+
+/// Payload of job events whose data has no job id of its own
+/// (`download-started`, `download-complete`, `download-error`), so the
+/// frontend can tell concurrent downloads apart.
+#[derive(Clone, Serialize)]
+struct JobEvent<'a, T> {
+    job_id: &'a str,
+    #[serde(flatten)]
+    data: T,
+}
+
+impl<'a, T> JobEvent<'a, T> {
+    fn new(job_id: &'a str, data: T) -> Self {
+        Self { job_id, data }
+    }
+}
+
+/// Payload of `download-progress`.
+#[derive(Clone, Serialize)]
+struct JobProgress<'a> {
+    job_id: &'a str,
+    /// Progress of the current item over all its streams and the merge,
+    /// 0–1.
+    progress: f64,
+}
+
+/// Payload of `download-log`. `job_id` is `None` for output of commands that
+/// are not downloads, such as `download_thumbnail`.
+#[derive(Clone, Serialize)]
+struct LogLine<'a> {
+    job_id: Option<&'a str>,
+    line: &'a str,
+}
+
+fn emit_log(app: &AppHandle, job_id: Option<&str>, line: &str) {
+    app.emit("download-log", LogLine { job_id, line }).ok();
+    logs::write(app, job_id, line);
+}
+
+/// Payload of `playlist-progress`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct PlaylistProgress {
+    /// Position of the item among those being downloaded, from 1.
+    current_index: u32,
+    total: u32,
+    /// Read from the item's file name once yt-dlp has named it.
+    current_title: Option<String>,
+}
+
+/// Follows which playlist item yt-dlp is working on.
+#[derive(Default)]
+struct PlaylistTracker {
+    current: Option<PlaylistProgress>,
+}
+
+impl PlaylistTracker {
+    /// Returns the new progress when `line` starts an item or names its
+    /// file.
+    fn observe(&mut self, line: &str) -> Option<PlaylistProgress> {
+        if let Some((current_index, total)) = parse_playlist_item(line) {
+            self.current = Some(PlaylistProgress {
+                current_index,
+                total,
+                current_title: None,
+            });
+            return self.current.clone();
+        }
+        let current = self
+            .current
+            .as_mut()
+            .filter(|current| current.current_title.is_none())?;
+        if !line.starts_with("[download] ") {
+            return None;
+        }
+        current.current_title = Some(history::title_from_file(parse_output_file(line)?)?);
+        Some(current.clone())
+    }
+}
+
+/// Reads `[download] Downloading item 3 of 12` (`video` in older yt-dlp
+/// versions).
+fn parse_playlist_item(line: &str) -> Option<(u32, u32)> {
+    let rest = line.strip_prefix("[download] Downloading ")?;
+    let rest = rest
+        .strip_prefix("item ")
+        .or_else(|| rest.strip_prefix("video "))?;
+    let (index, total) = rest.trim().split_once(" of ")?;
+    Some((index.parse().ok()?, total.parse().ok()?))
+}
+
+/// Payload of `batch-item-started`.
+#[derive(Clone, Serialize)]
+struct BatchItemStarted {
+    job_id: String,
+    url: String,
+    /// Position of `url` in the batch, from 0.
+    index: usize,
+    total: usize,
+}
+
+/// Reads the URL from an extractor's first line, such as
+/// `[youtube] Extracting URL: https://www.youtube.com/watch?v=abc`.
+fn extracted_url(line: &str) -> Option<&str> {
+    let (_, url) = line.strip_prefix('[')?.split_once("] Extracting URL: ")?;
+    Some(url.trim())
+}
+
+/// Finds which batch URL an extractor line refers to. yt-dlp shortens URLs
+/// longer than 100 characters to their first 97 and last 20 characters
+/// joined by `...`.
+fn batch_index(url: &str, batch_urls: &[String]) -> Option<usize> {
+    batch_urls.iter().position(|batch_url| {
+        batch_url == url
+            || url.split_once("...").is_some_and(|(start, end)| {
+                batch_url.len() > 100 && batch_url.starts_with(start) && batch_url.ends_with(end)
+            })
+    })
+}
+
+/// Speed below which a YouTube download counts as throttled.
+const THROTTLE_SPEED: u64 = 100 * 1024;
+/// How long the speed has to stay below `THROTTLE_SPEED` before switching.
+const THROTTLE_WINDOW: std::time::Duration = std::time::Duration::from_secs(45);
+
+#[derive(Clone, Serialize)]
+struct ThrottleDetected {
+    job_id: String,
+    /// Bytes per second when the throttling was detected.
+    speed: u64,
+}
+
+/// Tracks how long a download has been crawling. A single fast sample
+/// resets it, since short dips are normal between fragments.
+#[derive(Default)]
+struct ThrottleDetector {
+    slow_since: Option<std::time::Instant>,
+}
+
+impl ThrottleDetector {
+    /// Returns true once the speed has stayed low for `THROTTLE_WINDOW`.
+    fn observe(&mut self, speed: u64, now: std::time::Instant) -> bool {
+        if speed >= THROTTLE_SPEED {
+            self.slow_since = None;
+            return false;
+        }
+        let slow_since = *self.slow_since.get_or_insert(now);
+        now.duration_since(slow_since) >= THROTTLE_WINDOW
+    }
+}
+
+/// Reads the transfer speed in bytes per second from a progress line such
+/// as `[download]  42.0% of ~ 120.50MiB at 2.00MiB/s ETA 00:30`.
+fn parse_speed(line: &str) -> Option<u64> {
+    let rest = line.strip_prefix("[download]")?;
+    let (_, after_at) = rest.split_once(" at ")?;
+    parse_size(after_at.split_whitespace().next()?.strip_suffix("/s")?)
+}
+
+/// Reads the remaining time from a progress line such as
+/// `[download]  42.0% of ~ 120.50MiB at 2.00MiB/s ETA 00:30`; longer ones
+/// read `ETA 1:02:03`.
+fn parse_eta(line: &str) -> Option<u64> {
+    let rest = line.strip_prefix("[download]")?;
+    let (_, eta) = rest.split_once(" ETA ")?;
+    eta.split_whitespace()
+        .next()?
+        .split(':')
+        .try_fold(0, |secs, part| Some(secs * 60 + part.parse::<u64>().ok()?))
+}
+
+/// Reads the stream size from a progress line such as
+/// `[download]  42.0% of ~ 120.50MiB at 2.00MiB/s ETA 00:30`.
+fn parse_total_size(line: &str) -> Option<u64> {
+    let rest = line.strip_prefix("[download]")?;
+    let (_, after_of) = rest.split_once("% of ")?;
+    let size = after_of.trim_start().trim_start_matches('~').trim_start();
+    parse_size(size.split_whitespace().next()?)
+}
+
+fn parse_size(value: &str) -> Option<u64> {
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let number = value[..unit_start].parse::<f64>().ok()?;
+    let multiplier = match &value[unit_start..] {
+        "B" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        "KB" | "kB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        _ => return None,
+    };
+    Some((number * multiplier).round() as u64)
+}
+
+/// Everything a yt-dlp progress line tells about the current stream; sent
+/// as `download-stats`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct DownloadProgress {
+    percent: f64,
+    speed_bytes_per_sec: Option<u64>,
+    eta_seconds: Option<u64>,
+    /// Worked out from `percent` and `total_bytes`.
+    downloaded_bytes: Option<u64>,
+    /// yt-dlp's estimate when the line reads `of ~`.
+    total_bytes: Option<u64>,
+}
+
+/// Reads a progress line such as
+/// `[download]  42.0% of ~ 120.50MiB at 2.00MiB/s ETA 00:30`.
+fn parse_progress_line(line: &str) -> Option<DownloadProgress> {
+    if !line.starts_with("[download]") {
+        return None;
+    }
+    let percent = parse_progress(line)?;
+    let total_bytes = parse_total_size(line);
+    Some(DownloadProgress {
+        percent,
+        speed_bytes_per_sec: parse_speed(line),
+        eta_seconds: parse_eta(line),
+        downloaded_bytes: total_bytes.map(|total| (total as f64 * percent / 100.0).round() as u64),
+        total_bytes,
+    })
+}
+
+/// Reads the percentage of a progress line such as
+/// `[download]  42.0% of ~ 120.50MiB at 2.00MiB/s ETA 00:30 (frag 3/120)`.
+/// Only the number leading the line counts, so file names containing `%`
+/// and other tools' output are ignored.
+fn parse_progress(line: &str) -> Option<f64> {
+    let rest = line.strip_prefix("[download]")?.trim_start();
+    let (number, _) = rest.split_once('%')?;
+    let percent = number.parse::<f64>().ok()?;
+    percent.is_finite().then(|| percent.clamp(0.0, 100.0))
+}
+
+/// Share of the overall progress taken by downloading when separate
+/// streams are merged afterwards; the merge takes the rest.
+const STREAMS_SHARE: f64 = 90.0;
+
+/// Turns the per-stream percentages yt-dlp prints into one figure for the
+/// current item, so a video+audio download does not run from 0 to 100%
+/// twice. With two streams the video takes 0–45%, the audio 45–90% and the
+/// merge 90–100%.
+#[derive(Default)]
+struct OverallProgress {
+    /// Streams of the current item, from its `Downloading 1 format(s)` line.
+    streams: usize,
+    /// `[download] Destination` lines seen for the current item.
+    started: usize,
+}
+
+impl OverallProgress {
+    /// Overall percentage, 0–100, when `line` changes it.
+    fn observe(&mut self, line: &str) -> Option<f64> {
+        if let Some((_, formats)) = line
+            .strip_prefix("[info] ")
+            .and_then(|rest| rest.split_once(" format(s): "))
+        {
+            self.streams = formats.split('+').count();
+            self.started = 0;
+            return None;
+        }
+        if line.starts_with("[download] Destination:") {
+            self.started += 1;
+            return None;
+        }
+        if line.starts_with("[Merger]") {
+            return (self.streams > 1).then_some(STREAMS_SHARE);
+        }
+        let percent = parse_progress(line)?;
+        if self.streams <= 1 {
+            return Some(percent);
+        }
+        let share = STREAMS_SHARE / self.streams as f64;
+        let done = self.started.clamp(1, self.streams) - 1;
+        Some(done as f64 * share + percent * share / 100.0)
+    }
+}
+
+// The app entry point stays at the end of the file, after the tests.
+#[allow(clippy::items_after_test_module)]
+#[cfg(test)]
+mod tests {
+    use super::{
+        audio_extraction_args, bandwidth_share, batch_file_urls, batch_index, chapter_sections,
+        chapters, check_output_template, classify_failure, clip_section, cookie_check_failure,
+        cover_args, dated_folder, duration_match_filter, extract_binaries_from_tar, extracted_url,
+        files_size, final_path, find_partial_files, format_preference_args, group_formats,
+        has_room_for, is_codec_incompatibility, is_direct_instagram_story_url, is_stale_temp_dir,
+        job_rate_limit, job_temp_dir, likely_video_id, lyrics_audio_file, output_template,
+        parse_checksum, parse_encoders, parse_eta, parse_extractors, parse_ffmpeg_duration,
+        parse_media_info, parse_output_file, parse_progress, parse_progress_line, parse_speed,
+        parse_thumbnail_path, parse_timestamp, playlist_entries, playlist_items_range,
+        playlist_manifest, quality_height, quality_sort, quick_download_params, quick_preset,
+        sha256_file, sponsorblock_categories, starts_like_html, subtitle_tracks, unavailable_item,
+        validate_url, without_ffmpeg, CookieBrowser, DownloadKind, DownloadOptions,
+        DownloadProgress, EntryStub, ErrorKind, JobPhase, JobSnapshot, MediaInfo, OverallProgress,
+        PlaylistProgress, PlaylistTracker, QuickPreset, SkippedItem, SponsorBlockResult,
+        SubtitleTrack, ThrottleDetector, TransferTracker, VideoInfo, LOW_DISK_ABORT_BYTES,
+    };
+
+    #[test]
+    fn reads_urls_from_batch_files() {
+        let text = "# talks\nhttps://example.com/a\n\n  https://example.com/b  \n";
+        assert_eq!(
+            batch_file_urls(text).unwrap(),
+            ["https://example.com/a", "https://example.com/b"]
+        );
+        assert!(batch_file_urls("https://example.com/a\nnot a url")
+            .unwrap_err()
+            .starts_with("Line 2:"));
+    }
+
+    #[test]
+    fn accepts_only_web_urls() {
+        assert!(validate_url(" https://www.youtube.com/watch?v=dQw4w9WgXcQ ").is_ok());
+        assert!(validate_url("http://example.com/clip").is_ok());
+        assert!(validate_url("   ").is_err());
+        assert!(validate_url("youtube.com/watch?v=dQw4w9WgXcQ").is_err());
+        assert!(validate_url("ftp://example.com/clip.mp4").is_err());
+        assert!(validate_url("file:///etc/passwd").is_err());
+        assert!(validate_url("--exec=touch /tmp/x").is_err());
+    }
+
+    #[test]
+    fn recognizes_direct_instagram_story_urls() {
+        assert!(is_direct_instagram_story_url(
+            "https://www.instagram.com/stories/account/3924911627980093004/"
+        ));
+        assert!(is_direct_instagram_story_url(
+            "https://instagram.com/stories/account/3924911627980093004/?utm_source=test"
+        ));
+    }
+
+    #[test]
+    fn leaves_story_feeds_and_other_urls_as_playlists() {
+        assert!(!is_direct_instagram_story_url(
+            "https://www.instagram.com/stories/account/"
+        ));
+        assert!(!is_direct_instagram_story_url(
+            "https://www.instagram.com/p/example/"
+        ));
+    }
+
+    #[test]
+    fn detects_html_error_pages_saved_as_binaries() {
+        assert!(starts_like_html(b"<!DOCTYPE html><html><body>Rate limit"));
+        assert!(starts_like_html(b"\xEF\xBB\xBF\n  <HTML lang=\"en\">"));
+        assert!(!starts_like_html(b"\x7fELF\x02\x01\x01"));
+        assert!(!starts_like_html(b"PK\x03\x04"));
+        assert!(!starts_like_html(b""));
+    }
+
+    #[test]
+    fn summarizes_separate_video_and_audio_streams() {
+        let mut tracker = TransferTracker::default();
+        for line in [
+            "[download] Destination: /out/Clip [abc] [1080p].f137.mp4",
+            "[download]  50.0% of   10.00MiB at    2.00MiB/s ETA 00:02",
+            "[download] 100% of   10.00MiB in 00:00:04 at 2.50MiB/s",
+            "[download] Destination: /out/Clip [abc] [1080p].f140.m4a",
+            "[download] 100% of ~   2.00MiB in 00:00:01 at 2.00MiB/s",
+            "[Merger] Merging formats into \"/out/.tmp/Clip [abc] [1080p].mp4\"",
+            "[MoveFiles] Moving file \"/out/.tmp/Clip [abc] [1080p].mp4\" to \"/out/Clip [abc] [1080p].mp4\"",
+        ] {
+            tracker.observe(line);
+        }
+        let summary = tracker.summary(std::time::Duration::from_secs(4));
+        assert_eq!(
+            summary.output_file.as_deref(),
+            Some("/out/Clip [abc] [1080p].mp4")
+        );
+        assert_eq!(summary.total_bytes, 12 * 1024 * 1024);
+        assert_eq!(summary.average_speed, 3.0 * 1024.0 * 1024.0);
+    }
+
+    fn tar_archive(entries: &[(&str, &[u8])], gnu: bool) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in entries {
+            let mut header = if gnu {
+                tar::Header::new_gnu()
+            } else {
+                tar::Header::new_ustar()
+            };
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn finds_the_published_checksum_for_a_build() {
+        let sums = "\
+0123abcd  yt-dlp
+4567EF01 *yt-dlp_linux
+89ab  yt-dlp_linux_aarch64
+";
+        assert_eq!(
+            parse_checksum(sums, "yt-dlp_linux").as_deref(),
+            Some("4567ef01")
+        );
+        assert_eq!(parse_checksum(sums, "yt-dlp").as_deref(), Some("0123abcd"));
+        assert_eq!(parse_checksum(sums, "yt-dlp.exe"), None);
+
+        let path = std::env::temp_dir().join(format!("sha-test-{}", std::process::id()));
+        std::fs::write(&path, "abc").unwrap();
+        assert_eq!(
+            sha256_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn finds_ffmpeg_inside_the_versioned_tar_folder() {
+        let tar = tar_archive(
+            &[
+                ("ffmpeg-7.0.2-amd64-static/readme.txt", b"static build"),
+                ("ffmpeg-7.0.2-amd64-static/ffprobe", b"probe"),
+                ("ffmpeg-7.0.2-amd64-static/ffmpeg", b"ffmpeg binary"),
+            ],
+            false,
+        );
+
+        let dest = std::env::temp_dir().join(format!("tar-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dest).unwrap();
+        extract_binaries_from_tar(tar.as_slice(), &["ffmpeg", "ffprobe"], &dest).unwrap();
+        assert_eq!(
+            std::fs::read(dest.join("ffmpeg")).unwrap(),
+            b"ffmpeg binary"
+        );
+        assert_eq!(std::fs::read(dest.join("ffprobe")).unwrap(), b"probe");
+
+        assert!(extract_binaries_from_tar(tar.as_slice(), &["ffmpeg", "ffplay"], &dest).is_err());
+        std::fs::remove_dir_all(&dest).ok();
+    }
+
+    #[test]
+    fn finds_binaries_behind_long_tar_paths() {
+        // Past 100 bytes, ustar splits the path into a prefix and GNU tar
+        // stores it in an extra entry.
+        let folder = format!("ffmpeg-n7.1-latest-linux64-gpl/{}", "nested/".repeat(12));
+        let ffmpeg = format!("{folder}ffmpeg");
+        let ffprobe = format!("{folder}ffprobe");
+        let dest = std::env::temp_dir().join(format!("tar-long-test-{}", std::process::id()));
+        for gnu in [false, true] {
+            let tar = tar_archive(
+                &[
+                    (ffmpeg.as_str(), b"ffmpeg binary"),
+                    (ffprobe.as_str(), b"probe"),
+                ],
+                gnu,
+            );
+            std::fs::create_dir_all(&dest).unwrap();
+            extract_binaries_from_tar(tar.as_slice(), &["ffmpeg", "ffprobe"], &dest).unwrap();
+            assert_eq!(
+                std::fs::read(dest.join("ffmpeg")).unwrap(),
+                b"ffmpeg binary"
+            );
+            assert_eq!(std::fs::read(dest.join("ffprobe")).unwrap(), b"probe");
+            std::fs::remove_dir_all(&dest).ok();
+        }
+    }
+
+    #[test]
+    fn reads_progress_only_from_download_lines() {
+        assert_eq!(
+            parse_progress("[download]  12.5% of ~ 50.00MiB at 1.00MiB/s ETA 00:30 (frag 3/120)"),
+            Some(12.5)
+        );
+        assert_eq!(
+            parse_progress("[download] 100% of   50.00MiB in 00:00:03 at 15.20MiB/s"),
+            Some(100.0)
+        );
+        assert_eq!(
+            parse_progress("[download] Destination: /out/100% Pure [abc].f137.mp4"),
+            None
+        );
+        assert_eq!(
+            parse_progress("[Merger] Merging formats into \"/out/50% off [abc].mp4\""),
+            None
+        );
+        assert_eq!(parse_progress("[ffmpeg] Fixing 25% of the stream"), None);
+    }
+
+    #[test]
+    fn combines_video_audio_and_merge_progress() {
+        let log = r#"[youtube] Extracting URL: https://www.youtube.com/watch?v=dQw4w9WgXcQ
+[youtube] dQw4w9WgXcQ: Downloading webpage
+[youtube] dQw4w9WgXcQ: Downloading tv client config
+[youtube] dQw4w9WgXcQ: Downloading ios player API JSON
+[youtube] dQw4w9WgXcQ: Downloading m3u8 information
+[info] dQw4w9WgXcQ: Downloading 1 format(s): 137+140
+[download] Destination: /out/.video-downloader-tmp-7/Rick Astley - Never Gonna Give You Up (Official Music Video) [dQw4w9WgXcQ] [1080p].f137.mp4
+[download]   0.0% of   78.57MiB at  Unknown B/s ETA Unknown
+[download]  50.0% of   78.57MiB at    9.87MiB/s ETA 00:04
+[download] 100% of   78.57MiB in 00:00:08 at 9.71MiB/s
+[download] Destination: /out/.video-downloader-tmp-7/Rick Astley - Never Gonna Give You Up (Official Music Video) [dQw4w9WgXcQ] [1080p].f140.m4a
+[download]   0.0% of    3.27MiB at  Unknown B/s ETA Unknown
+[download]  50.0% of    3.27MiB at    8.41MiB/s ETA 00:00
+[download] 100% of    3.27MiB in 00:00:00 at 8.01MiB/s
+[Merger] Merging formats into "/out/.video-downloader-tmp-7/Rick Astley - Never Gonna Give You Up (Official Music Video) [dQw4w9WgXcQ] [1080p].mp4"
+Deleting original file /out/.video-downloader-tmp-7/Rick Astley - Never Gonna Give You Up (Official Music Video) [dQw4w9WgXcQ] [1080p].f140.m4a (pass -k to keep)
+Deleting original file /out/.video-downloader-tmp-7/Rick Astley - Never Gonna Give You Up (Official Music Video) [dQw4w9WgXcQ] [1080p].f137.mp4 (pass -k to keep)"#;
+        let mut overall = OverallProgress::default();
+        let reported: Vec<f64> = log
+            .lines()
+            .filter_map(|line| overall.observe(line))
+            .collect();
+        assert_eq!(reported, [0.0, 22.5, 45.0, 45.0, 67.5, 90.0, 90.0]);
+
+        let mut single = OverallProgress::default();
+        single.observe("[info] abc: Downloading 1 format(s): 22");
+        single.observe("[download] Destination: /out/Clip [abc].mp4");
+        assert_eq!(
+            single.observe("[download]  42.0% of 10.00MiB at 1.00MiB/s ETA 00:06"),
+            Some(42.0)
+        );
+        assert_eq!(single.observe("[Merger] Merging formats into \"x\""), None);
+    }
+
+    #[test]
+    fn reads_speed_and_eta_from_progress_lines() {
+        assert_eq!(
+            parse_progress_line("[download]  50.0% of   10.00MiB at    2.00MiB/s ETA 00:37"),
+            Some(DownloadProgress {
+                percent: 50.0,
+                speed_bytes_per_sec: Some(2 * 1024 * 1024),
+                eta_seconds: Some(37),
+                downloaded_bytes: Some(5 * 1024 * 1024),
+                total_bytes: Some(10 * 1024 * 1024),
+            })
+        );
+        let unknown =
+            parse_progress_line("[download]   0.0% of 10.00MiB at Unknown B/s ETA Unknown")
+                .unwrap();
+        assert_eq!(
+            (unknown.speed_bytes_per_sec, unknown.eta_seconds),
+            (None, None)
+        );
+        assert_eq!(parse_progress_line("[ExtractAudio] 100% done"), None);
+    }
+
+    #[test]
+    fn tracks_the_phase_of_a_running_job() {
+        let mut snapshot = JobSnapshot::new("job-1".to_string(), "https://example.com".to_string());
+        snapshot.observe("[youtube] abc: Downloading webpage");
+        assert_eq!(snapshot.phase, JobPhase::Preparing);
+
+        snapshot.observe("[download]  42.0% of ~ 120.50MiB at 2.00MiB/s ETA 00:30");
+        assert_eq!(snapshot.phase, JobPhase::Downloading);
+        assert_eq!(snapshot.percent, 42.0);
+        assert_eq!(snapshot.speed, Some(2 * 1024 * 1024));
+        assert_eq!(snapshot.eta_secs, Some(30));
+        assert_eq!(
+            parse_eta("[download]   3.0% of 2.00GiB at 1.00MiB/s ETA 1:02:03"),
+            Some(3723)
+        );
+        assert_eq!(
+            parse_eta("[download]   0.0% of 10.00MiB at Unknown B/s ETA Unknown"),
+            None
+        );
+
+        snapshot.observe("[Merger] Merging formats into \"/out/Clip [abc].mp4\"");
+        assert_eq!(
+            (snapshot.phase, snapshot.speed),
+            (JobPhase::Processing, None)
+        );
+    }
+
+    #[test]
+    fn resolves_files_in_the_temp_folder_to_the_output_folder() {
+        let output_dir = std::path::Path::new("/out");
+        let temp_dir = job_temp_dir(output_dir, "7");
+        let in_temp = temp_dir.join("Clip [abc] [1080p].mp4");
+        assert_eq!(
+            final_path(&in_temp, &temp_dir, output_dir),
+            std::path::Path::new("/out/Clip [abc] [1080p].mp4")
+        );
+        assert_eq!(
+            final_path(
+                std::path::Path::new("/out/Song [abc].mp3"),
+                &temp_dir,
+                output_dir
+            ),
+            std::path::Path::new("/out/Song [abc].mp3")
+        );
+        assert_eq!(
+            final_path(
+                std::path::Path::new("Clip [abc].mp4"),
+                &temp_dir,
+                output_dir
+            ),
+            std::path::Path::new("/out/Clip [abc].mp4")
+        );
+    }
+
+    #[test]
+    fn finds_output_file_for_already_downloaded_items() {
+        assert_eq!(
+            parse_output_file("[download] /out/Clip [abc].mp4 has already been downloaded"),
+            Some("/out/Clip [abc].mp4")
+        );
+        assert_eq!(
+            parse_output_file("[youtube] abc: Downloading webpage"),
+            None
+        );
+    }
+
+    #[test]
+    fn validates_upload_date_range() {
+        let options = DownloadOptions {
+            date_after: Some("20240101".to_string()),
+            date_before: Some(" 20240301 ".to_string()),
+            ..Default::default()
+        };
+        let args = options.yt_dlp_args().unwrap();
+        assert!(args.ends_with(&[
+            "--dateafter".to_string(),
+            "20240101".to_string(),
+            "--datebefore".to_string(),
+            "20240301".to_string(),
+        ]));
+
+        for (after, before) in [
+            ("2024-01-01", "20240301"),
+            ("20241301", "20240301"),
+            ("20240231", "20240301"),
+            ("20230229", "20240301"),
+        ] {
+            let options = DownloadOptions {
+                date_after: Some(after.to_string()),
+                date_before: Some(before.to_string()),
+                ..Default::default()
+            };
+            assert!(options.yt_dlp_args().is_err());
+        }
+        let reversed = DownloadOptions {
+            date_after: Some("20240302".to_string()),
+            date_before: Some("20240301".to_string()),
+            ..Default::default()
+        };
+        assert!(reversed.yt_dlp_args().is_err());
+    }
+
+    #[test]
+    fn separates_rejected_cookies_from_other_failures() {
+        assert_eq!(
+            cookie_check_failure(
+                "WARNING: [youtube] unavailable\nERROR: [youtube] abc: Sign in to confirm you're not a bot"
+            ),
+            Ok(false)
+        );
+        assert!(cookie_check_failure(
+            "ERROR: [generic] Unable to download webpage: <urlopen error [Errno 8] nodename nor servname provided> (caused by TransportError)"
+        )
+        .unwrap_err()
+        .starts_with("Network error"));
+        assert!(
+            cookie_check_failure("ERROR: Could not copy Chrome cookie database. See  https://github.com/yt-dlp/yt-dlp/issues/7271")
+                .unwrap_err()
+                .starts_with("Could not read browser cookies")
+        );
+    }
+
+    #[test]
+    fn recognizes_membership_failures() {
+        assert_eq!(
+            classify_failure(
+                "ERROR: [youtube] abc: Join this channel to get access to members-only content like this video, and other exclusive perks."
+            ),
+            ErrorKind::MembershipRequired
+        );
+        assert_eq!(
+            classify_failure("ERROR: [youtube] abc: This video requires payment to watch."),
+            ErrorKind::MembershipRequired
+        );
+        assert_eq!(
+            classify_failure("WARNING: members-only formats skipped\nERROR: unable to download"),
+            ErrorKind::Unknown
+        );
+    }
+
+    #[test]
+    fn follows_thumbnail_conversion_to_jpg() {
+        let lines = [
+            "[info] Writing video thumbnail original to: /out/Clip [abc].webp",
+            "[ThumbnailsConvertor] Converting thumbnail \"/out/Clip [abc].webp\" to jpg",
+        ];
+        assert_eq!(
+            lines
+                .iter()
+                .rev()
+                .find_map(|line| parse_thumbnail_path(line)),
+            Some("/out/Clip [abc].jpg".to_string())
+        );
+        assert_eq!(
+            parse_thumbnail_path("[info] There are no video thumbnails to download"),
+            None
+        );
+    }
+
+    #[test]
+    fn builds_duration_filter() {
+        assert_eq!(duration_match_filter(None, None), Ok(None));
+        assert_eq!(
+            duration_match_filter(Some(61), None),
+            Ok(Some("duration >=? 61".to_string()))
+        );
+        assert_eq!(
+            duration_match_filter(Some(600), Some(7200)),
+            Ok(Some("duration >=? 600 & duration <=? 7200".to_string()))
+        );
+        assert!(duration_match_filter(Some(600), Some(600)).is_err());
+    }
+
+    #[test]
+    fn names_each_chosen_chapter_differently() {
+        let template = |chapters: &[&str], custom: Option<&str>| {
+            let options = DownloadOptions {
+                download_chapters: chapters.iter().map(|c| c.to_string()).collect(),
+                output_template: custom.map(str::to_string),
+                ..Default::default()
+            };
+            output_template(&options, false)
+        };
+        assert_eq!(
+            template(&["Intro"], None),
+            "%(title)s [%(id)s] [%(height)sp].%(ext)s"
+        );
+        assert_eq!(
+            template(&["Intro", "Song (2)"], None),
+            "%(title)s - %(section_number)s %(section_title)s [%(id)s] [%(height)sp].%(ext)s"
+        );
+        assert_eq!(
+            template(&["Intro", "Outro"], Some("%(uploader)s/%(id)s.%(ext)s")),
+            "%(uploader)s/%(id)s - %(section_number)s %(section_title)s.%(ext)s"
+        );
+        assert_eq!(
+            template(&["Intro", "Outro"], Some("%(section_title)s.%(ext)s")),
+            "%(section_title)s.%(ext)s"
+        );
+    }
+
+    #[test]
+    fn keeps_output_templates_inside_the_output_folder() {
+        assert!(check_output_template("%(title)s [%(id)s].%(ext)s").is_ok());
+        assert!(check_output_template("%(uploader)s/%(title)s.%(ext)s").is_ok());
+        assert!(check_output_template("/tmp/%(title)s.%(ext)s").is_err());
+        assert!(check_output_template("C:%(title)s.%(ext)s").is_err());
+        assert!(check_output_template("../%(title)s.%(ext)s").is_err());
+        assert!(check_output_template("%(uploader)s\\..\\%(title)s.%(ext)s").is_err());
+        assert!(check_output_template("%(title)s").is_err());
+    }
+
+    #[test]
+    fn builds_clip_sections_from_start_and_end_times() {
+        assert_eq!(clip_section(None, None), Ok(None));
+        assert_eq!(
+            clip_section(Some("1:02:00"), Some("1:04:00")),
+            Ok(Some("*1:02:00-1:04:00".to_string()))
+        );
+        assert_eq!(
+            clip_section(Some("90"), None),
+            Ok(Some("*90-inf".to_string()))
+        );
+        assert_eq!(
+            clip_section(None, Some("02:30")),
+            Ok(Some("*0-02:30".to_string()))
+        );
+        assert!(clip_section(Some("05:00"), Some("04:59")).is_err());
+        assert!(clip_section(None, Some("1:2:3:4")).is_err());
+        assert!(clip_section(Some("abc"), None).is_err());
+    }
+
+    #[test]
+    fn reads_ffmpeg_input_duration_and_codec_errors() {
+        let stderr = "Input #0, matroska,webm, from 'clip.mkv':\n  Duration: 01:02:03.50, start: 0.000000, bitrate: 1200 kb/s\n";
+        assert_eq!(parse_ffmpeg_duration(stderr), Some(3723.5));
+        assert_eq!(parse_ffmpeg_duration("no input"), None);
+
+        assert!(is_codec_incompatibility(
+            "[mp4 @ 0x1] Could not find tag for codec vp9 in stream #0, codec not currently supported in container"
+        ));
+        assert!(!is_codec_incompatibility(
+            "clip.mkv: No such file or directory"
+        ));
+    }
+
+    #[test]
+    fn validates_sponsorblock_categories() {
+        let categories = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sponsorblock_categories(&categories(&["sponsor", "Music_Offtopic"]), false),
+            Ok("sponsor,music_offtopic".to_string())
+        );
+        assert!(sponsorblock_categories(&categories(&["poi_highlight"]), true).is_ok());
+        assert!(sponsorblock_categories(&categories(&["poi_highlight"]), false).is_err());
+        assert!(sponsorblock_categories(&categories(&["ads"]), true).is_err());
+    }
+
+    #[test]
+    fn counts_sponsorblock_segments_and_cuts() {
+        let mut result = SponsorBlockResult::default();
+        for line in [
+            "[SponsorBlock] Fetching SponsorBlock segments",
+            "[SponsorBlock] Found 3 segments in the SponsorBlock database",
+            "[ModifyChapters] Removing chapters from Clip [abc].mp4",
+            "[SponsorBlock] No matching segments were found in the SponsorBlock database",
+        ] {
+            result.observe(line);
+        }
+        assert_eq!(result.segments_found, 3);
+        assert_eq!(result.files_cut, 1);
+    }
+
+    #[test]
+    fn accepts_only_supported_cookie_browsers() {
+        let parse = |value: &str| serde_json::from_value::<CookieBrowser>(value.into());
+        assert_eq!(parse("none").unwrap(), CookieBrowser::None);
+        assert_eq!(parse("").unwrap(), CookieBrowser::None);
+        assert_eq!(parse("vivaldi").unwrap().yt_dlp_keyword(), Some("vivaldi"));
+        assert!(parse("netscape").is_err());
+    }
+
+    #[test]
+    fn groups_formats_and_recommends_merging_separate_streams() {
+        let info = serde_json::json!({
+            "formats": [
+                { "format_id": "sb0", "ext": "mhtml", "vcodec": "none", "acodec": "none" },
+                { "format_id": "140", "ext": "m4a", "vcodec": "none", "acodec": "mp4a.40.2", "tbr": 129.5 },
+                { "format_id": "251", "ext": "webm", "vcodec": "none", "acodec": "opus", "tbr": 135.0 },
+                { "format_id": "18", "ext": "mp4", "height": 360, "vcodec": "avc1", "acodec": "mp4a.40.2" },
+                { "format_id": "136", "ext": "mp4", "height": 720, "fps": 30.0, "vcodec": "avc1", "acodec": "none" },
+                { "format_id": "137", "ext": "mp4", "height": 1080, "fps": 30.0, "vcodec": "avc1", "acodec": "none" }
+            ]
+        });
+        let groups = group_formats(&info);
+        let ids = |formats: &[super::FormatOption]| {
+            formats
+                .iter()
+                .map(|format| format.format_id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&groups.video_only), ["137", "136"]);
+        assert_eq!(ids(&groups.audio_only), ["251", "140"]);
+        assert_eq!(ids(&groups.combined), ["18"]);
+        assert_eq!(groups.recommended.as_deref(), Some("137+251"));
+    }
+
+    #[test]
+    fn reads_video_info_for_a_preview() {
+        let info = serde_json::json!({
+            "title": "Clip",
+            "duration": 212.5,
+            "thumbnail": "https://i.ytimg.com/vi/abc/maxresdefault.jpg",
+            "channel": "Someone",
+            "formats": [
+                { "format_id": "140", "ext": "m4a", "resolution": "audio only", "vcodec": "none", "acodec": "mp4a.40.2" },
+                { "format_id": "137", "ext": "mp4", "resolution": "1920x1080", "height": 1080, "vcodec": "avc1", "acodec": "none" }
+            ]
+        });
+        let video = VideoInfo::from_info(&info);
+        assert_eq!(video.title.as_deref(), Some("Clip"));
+        assert_eq!(video.duration_seconds, Some(212.5));
+        assert_eq!(video.uploader.as_deref(), Some("Someone"));
+        assert_eq!(video.formats.len(), 2);
+        assert_eq!(video.formats[1].resolution.as_deref(), Some("1920x1080"));
+    }
+
+    #[test]
+    fn splits_the_bandwidth_limit_between_downloads() {
+        assert_eq!(bandwidth_share(None, 3), None);
+        assert_eq!(bandwidth_share(Some(3000), 3), Some(1000));
+        assert_eq!(bandwidth_share(Some(3000), 0), Some(3000));
+        assert_eq!(bandwidth_share(Some(2), 3), Some(1));
+
+        let own = DownloadOptions {
+            rate_limit: Some("1K".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(job_rate_limit(&own, Some(4096)), Some(1024));
+        assert_eq!(job_rate_limit(&own, Some(512)), Some(512));
+        assert_eq!(job_rate_limit(&own, None), Some(1024));
+        assert_eq!(job_rate_limit(&DownloadOptions::default(), None), None);
+    }
+
+    #[test]
+    fn counts_partial_bytes_already_downloaded() {
+        let dir = std::env::temp_dir().join(format!("files-size-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("clip [abc].mp4.part"), [0; 300]).unwrap();
+        std::fs::write(dir.join("clip [abc].mp4.ytdl"), [0; 20]).unwrap();
+        std::fs::write(dir.join("nested").join("other"), [0; 1000]).unwrap();
+        assert_eq!(files_size(&dir), 320);
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(files_size(&dir), 0);
+    }
+
+    #[test]
+    fn sweeps_only_old_job_temp_folders() {
+        let week = std::time::Duration::from_secs(7 * 24 * 60 * 60);
+        let hour = std::time::Duration::from_secs(60 * 60);
+        assert!(is_stale_temp_dir(".video-downloader-tmp-job-1-1", week));
+        assert!(!is_stale_temp_dir(".video-downloader-tmp-job-1-1", hour));
+        assert!(!is_stale_temp_dir("2024-01-01", week));
+    }
+
+    #[test]
+    fn leaves_partial_files_of_busy_jobs_alone() {
+        let out = std::env::temp_dir().join(format!("partials-test-{}", std::process::id()));
+        let part = "Clip [abc].mp4.part";
+        for dir in [
+            "",
+            ".video-downloader-tmp-old",
+            ".video-downloader-tmp-running",
+        ] {
+            std::fs::create_dir_all(out.join(dir)).unwrap();
+            std::fs::write(out.join(dir).join(part), b"data").unwrap();
+        }
+        let mut found = find_partial_files(&out, "abc", &["running".to_string()])
+            .into_iter()
+            .map(|(path, bytes)| (path.strip_prefix(&out).unwrap().to_path_buf(), bytes))
+            .collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                (
+                    std::path::PathBuf::from(".video-downloader-tmp-old").join(part),
+                    4
+                ),
+                (std::path::PathBuf::from(part), 4),
+            ]
+        );
+        assert!(find_partial_files(&out, "xyz", &[]).is_empty());
+        std::fs::remove_dir_all(&out).ok();
+    }
+
+    #[test]
+    fn finds_the_fragments_of_a_partial_download() {
+        let out = std::env::temp_dir().join(format!("fragments-test-{}", std::process::id()));
+        std::fs::create_dir_all(&out).unwrap();
+        for file in [
+            "Clip [abc].f137.mp4.part",
+            "Clip [abc].f137.mp4.part-Frag1",
+            "Clip [abc].f137.mp4.part-Frag2",
+            "Clip [abc].f137.mp4.ytdl",
+            "Party.part 2 [abc].mp4",
+        ] {
+            std::fs::write(out.join(file), b"data").unwrap();
+        }
+        let mut found = find_partial_files(&out, "abc", &[])
+            .into_iter()
+            .map(|(path, _)| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                "Clip [abc].f137.mp4.part",
+                "Clip [abc].f137.mp4.part-Frag1",
+                "Clip [abc].f137.mp4.part-Frag2",
+                "Clip [abc].f137.mp4.ytdl",
+            ]
+        );
+        std::fs::remove_dir_all(&out).ok();
+    }
+
+    #[test]
+    fn labels_repeated_chapter_titles_by_index() {
+        let info = serde_json::json!({
+            "chapters": [
+                { "title": "Intro", "start_time": 0.0, "end_time": 30.0 },
+                { "title": "Song", "start_time": 30.0, "end_time": 95.5 },
+                { "title": "Song", "start_time": 95.5, "end_time": 160.0 },
+                { "title": "", "start_time": 160.0 }
+            ]
+        });
+        let found = chapters(&info);
+        let labels = found
+            .iter()
+            .map(|chapter| chapter.label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["Intro", "Song (2)", "Song (3)", "Chapter 4"]);
+        assert_eq!(
+            chapter_sections(&found, &["Chapter 4".to_string(), "Song (2)".to_string()]),
+            Ok(vec!["*30-95.5".to_string(), "*160-inf".to_string()])
+        );
+        assert!(chapter_sections(&found, &["Song".to_string()]).is_err());
+        assert!(chapters(&serde_json::json!({ "chapters": null })).is_empty());
+        assert!(chapter_sections(&[], &["Intro".to_string()]).is_err());
+    }
+
+    #[test]
+    fn lists_uploaded_subtitles_before_automatic_captions() {
+        let info = serde_json::json!({
+            "subtitles": {
+                "en": [{ "ext": "vtt", "name": "English" }],
+                "live_chat": [{ "ext": "json" }]
+            },
+            "automatic_captions": {
+                "de": [{ "ext": "vtt", "name": "German" }],
+                "en": [{ "ext": "vtt", "name": "English" }]
+            }
+        });
+        let track = |language: &str, name: &str, automatic| SubtitleTrack {
+            language: language.to_string(),
+            name: Some(name.to_string()),
+            automatic,
+        };
+        assert_eq!(
+            subtitle_tracks(&info),
+            [
+                track("en", "English", false),
+                track("de", "German", true),
+                track("en", "English", true),
+            ]
+        );
+        assert!(subtitle_tracks(&serde_json::json!({})).is_empty());
+    }
+
+    #[test]
+    fn locates_converted_transcripts() {
+        let mut tracker = TransferTracker::default();
+        tracker.observe(
+            "[info] Writing video subtitles to: /out/.video-downloader-tmp-job-1/Talk [abc].en.vtt",
+        );
+        assert_eq!(
+            tracker.converted_subtitles(std::path::Path::new("/out"), "srt"),
+            ["/out/Talk [abc].en.srt"]
+        );
+    }
+
+    #[test]
+    fn lists_flat_playlist_entries() {
+        let info = serde_json::json!({
+            "_type": "playlist",
+            "entries": [
+                {
+                    "id": "abc",
+                    "title": "First",
+                    "url": "https://www.youtube.com/watch?v=abc",
+                    "duration": 61.0
+                },
+                { "id": "def", "title": null },
+                { "title": "No id" }
+            ]
+        });
+        let entries = playlist_entries(&info);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0],
+            EntryStub {
+                id: "abc".to_string(),
+                title: Some("First".to_string()),
+                url: Some("https://www.youtube.com/watch?v=abc".to_string()),
+                duration_secs: Some(61.0),
+            }
+        );
+        assert_eq!(
+            (entries[1].title.as_deref(), entries[1].url.as_deref()),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn follows_the_current_playlist_item() {
+        let mut playlist = PlaylistTracker::default();
+        assert_eq!(
+            playlist.observe("[youtube:tab] Playlist Mix: Downloading 12 items"),
+            None
+        );
+        let progress = |current_title: Option<&str>| PlaylistProgress {
+            current_index: 3,
+            total: 12,
+            current_title: current_title.map(str::to_string),
+        };
+        assert_eq!(
+            playlist.observe("[download] Downloading item 3 of 12"),
+            Some(progress(None))
+        );
+        assert_eq!(
+            playlist.observe("[download] Destination: /tmp/job/Song [abc] [1080p].f137.mp4"),
+            Some(progress(Some("Song")))
+        );
+        // The audio stream of the same item changes nothing.
+        assert_eq!(
+            playlist.observe("[download] Destination: /tmp/job/Song [abc] [1080p].f251.webm"),
+            None
+        );
+    }
+
+    #[test]
+    fn pages_through_playlist_items() {
+        assert_eq!(playlist_items_range(None, None), Ok(None));
+        assert_eq!(
+            playlist_items_range(None, Some(50)).unwrap().as_deref(),
+            Some("1:50")
+        );
+        assert_eq!(
+            playlist_items_range(Some(51), Some(50)).unwrap().as_deref(),
+            Some("51:100")
+        );
+        assert_eq!(
+            playlist_items_range(Some(51), None).unwrap().as_deref(),
+            Some("51:")
+        );
+        assert!(playlist_items_range(Some(0), None).is_err());
+        assert!(playlist_items_range(None, Some(0)).is_err());
+    }
+
+    #[test]
+    fn tracks_the_active_url_of_a_batch() {
+        let long = format!("https://example.com/watch?v=abc&list={}", "x".repeat(100));
+        let batch = vec!["https://youtu.be/abc".to_string(), long.clone()];
+        let line = "[youtube] Extracting URL: https://youtu.be/abc";
+        assert_eq!(
+            extracted_url(line).and_then(|url| batch_index(url, &batch)),
+            Some(0)
+        );
+
+        let shortened = format!("{}...{}", &long[..97], &long[long.len() - 20..]);
+        assert_eq!(batch_index(&shortened, &batch), Some(1));
+        assert_eq!(batch_index("https://youtu.be/other", &batch), None);
+        assert_eq!(extracted_url("[download] Destination: a.mp4"), None);
+    }
+
+    #[test]
+    fn nests_downloads_in_dated_folders() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 3, 7).unwrap();
+        assert_eq!(
+            std::path::PathBuf::from(dated_folder("/videos", date)),
+            std::path::Path::new("/videos").join("2024-03-07")
+        );
+    }
+
+    #[test]
+    fn guesses_video_ids_from_urls() {
+        assert_eq!(
+            likely_video_id("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42").as_deref(),
+            Some("dQw4w9WgXcQ")
+        );
+        assert_eq!(
+            likely_video_id("https://youtu.be/dQw4w9WgXcQ/").as_deref(),
+            Some("dQw4w9WgXcQ")
+        );
+        assert_eq!(
+            likely_video_id("https://vimeo.com/76979871").as_deref(),
+            Some("76979871")
+        );
+        assert_eq!(likely_video_id("https://example.com/"), None);
+        assert_eq!(likely_video_id("not a url"), None);
+        for url in [
+            "https://www.youtube.com/@SomeChannel/videos",
+            "https://www.youtube.com/playlist?list=PLrAXtmErZgOeiKm4sgNOknGvNjby9efdf",
+            "https://vimeo.com/channels/staffpicks",
+        ] {
+            assert_eq!(likely_video_id(url), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn finds_encoders_a_download_needs() {
+        let output = "Encoders:\n V..... = Video\n ------\n V....D libx264              libx264 H.264 (codec h264)\n A....D aac                  AAC (Advanced Audio Coding)\n";
+        let encoders = parse_encoders(output);
+        assert!(encoders.contains("libx264") && encoders.contains("aac"));
+        assert!(!encoders.contains("="));
+        assert_eq!(encoders.len(), 2);
+
+        let options = DownloadOptions {
+            audio_format: Some(" MP3".to_string()),
+            crop_thumbnail_square: true,
+            ..Default::default()
+        };
+        assert_eq!(options.required_encoders(), ["libmp3lame", "mjpeg"]);
+        assert!(DownloadOptions::default().required_encoders().is_empty());
+    }
+
+    #[test]
+    fn embeds_thumbnails_as_jpeg_and_metadata() {
+        let options = DownloadOptions {
+            embed_thumbnail: true,
+            embed_metadata: true,
+            ..Default::default()
+        };
+        let args = options.yt_dlp_args().unwrap();
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["--convert-thumbnails", "jpg"]));
+        assert!(args.contains(&"--embed-thumbnail".to_string()));
+        assert!(args.contains(&"--embed-metadata".to_string()));
+    }
+
+    #[test]
+    fn fetches_fragments_concurrently() {
+        let fragments = |concurrent_fragments| {
+            DownloadOptions {
+                concurrent_fragments,
+                ..Default::default()
+            }
+            .yt_dlp_args()
+        };
+        let count = |args: Vec<String>| {
+            let at = args
+                .iter()
+                .position(|arg| arg == "--concurrent-fragments")
+                .unwrap();
+            args[at + 1].clone()
+        };
+        assert_eq!(count(fragments(None).unwrap()), "4");
+        assert_eq!(count(fragments(Some(16)).unwrap()), "16");
+        assert!(fragments(Some(0)).is_err());
+        assert!(fragments(Some(17)).is_err());
+    }
+
+    #[test]
+    fn extracts_audio_without_video_sorting_or_remuxing() {
+        for prefer_quality_over_compatibility in [false, true] {
+            let args = DownloadOptions {
+                audio_format: Some("mp3".to_string()),
+                quality: Some("1080p AV1".to_string()),
+                prefer_quality_over_compatibility,
+                ..Default::default()
+            }
+            .yt_dlp_args()
+            .unwrap();
+            assert!(args
+                .windows(2)
+                .any(|pair| pair == ["--audio-format", "mp3"]));
+            for video_arg in ["-S", "-f", "--merge-output-format", "--remux-video"] {
+                assert!(!args.iter().any(|arg| arg == video_arg), "{video_arg}");
+            }
+        }
+
+        let audio = |audio_format: Option<&str>| DownloadOptions {
+            download_kind: DownloadKind::Audio,
+            audio_format: audio_format.map(str::to_string),
+            ..Default::default()
+        };
+        assert!(audio(None).yt_dlp_args().is_err());
+        let args = audio(Some("m4a")).yt_dlp_args().unwrap();
+        assert!(args.iter().any(|arg| arg == "-x"));
+        assert!(!args.iter().any(|arg| arg == "--remux-video"));
+    }
+
+    #[test]
+    fn caps_video_height_but_not_audio() {
+        let capped = |max_height, audio_format: Option<&str>| {
+            DownloadOptions {
+                max_height,
+                audio_format: audio_format.map(str::to_string),
+                ..Default::default()
+            }
+            .yt_dlp_args()
+        };
+        let args = capped(Some(720), None).unwrap();
+        assert!(args.windows(2).any(|pair| pair == ["-S", "res:720"]));
+        let args = capped(Some(720), Some("mp3")).unwrap();
+        assert!(!args.iter().any(|arg| arg == "res:720"));
+        assert!(capped(Some(0), None).is_err());
+    }
+
+    #[test]
+    fn reads_quality_shorthand() {
+        assert_eq!(
+            quality_sort("1080p60 AV1").as_deref(),
+            Ok("res:1080,fps:60,vcodec:av01")
+        );
+        assert_eq!(
+            quality_sort(" 720p  h264").as_deref(),
+            Ok("res:720,vcodec:h264")
+        );
+        assert_eq!(quality_sort("vp9").as_deref(), Ok("vcodec:vp9"));
+        assert_eq!(quality_height("av1 1440p60"), Some(1440));
+        assert_eq!(quality_height("h264"), None);
+        for quality in ["", "1000p", "1080p24", "4k", "720p 1080p", "hevc"] {
+            assert!(quality_sort(quality).is_err(), "{quality}");
+        }
 
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
-        return Err(if error.is_empty() {
-            format!("yt-dlp exited with code {:?}", output.status.code())
-        } else {
-            error
-        });
+        let options = DownloadOptions {
+            quality: Some("best please".to_string()),
+            ..Default::default()
+        };
+        let args = options.yt_dlp_args().unwrap();
+        assert!(args.windows(2).any(|pair| pair == ["-f", "bv*+ba/b"]));
+        assert!(!args.iter().any(|argument| argument.starts_with("res:")));
+        assert_eq!(options.warnings().len(), 1);
     }
 
-    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    if version.is_empty() {
-        Err("yt-dlp returned an empty version".to_string())
-    } else {
-        Ok(version)
+    #[test]
+    fn lists_working_extractors() {
+        let output = "abc.net.au\nyoutube\n\nyoutube:tab\nDouyin (CURRENTLY BROKEN)\n";
+        assert_eq!(
+            parse_extractors(output),
+            ["abc.net.au", "youtube", "youtube:tab"]
+        );
     }
-}
 
-async fn ffmpeg_is_working(path: &std::path::Path) -> bool {
-    let mut command = tokio::process::Command::new(path);
-    command
-        .arg("-version")
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .kill_on_drop(true);
-    tokio::time::timeout(std::time::Duration::from_secs(10), command.status())
-        .await
-        .ok()
-        .and_then(Result::ok)
-        .map(|status| status.success())
-        .unwrap_or(false)
-}
+    #[test]
+    fn warns_when_the_info_json_cannot_be_embedded() {
+        let warns = |merge_format_fallback: &[&str], audio_format: Option<&str>| {
+            DownloadOptions {
+                embed_info_json: true,
+                merge_format_fallback: merge_format_fallback
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect(),
+                audio_format: audio_format.map(str::to_string),
+                ..Default::default()
+            }
+            .warnings()
+            .iter()
+            .any(|warning| warning.starts_with("Info JSON"))
+        };
+        assert!(warns(&[], None));
+        assert!(warns(&["mp4"], None));
+        assert!(!warns(&["mkv"], None));
+        assert!(warns(&["mkv"], Some("mp3")));
+        assert!(warns(&[], Some("m4a")));
+    }
 
-async fn fetch_latest_ytdlp_version() -> Result<String, String> {
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(8))
-        .build()
-        .map_err(|e| format!("Could not prepare update check: {e}"))?;
-    let response = client
-        .get("https://api.github.com/repos/yt-dlp/yt-dlp/releases/latest")
-        .header(
-            reqwest::header::USER_AGENT,
-            concat!("VideoDownloader/", env!("CARGO_PKG_VERSION")),
-        )
-        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
-        .send()
-        .await
-        .map_err(|e| format!("Could not check for updates: {e}"))?
-        .error_for_status()
-        .map_err(|e| format!("Update check failed: {e}"))?;
-    let body = response
-        .bytes()
-        .await
-        .map_err(|e| format!("Could not read update information: {e}"))?;
-    let release: GithubRelease = serde_json::from_slice(&body)
-        .map_err(|e| format!("Could not parse update information: {e}"))?;
+    #[test]
+    fn writes_without_part_files() {
+        let options = DownloadOptions {
+            no_part: true,
+            ..Default::default()
+        };
+        assert!(options
+            .yt_dlp_args()
+            .unwrap()
+            .contains(&"--no-part".to_string()));
+        assert_eq!(options.warnings().len(), 1);
+    }
 
-    Ok(release.tag_name.trim_start_matches('v').to_string())
-}
+    #[test]
+    fn limits_saved_comments() {
+        let options = DownloadOptions {
+            write_comments: true,
+            max_comments: Some(500),
+            ..Default::default()
+        };
+        let args = options.yt_dlp_args().unwrap();
+        assert!(args.contains(&"--write-comments".to_string()));
+        assert!(args.contains(&"youtube:max_comments=500".to_string()));
+        assert_eq!(options.warnings().len(), 1);
 
-fn version_day(version: &str) -> Option<i64> {
-    let date = version.trim_start_matches('v').get(..10)?;
-    let mut parts = date.split('.');
-    let year = parts.next()?.parse::<i64>().ok()?;
-    let month = parts.next()?.parse::<u32>().ok()?;
-    let day = parts.next()?.parse::<u32>().ok()?;
-    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
-        return None;
+        for (write_comments, max_comments) in [(true, 0), (false, 500)] {
+            let options = DownloadOptions {
+                write_comments,
+                max_comments: Some(max_comments),
+                ..Default::default()
+            };
+            assert!(options.yt_dlp_args().is_err());
+        }
     }
-    Some(days_from_civil(year, month, day))
-}
 
-fn days_from_civil(mut year: i64, month: u32, day: u32) -> i64 {
-    year -= i64::from(month <= 2);
-    let era = if year >= 0 { year } else { year - 399 } / 400;
-    let year_of_era = year - era * 400;
-    let shifted_month = month as i64 + if month > 2 { -3 } else { 9 };
-    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
-    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
-    era * 146_097 + day_of_era
-}
+    #[test]
+    fn embeds_custom_covers_by_container() {
+        let image = std::path::Path::new("/art/cover.png");
+        let args = cover_args(std::path::Path::new("/out/Song.mp3"), image).unwrap();
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["-disposition:0", "attached_pic"]));
+        assert!(args.ends_with(&["-id3v2_version".to_string(), "3".to_string()]));
 
-async fn download_to_path(url: &str, dest: &std::path::Path) -> Result<(), String> {
-    use futures_util::StreamExt;
-    use tokio::io::AsyncWriteExt;
+        let args = cover_args(std::path::Path::new("/out/Clip.mkv"), image).unwrap();
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["-attach", "/art/cover.png"]));
+        assert!(args.contains(&"mimetype=image/png".to_string()));
 
-    let response = reqwest::get(url)
-        .await
-        .map_err(|e| format!("Could not download yt-dlp: {e}"))?
-        .error_for_status()
-        .map_err(|e| format!("yt-dlp download failed: {e}"))?;
+        assert!(cover_args(std::path::Path::new("/out/Clip.webm"), image).is_err());
+        assert!(cover_args(
+            std::path::Path::new("/out/Clip.mp4"),
+            std::path::Path::new("/art/cover.gif")
+        )
+        .is_err());
+    }
 
-    let mut file = tokio::fs::File::create(dest)
-        .await
-        .map_err(|e| format!("Cannot create update file: {e}"))?;
-    let mut stream = response.bytes_stream();
+    #[test]
+    fn passes_a_valid_referer() {
+        let options = |referer: &str| DownloadOptions {
+            referer: Some(referer.to_string()),
+            ..Default::default()
+        };
+        let args = options(" https://example.com/embed/1 ")
+            .yt_dlp_args()
+            .unwrap();
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["--referer", "https://example.com/embed/1"]));
+        assert!(options("example.com").yt_dlp_args().is_err());
+        assert!(options("ftp://example.com/").yt_dlp_args().is_err());
+        for unset in ["", "   "] {
+            let args = options(unset).yt_dlp_args().unwrap();
+            assert!(!args.iter().any(|arg| arg == "--referer"));
+        }
+    }
 
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("yt-dlp download failed: {e}"))?;
-        file.write_all(&chunk)
-            .await
-            .map_err(|e| format!("Cannot write update file: {e}"))?;
+    #[test]
+    fn requests_automatic_captions_when_chosen() {
+        let options = |subtitle_langs: &[&str], auto_subtitle_langs: &[&str]| {
+            DownloadOptions {
+                subtitle_langs: subtitle_langs.iter().map(|l| l.to_string()).collect(),
+                auto_subtitle_langs: auto_subtitle_langs.iter().map(|l| l.to_string()).collect(),
+                ..Default::default()
+            }
+            .yt_dlp_args()
+            .unwrap()
+        };
+        let uploaded = options(&["en"], &[]);
+        assert!(uploaded
+            .windows(3)
+            .any(|args| args == ["--write-subs", "--sub-langs", "en"]));
+        assert!(!uploaded.contains(&"--write-auto-subs".to_string()));
+        assert!(options(&["en"], &["de", "en"])
+            .windows(4)
+            .any(|args| args == ["--write-subs", "--write-auto-subs", "--sub-langs", "en,de"]));
     }
 
-    file.flush()
-        .await
-        .map_err(|e| format!("Cannot finish update file: {e}"))
-}
+    #[test]
+    fn embeds_lyrics_only_into_extracted_audio() {
+        let options = |audio_format: Option<&str>| DownloadOptions {
+            audio_format: audio_format.map(str::to_string),
+            embed_lyrics: true,
+            ..Default::default()
+        };
+        let args = options(Some("mp3")).yt_dlp_args().unwrap();
+        assert!(args.ends_with(&[
+            "--write-subs".to_string(),
+            "--sub-langs".to_string(),
+            "en".to_string(),
+            "--convert-subs".to_string(),
+            "lrc".to_string(),
+        ]));
+        assert!(options(None).yt_dlp_args().is_err());
+        assert!(options(Some("wav")).yt_dlp_args().is_err());
 
-#[cfg(not(windows))]
-async fn replace_ytdlp(current: &std::path::Path, update: &std::path::Path) -> Result<(), String> {
-    tokio::fs::rename(update, current)
-        .await
-        .map_err(|e| format!("Could not install yt-dlp update: {e}"))
-}
+        assert_eq!(
+            lyrics_audio_file(std::path::Path::new("/out/Song [abc].en.lrc"), "ogg"),
+            std::path::Path::new("/out/Song [abc].ogg")
+        );
+    }
 
-#[cfg(windows)]
-async fn replace_ytdlp(current: &std::path::Path, update: &std::path::Path) -> Result<(), String> {
-    let backup = current.with_extension("exe.backup");
-    tokio::fs::remove_file(&backup).await.ok();
-    tokio::fs::rename(current, &backup)
-        .await
-        .map_err(|e| format!("Could not prepare yt-dlp update: {e}"))?;
+    #[test]
+    fn keeps_single_file_formats_without_ffmpeg() {
+        let args = |values: &[&str]| {
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            without_ffmpeg(args(&[
+                "-f",
+                "bestvideo[height<=720]+bestaudio/best[height<=720][ext=mp4]",
+                "-S",
+                "res,fps,br",
+                "--merge-output-format",
+                "mp4",
+                "--remux-video",
+                "mp4",
+            ])),
+            Ok(args(&[
+                "-f",
+                "best[height<=720][ext=mp4]",
+                "-S",
+                "res,fps,br"
+            ]))
+        );
+        assert!(without_ffmpeg(args(&["-f", "bestvideo+bestaudio"])).is_err());
+        assert!(without_ffmpeg(args(&["-x", "--audio-format", "mp3"])).is_err());
+        assert!(without_ffmpeg(args(&["--download-sections", "*10-20"])).is_err());
+        assert!(without_ffmpeg(args(&["--force-keyframes-at-cuts"])).is_err());
+    }
 
-    if let Err(error) = tokio::fs::rename(update, current).await {
-        tokio::fs::rename(&backup, current).await.ok();
-        return Err(format!("Could not install yt-dlp update: {error}"));
+    #[test]
+    fn recognizes_rate_limiting() {
+        assert_eq!(
+            classify_failure("ERROR: [youtube] abc: Unable to download webpage: HTTP Error 429: Too Many Requests"),
+            ErrorKind::RateLimited
+        );
+        assert_eq!(
+            classify_failure("WARNING: HTTP Error 429, retrying\nERROR: Video unavailable"),
+            ErrorKind::Unavailable
+        );
+        let network = "ERROR: [youtube] abc: Unable to download webpage: <urlopen error [Errno -3] Temporary failure in name resolution>";
+        assert_eq!(classify_failure(network), ErrorKind::Network);
+        assert!(ErrorKind::Network.is_temporary() && !ErrorKind::Unavailable.is_temporary());
     }
 
-    tokio::fs::remove_file(backup).await.ok();
-    Ok(())
-}
+    #[test]
+    fn reads_media_info_from_ffmpeg_description() {
+        let description = "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'Clip [abc].mp4':
+  Duration: 00:03:33.07, start: 0.000000, bitrate: 1939 kb/s
+  Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), yuv420p(tv, bt709, progressive), 1920x1080 [SAR 1:1 DAR 16:9], 1806 kb/s, 25 fps, 25 tbr (default)
+  Stream #0:1[0x2](eng): Audio: aac (LC) (mp4a / 0x6134706D), 44100 Hz, stereo, fltp, 128 kb/s (default)
+At least one output file must be specified";
+        assert_eq!(
+            parse_media_info(description),
+            MediaInfo {
+                duration_secs: Some(213.07),
+                width: Some(1920),
+                height: Some(1080),
+                video_codec: Some("h264".to_string()),
+                audio_codec: Some("aac".to_string()),
+                bitrate_kbps: Some(1939),
+            }
+        );
+    }
 
-async fn download_file(
-    app: &AppHandle,
-    url: &str,
-    dest: &std::path::Path,
-    start: f64,
-    end: f64,
-) -> Result<(), String> {
-    use futures_util::StreamExt;
-    use tokio::io::AsyncWriteExt;
+    #[test]
+    fn builds_playlist_manifest_from_recorded_items() {
+        let items = concat!(
+            r#"{"id": "a1", "title": "First", "filepath": "/out/First [a1].mp4", "playlist_index": 1, "playlist_id": "PL1", "playlist_title": "Talks"}"#,
+            "\n",
+            r#"{"id": "b2", "title": "Second", "filepath": "/out/Second [b2].mp4", "playlist_index": 2, "playlist_id": "PL1", "playlist_title": "Talks"}"#,
+            "\n",
+        );
+        let manifest = playlist_manifest(items, "https://example.com/playlist?list=PL1").unwrap();
+        assert_eq!(manifest["playlist_title"], "Talks");
+        assert_eq!(manifest["items"][1]["file"], "/out/Second [b2].mp4");
 
-    let resp = reqwest::get(url)
-        .await
-        .map_err(|e| e.to_string())?
-        .error_for_status()
-        .map_err(|e| e.to_string())?;
-    let total = resp.content_length().unwrap_or(0);
-    let mut received: u64 = 0;
+        let single = r#"{"id": "a1", "title": "First", "filepath": "/out/First [a1].mp4", "playlist_index": null, "playlist_id": null, "playlist_title": null}"#;
+        assert!(playlist_manifest(single, "https://example.com/watch?v=a1").is_none());
+    }
 
-    let temp_dest = dest.with_extension("download");
-    tokio::fs::remove_file(&temp_dest).await.ok();
+    #[test]
+    fn keeps_a_margin_of_free_space_after_downloading() {
+        let gib = 1024 * 1024 * 1024;
+        assert!(has_room_for(gib, 2 * gib));
+        assert!(!has_room_for(gib, gib + LOW_DISK_ABORT_BYTES - 1));
+        assert!(!has_room_for(u64::MAX, u64::MAX));
+    }
 
-    let mut file = tokio::fs::File::create(&temp_dest)
-        .await
-        .map_err(|e| e.to_string())?;
+    #[test]
+    fn merges_into_first_compatible_container() {
+        assert_eq!(
+            format_preference_args(false, &[])[2..],
+            [
+                "--merge-output-format",
+                "mp4/mkv",
+                "--remux-video",
+                "mkv>mkv/mp4"
+            ]
+        );
+        assert_eq!(
+            format_preference_args(true, &["webm".to_string(), "mkv".to_string()])[2..],
+            ["--merge-output-format", "webm/mkv"]
+        );
 
-    let mut stream = resp.bytes_stream();
-    while let Some(chunk) = stream.next().await {
-        let chunk = match chunk {
-            Ok(chunk) => chunk,
-            Err(error) => {
-                tokio::fs::remove_file(&temp_dest).await.ok();
-                return Err(error.to_string());
-            }
+        let options = DownloadOptions {
+            merge_format_fallback: vec!["mp3".to_string()],
+            ..Default::default()
         };
-        if let Err(error) = file.write_all(&chunk).await {
-            tokio::fs::remove_file(&temp_dest).await.ok();
-            return Err(error.to_string());
-        }
-        received += chunk.len() as u64;
-        if total > 0 {
-            let frac = received as f64 / total as f64;
-            let p = start + frac * (end - start);
-            app.emit("setup-progress", p).ok();
-        }
+        assert!(options.yt_dlp_args().is_err());
     }
-    file.flush().await.map_err(|e| e.to_string())?;
-    drop(file);
-    tokio::fs::remove_file(dest).await.ok();
-    tokio::fs::rename(&temp_dest, dest)
-        .await
-        .map_err(|e| e.to_string())?;
-    Ok(())
-}
 
-fn extract_binary(
-    zip_path: &std::path::Path,
-    binary_name: &str,
-    dest: &std::path::Path,
-) -> Result<(), String> {
-    let file = std::fs::File::open(zip_path).map_err(|e| e.to_string())?;
-    let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+    #[test]
+    fn parses_frame_timestamps() {
+        assert_eq!(parse_timestamp("90"), Some(90.0));
+        assert_eq!(parse_timestamp("1:30"), Some(90.0));
+        assert_eq!(parse_timestamp("01:02:03.5"), Some(3723.5));
+        assert_eq!(parse_timestamp("-5"), None);
+        assert_eq!(parse_timestamp("1:2:3:4"), None);
+        assert_eq!(parse_timestamp("soon"), None);
+    }
 
-    for i in 0..archive.len() {
-        let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
-        let name = entry.name().to_string();
-        let fname = std::path::Path::new(&name)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
+    #[test]
+    fn validates_audio_bitrate() {
+        assert_eq!(
+            audio_extraction_args(Some("MP3"), Some("192k")).unwrap(),
+            ["-x", "--audio-format", "mp3", "--audio-quality", "192K"]
+        );
+        assert!(audio_extraction_args(Some("flac"), Some("320K")).is_err());
+        assert!(audio_extraction_args(Some("mp3"), Some("loud")).is_err());
+        assert!(audio_extraction_args(None, Some("192K")).is_err());
+        assert!(audio_extraction_args(None, None).unwrap().is_empty());
+    }
 
-        if fname == binary_name {
-            let mut out = std::fs::File::create(dest).map_err(|e| e.to_string())?;
-            std::io::copy(&mut entry, &mut out).map_err(|e| e.to_string())?;
-            return Ok(());
-        }
+    #[test]
+    fn parses_download_speed() {
+        assert_eq!(
+            parse_speed("[download]  42.0% of ~ 120.50MiB at   50.00KiB/s ETA 40:12"),
+            Some(51200)
+        );
+        assert_eq!(
+            parse_speed("[download]   0.0% of 10.00MiB at Unknown B/s ETA Unknown"),
+            None
+        );
+        assert_eq!(parse_speed("[info] Downloading at night"), None);
     }
-    Err(format!("{} not found in archive", binary_name))
-}
 
-#[cfg(unix)]
-fn make_executable(path: &std::path::Path) {
-    use std::os::unix::fs::PermissionsExt;
-    if let Ok(meta) = std::fs::metadata(path) {
-        let mut perms = meta.permissions();
-        perms.set_mode(0o755);
-        std::fs::set_permissions(path, perms).ok();
+    #[test]
+    fn detects_sustained_throttling() {
+        let start = std::time::Instant::now();
+        let mut detector = ThrottleDetector::default();
+        assert!(!detector.observe(20 * 1024, start));
+        assert!(!detector.observe(20 * 1024, start + std::time::Duration::from_secs(30)));
+        // A burst of speed starts the window over.
+        assert!(!detector.observe(2 * 1024 * 1024, start + std::time::Duration::from_secs(31)));
+        assert!(!detector.observe(20 * 1024, start + std::time::Duration::from_secs(50)));
+        assert!(detector.observe(20 * 1024, start + std::time::Duration::from_secs(95)));
     }
-}
-#[cfg(not(unix))]
-fn make_executable(_path: &std::path::Path) {}
 
-fn parse_progress(line: &str) -> Option<f64> {
-    let pct_idx = line.find('%')?;
-    let before = &line[..pct_idx];
-    let num_start = before
-        .rfind(|c: char| !c.is_ascii_digit() && c != '.')
-        .map(|i| i + 1)
-        .unwrap_or(0);
-    before[num_start..].parse::<f64>().ok()
-}
+    #[test]
+    fn picks_quick_download_presets() {
+        let video = serde_json::json!({ "extractor_key": "Youtube", "live_status": "not_live" });
+        assert_eq!(
+            quick_preset("https://www.youtube.com/watch?v=abc", &video),
+            QuickPreset::Video
+        );
+        assert_eq!(
+            quick_preset("https://music.youtube.com/watch?v=abc", &video),
+            QuickPreset::Audio
+        );
+        assert_eq!(
+            quick_preset(
+                "https://soundcloud.com/artist/track",
+                &serde_json::json!({ "extractor_key": "Soundcloud" })
+            ),
+            QuickPreset::Audio
+        );
+        assert_eq!(
+            quick_preset(
+                "https://www.twitch.tv/channel",
+                &serde_json::json!({ "extractor_key": "TwitchStream", "is_live": true })
+            ),
+            QuickPreset::Live
+        );
 
-#[cfg(test)]
-mod tests {
-    use super::is_direct_instagram_story_url;
+        let live = quick_download_params("u".to_string(), "o".to_string(), QuickPreset::Live);
+        assert!(live
+            .options
+            .yt_dlp_args()
+            .unwrap()
+            .contains(&"--live-from-start".to_string()));
+    }
 
     #[test]
-    fn recognizes_direct_instagram_story_urls() {
-        assert!(is_direct_instagram_story_url(
-            "https://www.instagram.com/stories/account/3924911627980093004/"
-        ));
-        assert!(is_direct_instagram_story_url(
-            "https://instagram.com/stories/account/3924911627980093004/?utm_source=test"
-        ));
+    fn tells_apart_access_failures() {
+        let cases = [
+            ("ERROR: [youtube] abc: Sign in to confirm your age. This video may be inappropriate for some users.", ErrorKind::AgeRestricted),
+            ("ERROR: [youtube] abc: Video unavailable. The uploader has not made this video available in your country", ErrorKind::GeoBlocked),
+            ("ERROR: [youtube] abc: Private video. Sign in if you've been granted access to this video", ErrorKind::Private),
+            ("ERROR: [generic] Unable to download webpage: HTTP Error 404: Not Found", ErrorKind::NotFound),
+            ("ERROR: [instagram] abc: Requested content is not available, rate-limit reached or login required. Use --cookies", ErrorKind::LoginRequired),
+            ("ERROR: Unsupported URL: https://example.com/", ErrorKind::UnsupportedUrl),
+        ];
+        for (stderr, kind) in cases {
+            assert_eq!(classify_failure(stderr), kind, "{stderr}");
+        }
     }
 
     #[test]
-    fn leaves_story_feeds_and_other_urls_as_playlists() {
-        assert!(!is_direct_instagram_story_url(
-            "https://www.instagram.com/stories/account/"
-        ));
-        assert!(!is_direct_instagram_story_url(
-            "https://www.instagram.com/p/example/"
-        ));
+    fn recognizes_unavailable_videos() {
+        let line =
+            "ERROR: [youtube] abc: Video unavailable. This video has been removed by the uploader";
+        assert_eq!(classify_failure(line), ErrorKind::Unavailable);
+        assert_eq!(
+            unavailable_item(line),
+            Some(SkippedItem {
+                id: Some("abc".to_string()),
+                reason: "Video unavailable. This video has been removed by the uploader"
+                    .to_string(),
+            })
+        );
+        assert_eq!(
+            unavailable_item("ERROR: Private video. Sign in if you've been granted access")
+                .and_then(|item| item.id),
+            None
+        );
+        assert_eq!(
+            unavailable_item("ERROR: [youtube] abc: Unable to download webpage"),
+            None
+        );
     }
 }
 
@@ -962,17 +8387,77 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .manage(AppState::default())
+        .setup(|app| {
+            tauri::async_runtime::spawn(settings::apply_saved_settings(app.handle().clone()));
+            tauri::async_runtime::spawn(queue::run_worker(app.handle().clone()));
+            tauri::async_runtime::spawn(run_bandwidth_worker(app.handle().clone()));
+            tauri::async_runtime::spawn(sweep_stale_temp_dirs(app.handle().clone()));
+            tauri::async_runtime::spawn(logs::start_session(app.handle().clone()));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             check_deps,
             get_ytdlp_version,
+            list_extractors,
             get_ytdlp_status,
             update_ytdlp,
             download_deps,
+            ensure_deps,
+            check_ffmpeg_features,
             get_default_output_path,
+            settings::load_settings,
+            settings::save_settings,
+            history::get_history,
+            history::clear_history,
+            history::get_last_download_date,
             start_download,
+            quick_download,
+            repeat_last_download,
+            enqueue_download,
+            start_batch_download,
+            set_job_priority,
+            update_job_output,
+            retry_job,
+            pause_queue,
+            resume_queue,
+            get_queue,
+            get_queue_eta,
+            get_active_jobs,
+            export_data,
+            import_data,
+            check_cookies,
+            list_formats_grouped,
+            get_video_info,
+            list_subtitles,
+            list_chapters,
+            list_playlist_entries,
+            download_thumbnail_only,
+            remux_file,
+            probe_file,
+            capture_frame,
+            verify_file,
+            is_supported_url,
             cancel_download,
+            cancel_all_downloads,
+            pause_download,
+            resume_download,
+            kill_orphan_processes,
             open_folder,
+            logs::open_logs_folder,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running application");
+        .build(tauri::generate_context!())
+        .expect("error while running application")
+        .run(|app, event| {
+            if let tauri::RunEvent::ExitRequested { api, code, .. } = event {
+                if app.state::<AppState>().shutting_down.load(Ordering::SeqCst) {
+                    return;
+                }
+                api.prevent_exit();
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    shutdown_downloads(&app.state::<AppState>()).await;
+                    app.exit(code.unwrap_or(0));
+                });
+            }
+        });
 }
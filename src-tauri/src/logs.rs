@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use tauri::{AppHandle, Manager};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use crate::AppState;
+
+// ── Session log files ─────────────────────────────────────────────────────────
+//
+// Every line of the download log and every setup step is also written to
+// `logs/session-<start time>.log` in the app data folder, so it can be
+// attached to bug reports after it has scrolled out of the UI. Lines go
+// through a channel to a writer task, so a slow disk never holds up the
+// readers of yt-dlp's output. Only the newest `MAX_SESSION_LOGS` are kept.
+
+const MAX_SESSION_LOGS: usize = 5;
+
+fn logs_dir(app: &AppHandle) -> PathBuf {
+    app.path().app_data_dir().unwrap().join("logs")
+}
+
+/// Session logs to delete so that, with the one about to be created, at
+/// most `MAX_SESSION_LOGS` remain. Names sort by their start time.
+fn stale_logs(mut names: Vec<String>) -> Vec<String> {
+    names.retain(|name| name.starts_with("session-") && name.ends_with(".log"));
+    names.sort();
+    let keep = MAX_SESSION_LOGS - 1;
+    let excess = names.len().saturating_sub(keep);
+    names.truncate(excess);
+    names
+}
+
+/// Opens this session's log file, removes the oldest ones and starts the
+/// task writing lines into it. Without a log file the app works as before.
+pub(crate) async fn start_session(app: AppHandle) {
+    let dir = logs_dir(&app);
+    if tokio::fs::create_dir_all(&dir).await.is_err() {
+        return;
+    }
+    let mut names = Vec::new();
+    if let Ok(mut entries) = tokio::fs::read_dir(&dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    for name in stale_logs(names) {
+        tokio::fs::remove_file(dir.join(name)).await.ok();
+    }
+
+    let started = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let path = dir.join(format!("session-{started}.log"));
+    let Ok(file) = tokio::fs::File::create(&path).await else {
+        return;
+    };
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    if app.state::<AppState>().session_log.set(tx).is_err() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut file = tokio::io::BufWriter::new(file);
+        while let Some(line) = rx.recv().await {
+            if file.write_all(line.as_bytes()).await.is_err() {
+                return;
+            }
+            // Flush once the backlog is written, so the file is current
+            // without a write per line during fast progress output.
+            if rx.is_empty() && file.flush().await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Queues `line` for this session's log file.
+pub(crate) fn write(app: &AppHandle, job_id: Option<&str>, line: &str) {
+    let state = app.state::<AppState>();
+    let Some(tx) = state.session_log.get() else {
+        return;
+    };
+    let time = chrono::Local::now().format("%H:%M:%S%.3f");
+    let line = match job_id {
+        Some(job_id) => format!("{time} [{job_id}] {line}\n"),
+        None => format!("{time} {line}\n"),
+    };
+    tx.send(line).ok();
+}
+
+/// Opens the folder holding the session logs in the file manager.
+#[tauri::command]
+pub(crate) fn open_logs_folder(app: AppHandle) -> Result<(), String> {
+    let dir = logs_dir(&app);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Cannot create the logs folder: {e}"))?;
+    crate::open_folder(dir.to_string_lossy().into_owned());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stale_logs;
+
+    #[test]
+    fn keeps_the_newest_session_logs() {
+        let names = [
+            "session-20240105-090000.log",
+            "session-20240101-090000.log",
+            "session-20240104-090000.log",
+            "notes.txt",
+            "session-20240102-090000.log",
+            "session-20240103-090000.log",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        assert_eq!(stale_logs(names), ["session-20240101-090000.log"]);
+        assert!(stale_logs(vec!["session-20240101-090000.log".to_string()]).is_empty());
+    }
+}
@@ -0,0 +1,1075 @@
+use std::sync::atomic::Ordering;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    free_space, launch_download, quality_height, AppState, CookieBrowser, DownloadError,
+    DownloadParams, JobEvent,
+};
+
+// ── Download queue ────────────────────────────────────────────────────────────
+//
+// Queued jobs run one at a time, next to any downloads started directly. A
+// background worker starts the next pending job whenever no other queued job
+// is running; jobs finishing or being added wake it up through
+// `AppState::queue_changed`.
+
+/// A job rate limited more often than this fails for good.
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+/// First wait after HTTP 429; doubled on every further retry.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(30);
+/// From this retry on the job switches to YouTube's Android player client,
+/// which is throttled separately from the web client.
+const ANDROID_CLIENT_FROM_RETRY: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum JobStatus {
+    Pending,
+    Running,
+    /// Failed with a temporary error; runs again once its backoff is over.
+    Retrying,
+    Completed,
+    Failed,
+    Cancelled,
+    /// Stopped by `pause_download`; its partial files are kept.
+    Paused,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct QueueJob {
+    pub job_id: String,
+    pub params: DownloadParams,
+    pub status: JobStatus,
+    /// Pending jobs with a higher priority start first.
+    pub priority: i32,
+    /// How often the job was retried after failing.
+    pub retries: u32,
+    /// Automatic retries after the site rate limited the job.
+    pub rate_limit_retries: u32,
+    /// Automatic retries under the `RetryPolicy`. A failed job that used
+    /// them all has given up.
+    pub auto_retries: u32,
+    /// A rate-limited job waits until this moment before it runs again.
+    #[serde(skip)]
+    not_before: Option<Instant>,
+    /// Expected size from a `-J` probe, filled in after the job is queued.
+    pub estimated_bytes: Option<u64>,
+    /// Fraction (0–1) of the current download, while running.
+    pub progress: f64,
+}
+
+impl QueueJob {
+    fn is_waiting(&self) -> bool {
+        matches!(self.status, JobStatus::Pending | JobStatus::Retrying)
+    }
+}
+
+/// Jobs queued together by `start_batch_download`.
+struct Batch {
+    batch_id: String,
+    job_ids: Vec<String>,
+    /// Keep going after a job fails instead of cancelling the rest.
+    continue_on_error: bool,
+    completed: usize,
+    failed: usize,
+}
+
+/// Payload of `batch-progress`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct BatchProgress {
+    pub batch_id: String,
+    pub completed: usize,
+    /// Jobs that failed or were cancelled.
+    pub failed: usize,
+    pub total: usize,
+    /// The URL of the batch that runs next, or `None` once it is over.
+    pub current_url: Option<String>,
+    /// Set when a failure cancelled the jobs that had not run yet.
+    pub stopped: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct DownloadQueue {
+    jobs: Vec<QueueJob>,
+    batches: Vec<Batch>,
+}
+
+impl DownloadQueue {
+    pub fn push(&mut self, job_id: String, params: DownloadParams) {
+        self.jobs.push(QueueJob {
+            job_id,
+            params,
+            status: JobStatus::Pending,
+            priority: 0,
+            retries: 0,
+            rate_limit_retries: 0,
+            auto_retries: 0,
+            not_before: None,
+            estimated_bytes: None,
+            progress: 0.0,
+        });
+    }
+
+    /// Queues `jobs` in order as one batch and returns its first progress.
+    pub fn push_batch(
+        &mut self,
+        batch_id: String,
+        jobs: Vec<(String, DownloadParams)>,
+        continue_on_error: bool,
+    ) -> BatchProgress {
+        let mut job_ids = Vec::with_capacity(jobs.len());
+        for (job_id, params) in jobs {
+            job_ids.push(job_id.clone());
+            self.push(job_id, params);
+        }
+        self.batches.push(Batch {
+            batch_id,
+            job_ids,
+            continue_on_error,
+            completed: 0,
+            failed: 0,
+        });
+        self.batch_progress(self.batches.len() - 1, false)
+    }
+
+    /// Counts a job of a batch that ended with `status`. A failure in a
+    /// batch that stops on errors cancels its jobs that have not run. The
+    /// batch is forgotten once all its jobs are done.
+    pub fn finish_batch_job(&mut self, job_id: &str, status: JobStatus) -> Option<BatchProgress> {
+        let at = self
+            .batches
+            .iter()
+            .position(|batch| batch.job_ids.iter().any(|id| id == job_id))?;
+        let batch = &mut self.batches[at];
+        let failed = match status {
+            JobStatus::Completed => false,
+            JobStatus::Failed | JobStatus::Cancelled => true,
+            _ => return None,
+        };
+        if failed {
+            batch.failed += 1;
+        } else {
+            batch.completed += 1;
+        }
+        let stop = failed && !batch.continue_on_error;
+        if stop {
+            let job_ids = batch.job_ids.clone();
+            for job in &mut self.jobs {
+                if job.is_waiting() && job_ids.contains(&job.job_id) {
+                    job.status = JobStatus::Cancelled;
+                }
+            }
+        }
+        let progress = self.batch_progress(at, stop);
+        if progress.current_url.is_none() {
+            self.batches.remove(at);
+        }
+        Some(progress)
+    }
+
+    fn batch_progress(&self, at: usize, stopped: bool) -> BatchProgress {
+        let batch = &self.batches[at];
+        let current_url = batch.job_ids.iter().find_map(|job_id| {
+            self.jobs
+                .iter()
+                .find(|job| job.job_id == *job_id)
+                .filter(|job| {
+                    job.is_waiting() || matches!(job.status, JobStatus::Running | JobStatus::Paused)
+                })
+                .map(|job| job.params.url.clone())
+        });
+        BatchProgress {
+            batch_id: batch.batch_id.clone(),
+            completed: batch.completed,
+            failed: batch.failed,
+            total: batch.job_ids.len(),
+            current_url,
+            stopped,
+        }
+    }
+
+    pub fn jobs(&self) -> &[QueueJob] {
+        &self.jobs
+    }
+
+    fn has_running(&self) -> bool {
+        self.jobs.iter().any(|job| job.status == JobStatus::Running)
+    }
+
+    pub fn contains(&self, job_id: &str) -> bool {
+        self.jobs.iter().any(|job| job.job_id == job_id)
+    }
+
+    fn job_mut(&mut self, job_id: &str) -> Option<&mut QueueJob> {
+        self.jobs.iter_mut().find(|job| job.job_id == job_id)
+    }
+
+    /// Marks the highest-priority pending job as running and returns it.
+    /// Jobs of equal priority run in the order they were queued; jobs
+    /// waiting out a rate limit or retry backoff are skipped until `now`
+    /// reaches their time.
+    fn take_next_pending(&mut self, now: Instant) -> Option<(String, DownloadParams)> {
+        let job = self
+            .jobs
+            .iter_mut()
+            .filter(|job| job.is_waiting())
+            .filter(|job| job.not_before.is_none_or(|not_before| not_before <= now))
+            // `max_by_key` keeps the last of equal keys, so walk backwards.
+            .rev()
+            .max_by_key(|job| job.priority)?;
+        job.status = JobStatus::Running;
+        job.progress = 0.0;
+        Some((job.job_id.clone(), job.params.clone()))
+    }
+
+    /// Returns `true` when `job_id` belongs to the queue.
+    pub fn set_status(&mut self, job_id: &str, status: JobStatus) -> bool {
+        match self.job_mut(job_id) {
+            Some(job) => {
+                job.status = status;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_priority(&mut self, job_id: &str, priority: i32) -> Result<(), String> {
+        let job = self
+            .job_mut(job_id)
+            .ok_or_else(|| format!("No queued job with id {job_id}"))?;
+        if job.status != JobStatus::Pending {
+            return Err("Only waiting jobs can be re-prioritized".to_string());
+        }
+        job.priority = priority;
+        Ok(())
+    }
+
+    /// Points a waiting job at another output folder.
+    pub fn set_output_path(&mut self, job_id: &str, output_path: String) -> Result<(), String> {
+        let job = self
+            .job_mut(job_id)
+            .ok_or_else(|| format!("No queued job with id {job_id}"))?;
+        if job.status != JobStatus::Pending {
+            return Err("Only waiting jobs can be moved to another folder".to_string());
+        }
+        job.params.output_path = output_path;
+        Ok(())
+    }
+
+    /// When the next job waiting out a rate limit or retry becomes due.
+    fn next_wake(&self) -> Option<Instant> {
+        self.jobs
+            .iter()
+            .filter(|job| job.is_waiting())
+            .filter_map(|job| job.not_before)
+            .min()
+    }
+
+    /// Schedules another attempt of a rate-limited job, adding it to the
+    /// queue if it was started directly. Returns `None` once the job has
+    /// used up its retries.
+    fn schedule_rate_limit_retry(
+        &mut self,
+        job_id: &str,
+        params: DownloadParams,
+        now: Instant,
+    ) -> Option<RateLimitRetry> {
+        if !self.contains(job_id) {
+            self.push(job_id.to_string(), params);
+        }
+        let job = self.job_mut(job_id)?;
+        if job.rate_limit_retries >= MAX_RATE_LIMIT_RETRIES {
+            return None;
+        }
+        let wait = RATE_LIMIT_BACKOFF * 2u32.pow(job.rate_limit_retries);
+        job.rate_limit_retries += 1;
+        job.status = JobStatus::Pending;
+        job.progress = 0.0;
+        job.not_before = Some(now + wait);
+        let switch_client = job.rate_limit_retries >= ANDROID_CLIENT_FROM_RETRY
+            && job.params.options.player_client.is_none();
+        if switch_client {
+            job.params.options.player_client = Some("android".to_string());
+        }
+        Some(RateLimitRetry {
+            job_id: job_id.to_string(),
+            wait_secs: wait.as_secs(),
+            attempt: job.rate_limit_retries,
+            player_client: job.params.options.player_client.clone(),
+        })
+    }
+
+    /// Queues a job again right away, extracting with another YouTube
+    /// player client.
+    fn restart_with_client(
+        &mut self,
+        job_id: &str,
+        params: DownloadParams,
+        player_client: &str,
+    ) -> Option<ClientSwitch> {
+        if !self.contains(job_id) {
+            self.push(job_id.to_string(), params);
+        }
+        let job = self.job_mut(job_id)?;
+        job.status = JobStatus::Pending;
+        job.progress = 0.0;
+        job.not_before = None;
+        job.params.options.player_client = Some(player_client.to_string());
+        Some(ClientSwitch {
+            job_id: job_id.to_string(),
+            player_client: player_client.to_string(),
+        })
+    }
+
+    /// Cancels every job that has not started yet, returning their ids.
+    pub fn cancel_waiting(&mut self) -> Vec<String> {
+        self.jobs
+            .iter_mut()
+            .filter(|job| job.is_waiting())
+            .map(|job| {
+                job.status = JobStatus::Cancelled;
+                job.job_id.clone()
+            })
+            .collect()
+    }
+
+    /// Queues a failed job again.
+    pub fn retry(&mut self, job_id: &str) -> Result<(), String> {
+        let job = self
+            .job_mut(job_id)
+            .ok_or_else(|| format!("No queued job with id {job_id}"))?;
+        if job.status != JobStatus::Failed {
+            return Err("Only failed jobs can be retried".to_string());
+        }
+        job.status = JobStatus::Pending;
+        job.progress = 0.0;
+        job.retries += 1;
+        job.auto_retries = 0;
+        job.not_before = None;
+        Ok(())
+    }
+
+    /// Schedules another attempt of a job that failed with a temporary
+    /// error, adding it to the queue if it was started directly. Returns
+    /// `None` once the job has used up `policy.max_retries`.
+    fn schedule_retry(
+        &mut self,
+        job_id: &str,
+        params: DownloadParams,
+        policy: RetryPolicy,
+        now: Instant,
+    ) -> Option<JobRetry> {
+        if !self.contains(job_id) {
+            self.push(job_id.to_string(), params);
+        }
+        let job = self.job_mut(job_id)?;
+        if job.auto_retries >= policy.max_retries {
+            return None;
+        }
+        let wait = policy.backoff * 2u32.pow(job.auto_retries.min(10));
+        job.auto_retries += 1;
+        job.status = JobStatus::Retrying;
+        job.progress = 0.0;
+        job.not_before = Some(now + wait);
+        let next_attempt_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| (elapsed + wait).as_secs())
+            .unwrap_or(0);
+        Some(JobRetry {
+            job_id: job_id.to_string(),
+            attempt: job.auto_retries,
+            max_retries: policy.max_retries,
+            wait_secs: wait.as_secs(),
+            next_attempt_at,
+        })
+    }
+
+    /// The job failed again after using up all of `policy.max_retries`.
+    fn gave_up(&self, job_id: &str, policy: RetryPolicy) -> Option<JobGaveUp> {
+        let job = self.jobs.iter().find(|job| job.job_id == job_id)?;
+        (policy.max_retries > 0 && job.auto_retries >= policy.max_retries).then(|| JobGaveUp {
+            job_id: job_id.to_string(),
+            attempts: job.auto_retries,
+        })
+    }
+
+    pub fn set_progress(&mut self, job_id: &str, progress: f64) {
+        if let Some(job) = self.job_mut(job_id) {
+            job.progress = progress.clamp(0.0, 1.0);
+        }
+    }
+
+    pub fn estimated_bytes(&self, job_id: &str) -> Option<u64> {
+        self.jobs
+            .iter()
+            .find(|job| job.job_id == job_id)?
+            .estimated_bytes
+    }
+
+    pub fn set_estimate(&mut self, job_id: &str, bytes: Option<u64>) {
+        if let Some(job) = self.job_mut(job_id) {
+            job.estimated_bytes = bytes;
+        }
+    }
+
+    /// Seconds until every pending and running job is done at
+    /// `average_speed` bytes per second. `None` when the speed or the size of
+    /// any outstanding job is unknown.
+    pub fn eta_secs(&self, average_speed: Option<f64>) -> Option<u64> {
+        let speed = average_speed.filter(|speed| *speed > 0.0)?;
+        let mut remaining_bytes = 0.0;
+        for job in &self.jobs {
+            let fraction_left = match job.status {
+                JobStatus::Pending | JobStatus::Retrying => 1.0,
+                JobStatus::Running => 1.0 - job.progress,
+                _ => continue,
+            };
+            remaining_bytes += job.estimated_bytes? as f64 * fraction_left;
+        }
+        Some((remaining_bytes / speed).ceil() as u64)
+    }
+}
+
+// ── Export and import ─────────────────────────────────────────────────────────
+//
+// The queue is exported as JSON with every job's full parameters, or as CSV
+// with one row per job for spreadsheets. Importing a CSV restores the URL and
+// output folder; everything else falls back to the defaults.
+
+const CSV_COLUMNS: [&str; 6] = [
+    "job_id",
+    "url",
+    "output_path",
+    "status",
+    "priority",
+    "retries",
+];
+
+pub(crate) fn is_csv(path: &std::path::Path) -> Result<bool, String> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("json") => Ok(false),
+        Some("csv") => Ok(true),
+        _ => Err("Choose a .json or .csv file".to_string()),
+    }
+}
+
+/// Writes `jobs` as JSON or CSV, depending on the extension of `path`.
+pub(crate) fn export_jobs(jobs: &[QueueJob], path: &std::path::Path) -> Result<String, String> {
+    if !is_csv(path)? {
+        return serde_json::to_string_pretty(jobs).map_err(|e| e.to_string());
+    }
+    let mut csv = CSV_COLUMNS.join(",");
+    for job in jobs {
+        let status = serde_json::to_value(job.status)
+            .ok()
+            .and_then(|status| status.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let row = [
+            job.job_id.clone(),
+            job.params.url.clone(),
+            job.params.output_path.clone(),
+            status,
+            job.priority.to_string(),
+            job.retries.to_string(),
+        ];
+        csv.push('\n');
+        csv.push_str(&row.map(|field| csv_field(&field)).join(","));
+    }
+    csv.push('\n');
+    Ok(csv)
+}
+
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Splits one CSV line into fields, undoing `csv_field`'s quoting.
+pub(crate) fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// Reads the jobs of an exported queue that have not completed yet. Only
+/// their URLs, folder, options and `-f` format selection are kept: the file
+/// may come from another machine, and raw yt-dlp arguments such as
+/// `--exec` would run commands.
+pub(crate) fn import_jobs(
+    text: &str,
+    path: &std::path::Path,
+) -> Result<Vec<DownloadParams>, String> {
+    if !is_csv(path)? {
+        let jobs: Vec<serde_json::Value> = serde_json::from_str(text)
+            .map_err(|e| format!("The file is not an exported queue: {e}"))?;
+        return jobs
+            .into_iter()
+            .filter(|job| job["status"] != "completed")
+            .map(|job| {
+                let params: DownloadParams = serde_json::from_value(job["params"].clone())
+                    .map_err(|e| format!("The file is not an exported queue: {e}"))?;
+                Ok(DownloadParams {
+                    format_args: format_selection(&params.format_args),
+                    cookie_browser: CookieBrowser::None,
+                    preset: None,
+                    ..params
+                })
+            })
+            .collect();
+    }
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let header = parse_csv_line(lines.next().unwrap_or_default());
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| column == name)
+            .ok_or_else(|| format!("The CSV file has no \"{name}\" column"))
+    };
+    let (url, output_path) = (column("url")?, column("output_path")?);
+    let status = column("status").ok();
+    let mut params = Vec::new();
+    for line in lines {
+        let fields = parse_csv_line(line);
+        if status
+            .and_then(|index| fields.get(index))
+            .map(String::as_str)
+            == Some("completed")
+        {
+            continue;
+        }
+        let field = |index: usize| fields.get(index).cloned().unwrap_or_default();
+        if field(url).trim().is_empty() {
+            continue;
+        }
+        params.push(DownloadParams {
+            url: field(url),
+            more_urls: Vec::new(),
+            format_args: Vec::new(),
+            output_path: field(output_path),
+            cookie_browser: CookieBrowser::None,
+            options: Default::default(),
+            preset: None,
+        });
+    }
+    Ok(params)
+}
+
+/// The `-f <selector>` pair of `format_args`, without any other argument.
+fn format_selection(format_args: &[String]) -> Vec<String> {
+    format_args
+        .windows(2)
+        .find(|pair| pair[0] == "-f" && !pair[1].starts_with('-'))
+        .map(<[String]>::to_vec)
+        .unwrap_or_default()
+}
+
+/// Payload of `download-rate-limited`.
+#[derive(Clone, Serialize)]
+pub(crate) struct RateLimitRetry {
+    job_id: String,
+    wait_secs: u64,
+    attempt: u32,
+    /// The YouTube player client the retry uses, if switched.
+    player_client: Option<String>,
+}
+
+/// How jobs failing with a temporary error are retried: after `backoff`,
+/// doubling with every further attempt, at most `max_retries` times.
+#[derive(Clone, Copy)]
+pub(crate) struct RetryPolicy {
+    pub max_retries: u32,
+    pub backoff: Duration,
+}
+
+/// Payload of `job-retrying`.
+#[derive(Clone, Serialize)]
+pub(crate) struct JobRetry {
+    job_id: String,
+    attempt: u32,
+    max_retries: u32,
+    wait_secs: u64,
+    /// When the next attempt starts, in seconds since the Unix epoch.
+    next_attempt_at: u64,
+}
+
+/// Payload of `job-gave-up`, sent with the job's last `download-error`.
+#[derive(Clone, Serialize)]
+pub(crate) struct JobGaveUp {
+    job_id: String,
+    /// Automatic retries made before giving up.
+    attempts: u32,
+}
+
+/// Payload of `switching-client`.
+#[derive(Clone, Serialize)]
+pub(crate) struct ClientSwitch {
+    job_id: String,
+    player_client: String,
+}
+
+/// Height queued video jobs are capped at while the disk is low on space.
+const LOW_DISK_MAX_HEIGHT: u32 = 720;
+
+/// Payload of `quality-downgraded`.
+#[derive(Clone, Serialize)]
+pub(crate) struct QualityDowngrade {
+    job_id: String,
+    quality: String,
+    /// Free bytes on the output volume when the job started.
+    available: u64,
+}
+
+/// Background task that launches queued jobs one after another.
+pub(crate) async fn run_worker(app: AppHandle) {
+    let state = app.state::<AppState>();
+    loop {
+        let next_wake = state.queue.lock().await.next_wake();
+        match next_wake {
+            Some(wake) => tokio::select! {
+                _ = state.queue_changed.notified() => {}
+                _ = tokio::time::sleep_until(wake) => {}
+            },
+            None => state.queue_changed.notified().await,
+        }
+        start_pending_jobs(&app, &state).await;
+    }
+}
+
+async fn start_pending_jobs(app: &AppHandle, state: &AppState) {
+    loop {
+        if state.shutting_down.load(Ordering::SeqCst)
+            || state.queue_paused.load(Ordering::SeqCst)
+            || state.queue.lock().await.has_running()
+        {
+            return;
+        }
+        let Some((job_id, mut params)) = state.queue.lock().await.take_next_pending(Instant::now())
+        else {
+            return;
+        };
+        emit_queue_updated(app, state).await;
+        if let Some(downgrade) = cap_quality_for_low_disk(state, &job_id, &mut params).await {
+            app.emit("quality-downgraded", downgrade).ok();
+        }
+
+        match launch_download(app, state, job_id.clone(), params).await {
+            Ok(()) => return,
+            Err(error) => {
+                let batch = {
+                    let mut queue = state.queue.lock().await;
+                    queue.set_status(&job_id, JobStatus::Failed);
+                    queue.finish_batch_job(&job_id, JobStatus::Failed)
+                };
+                app.emit(
+                    "download-error",
+                    JobEvent::new(&job_id, DownloadError::unknown(error)),
+                )
+                .ok();
+                if let Some(batch) = batch {
+                    app.emit("batch-progress", batch).ok();
+                }
+                emit_queue_updated(app, state).await;
+            }
+        }
+    }
+}
+
+/// Lowers a video job to `LOW_DISK_MAX_HEIGHT` when its output volume has
+/// less free space than the saved threshold, so a long queue keeps going
+/// instead of failing on a full disk. The queued job keeps its quality, so
+/// a retry after freeing space downloads it in full.
+async fn cap_quality_for_low_disk(
+    state: &AppState,
+    job_id: &str,
+    params: &mut DownloadParams,
+) -> Option<QualityDowngrade> {
+    let threshold = (*state.low_disk_threshold.lock().await)?;
+    let already_small = params
+        .options
+        .quality
+        .as_deref()
+        .and_then(quality_height)
+        .is_some_and(|height| height <= LOW_DISK_MAX_HEIGHT);
+    if params.is_audio_only() || params.options.transcript_only || already_small {
+        return None;
+    }
+    let available = free_space(params.output_path.clone().into()).await?;
+    if available >= threshold {
+        return None;
+    }
+    let quality = capped_quality(params.options.quality.as_deref());
+    params.options.quality = Some(quality.clone());
+    Some(QualityDowngrade {
+        job_id: job_id.to_string(),
+        quality,
+        available,
+    })
+}
+
+/// `quality` with its resolution, and the frame rate that goes with it,
+/// replaced by `LOW_DISK_MAX_HEIGHT`. The codec is kept.
+fn capped_quality(quality: Option<&str>) -> String {
+    let capped = format!("{LOW_DISK_MAX_HEIGHT}p");
+    let codecs = quality
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter(|part| quality_height(part).is_none());
+    std::iter::once(capped.as_str())
+        .chain(codecs)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Records the outcome of a finished job and lets the worker move on.
+pub(crate) async fn job_finished(
+    app: &AppHandle,
+    state: &AppState,
+    job_id: &str,
+    status: JobStatus,
+) {
+    let (queued, batch) = {
+        let mut queue = state.queue.lock().await;
+        let queued = queue.set_status(job_id, status);
+        (queued, queue.finish_batch_job(job_id, status))
+    };
+    if let Some(batch) = batch {
+        app.emit("batch-progress", batch).ok();
+    }
+    if queued {
+        emit_queue_updated(app, state).await;
+        app.emit("queue-eta", queue_eta(state).await).ok();
+    }
+    state.queue_changed.notify_one();
+}
+
+/// Puts a rate-limited job back in the queue to run again after a backoff.
+pub(crate) async fn retry_rate_limited(
+    state: &AppState,
+    job_id: &str,
+    params: DownloadParams,
+) -> Option<RateLimitRetry> {
+    state
+        .queue
+        .lock()
+        .await
+        .schedule_rate_limit_retry(job_id, params, Instant::now())
+}
+
+/// Schedules a job that failed with a temporary error to run again, if
+/// automatic retries are on and the job has retries left.
+pub(crate) async fn retry_failed(
+    state: &AppState,
+    job_id: &str,
+    params: DownloadParams,
+) -> Option<JobRetry> {
+    let policy = (*state.retry_policy.lock().await)?;
+    state
+        .queue
+        .lock()
+        .await
+        .schedule_retry(job_id, params, policy, Instant::now())
+}
+
+/// Whether a job that failed with a temporary error has no automatic
+/// retries left.
+pub(crate) async fn gave_up(state: &AppState, job_id: &str) -> Option<JobGaveUp> {
+    let policy = (*state.retry_policy.lock().await)?;
+    state.queue.lock().await.gave_up(job_id, policy)
+}
+
+/// Restarts a throttled job with the `android` player client.
+pub(crate) async fn switch_player_client(
+    state: &AppState,
+    job_id: &str,
+    params: DownloadParams,
+) -> Option<ClientSwitch> {
+    state
+        .queue
+        .lock()
+        .await
+        .restart_with_client(job_id, params, "android")
+}
+
+pub(crate) async fn emit_queue_updated(app: &AppHandle, state: &AppState) {
+    let jobs = state.queue.lock().await.jobs().to_vec();
+    app.emit("queue-updated", jobs).ok();
+}
+
+pub(crate) async fn queue_eta(state: &AppState) -> Option<u64> {
+    let average_speed = state.session_stats.lock().await.average_speed();
+    state.queue.lock().await.eta_secs(average_speed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        capped_quality, export_jobs, import_jobs, parse_csv_line, DownloadQueue, JobStatus,
+        RetryPolicy,
+    };
+    use crate::{CookieBrowser, DownloadOptions, DownloadParams};
+    use tokio::time::{Duration, Instant};
+
+    fn params() -> DownloadParams {
+        DownloadParams {
+            url: "https://example.com/watch?v=1".to_string(),
+            more_urls: Vec::new(),
+            format_args: Vec::new(),
+            output_path: "/tmp".to_string(),
+            cookie_browser: CookieBrowser::None,
+            options: DownloadOptions::default(),
+            preset: None,
+        }
+    }
+
+    #[test]
+    fn estimates_remaining_time_for_outstanding_jobs() {
+        let mut queue = DownloadQueue::default();
+        for job_id in ["a", "b", "c"] {
+            queue.push(job_id.to_string(), params());
+            queue.set_estimate(job_id, Some(1_000));
+        }
+        queue.set_status("a", JobStatus::Completed);
+        queue.set_status("b", JobStatus::Running);
+        queue.set_progress("b", 0.5);
+
+        assert_eq!(queue.eta_secs(Some(100.0)), Some(15));
+        assert_eq!(queue.eta_secs(None), None);
+
+        queue.set_estimate("c", None);
+        assert_eq!(queue.eta_secs(Some(100.0)), None);
+    }
+
+    #[test]
+    fn stops_a_batch_at_its_first_failure() {
+        let mut queue = DownloadQueue::default();
+        let jobs = ["a", "b", "c"]
+            .map(|job_id| (job_id.to_string(), params()))
+            .to_vec();
+        let started = queue.push_batch("batch".to_string(), jobs, false);
+        assert_eq!((started.total, started.completed), (3, 0));
+
+        queue.take_next_pending(Instant::now());
+        queue.set_status("a", JobStatus::Completed);
+        let progress = queue.finish_batch_job("a", JobStatus::Completed).unwrap();
+        assert_eq!(progress.completed, 1);
+        assert!(progress.current_url.is_some() && !progress.stopped);
+
+        queue.take_next_pending(Instant::now());
+        assert!(queue.finish_batch_job("b", JobStatus::Retrying).is_none());
+        queue.set_status("b", JobStatus::Failed);
+        let progress = queue.finish_batch_job("b", JobStatus::Failed).unwrap();
+        assert!(progress.stopped && progress.current_url.is_none());
+        assert!(queue.jobs()[2].status == JobStatus::Cancelled);
+        assert!(queue.finish_batch_job("c", JobStatus::Cancelled).is_none());
+    }
+
+    #[test]
+    fn runs_pending_jobs_in_order() {
+        let mut queue = DownloadQueue::default();
+        queue.push("a".to_string(), params());
+        queue.push("b".to_string(), params());
+
+        assert_eq!(queue.take_next_pending(Instant::now()).unwrap().0, "a");
+        assert_eq!(queue.take_next_pending(Instant::now()).unwrap().0, "b");
+        assert!(queue.take_next_pending(Instant::now()).is_none());
+    }
+
+    #[test]
+    fn cancels_only_waiting_jobs() {
+        let mut queue = DownloadQueue::default();
+        queue.push("a".to_string(), params());
+        queue.push("b".to_string(), params());
+        queue.push("c".to_string(), params());
+        queue.take_next_pending(Instant::now());
+
+        assert_eq!(queue.cancel_waiting(), ["b", "c"]);
+        assert!(queue.jobs()[0].status == JobStatus::Running);
+        assert!(queue.take_next_pending(Instant::now()).is_none());
+    }
+
+    #[test]
+    fn runs_higher_priority_jobs_first() {
+        let mut queue = DownloadQueue::default();
+        queue.push("a".to_string(), params());
+        queue.push("b".to_string(), params());
+        queue.push("c".to_string(), params());
+        queue.set_priority("c", 5).unwrap();
+
+        assert_eq!(queue.take_next_pending(Instant::now()).unwrap().0, "c");
+        assert_eq!(queue.take_next_pending(Instant::now()).unwrap().0, "a");
+        assert!(queue.set_priority("a", 1).is_err());
+        assert_eq!(queue.take_next_pending(Instant::now()).unwrap().0, "b");
+    }
+
+    #[test]
+    fn changes_output_path_of_waiting_jobs_only() {
+        let mut queue = DownloadQueue::default();
+        queue.push("a".to_string(), params());
+        queue.set_output_path("a", "/videos".to_string()).unwrap();
+        assert_eq!(queue.jobs()[0].params.output_path, "/videos");
+
+        queue.take_next_pending(Instant::now());
+        assert!(queue.set_output_path("a", "/music".to_string()).is_err());
+        assert_eq!(queue.jobs()[0].params.output_path, "/videos");
+        assert!(queue.set_output_path("b", "/music".to_string()).is_err());
+    }
+
+    #[test]
+    fn exports_and_imports_unfinished_jobs() {
+        let mut queue = DownloadQueue::default();
+        queue.push("a".to_string(), params());
+        queue.push("b".to_string(), params());
+        queue.set_status("a", JobStatus::Completed);
+
+        for file in ["queue.json", "queue.CSV"] {
+            let path = std::path::Path::new(file);
+            let exported = export_jobs(queue.jobs(), path).unwrap();
+            let imported = import_jobs(&exported, path).unwrap();
+            assert_eq!(imported.len(), 1);
+            assert_eq!(imported[0].url, "https://example.com/watch?v=1");
+            assert_eq!(imported[0].output_path, "/tmp");
+        }
+        assert!(export_jobs(queue.jobs(), std::path::Path::new("queue.txt")).is_err());
+    }
+
+    #[test]
+    fn imports_only_the_format_selection() {
+        let mut queue = DownloadQueue::default();
+        let mut job = params();
+        job.format_args = ["--exec", "rm -rf ~", "-f", "bestaudio/best"]
+            .map(str::to_string)
+            .to_vec();
+        queue.push("a".to_string(), job);
+        let path = std::path::Path::new("queue.json");
+        let exported = export_jobs(queue.jobs(), path).unwrap();
+        let imported = import_jobs(&exported, path).unwrap();
+        assert_eq!(imported[0].format_args, ["-f", "bestaudio/best"]);
+
+        let mut queue = DownloadQueue::default();
+        let mut job = params();
+        job.format_args = ["-f", "--exec"].map(str::to_string).to_vec();
+        queue.push("a".to_string(), job);
+        let exported = export_jobs(queue.jobs(), path).unwrap();
+        assert!(import_jobs(&exported, path).unwrap()[0]
+            .format_args
+            .is_empty());
+    }
+
+    #[test]
+    fn reads_quoted_csv_fields() {
+        assert_eq!(parse_csv_line(r#"a,"b, ""c""",d"#), ["a", r#"b, "c""#, "d"]);
+    }
+
+    #[test]
+    fn retries_only_failed_jobs() {
+        let mut queue = DownloadQueue::default();
+        queue.push("a".to_string(), params());
+        assert!(queue.retry("a").is_err());
+
+        queue.take_next_pending(Instant::now());
+        queue.set_status("a", JobStatus::Failed);
+        queue.retry("a").unwrap();
+        assert_eq!(queue.jobs()[0].retries, 1);
+        assert_eq!(queue.take_next_pending(Instant::now()).unwrap().0, "a");
+    }
+
+    #[test]
+    fn backs_off_rate_limited_jobs() {
+        let mut queue = DownloadQueue::default();
+        let now = Instant::now();
+
+        let first = queue.schedule_rate_limit_retry("a", params(), now).unwrap();
+        assert_eq!((first.wait_secs, first.player_client), (30, None));
+        assert!(queue.take_next_pending(now).is_none());
+        assert_eq!(queue.next_wake(), Some(now + Duration::from_secs(30)));
+        assert_eq!(
+            queue
+                .take_next_pending(now + Duration::from_secs(30))
+                .unwrap()
+                .0,
+            "a"
+        );
+
+        let second = queue.schedule_rate_limit_retry("a", params(), now).unwrap();
+        assert_eq!(second.wait_secs, 60);
+        assert_eq!(second.player_client.as_deref(), Some("android"));
+        queue.schedule_rate_limit_retry("a", params(), now).unwrap();
+        assert!(queue
+            .schedule_rate_limit_retry("a", params(), now)
+            .is_none());
+    }
+
+    #[test]
+    fn retries_temporary_failures_until_giving_up() {
+        let mut queue = DownloadQueue::default();
+        let now = Instant::now();
+        let policy = RetryPolicy {
+            max_retries: 2,
+            backoff: Duration::from_secs(60),
+        };
+
+        let first = queue.schedule_retry("a", params(), policy, now).unwrap();
+        assert_eq!((first.attempt, first.wait_secs), (1, 60));
+        assert!(queue.jobs()[0].status == JobStatus::Retrying);
+        assert!(queue.take_next_pending(now).is_none());
+        assert_eq!(
+            queue
+                .take_next_pending(now + Duration::from_secs(60))
+                .unwrap()
+                .0,
+            "a"
+        );
+
+        assert!(queue.gave_up("a", policy).is_none());
+        let second = queue.schedule_retry("a", params(), policy, now).unwrap();
+        assert_eq!((second.attempt, second.wait_secs), (2, 120));
+        assert!(queue.schedule_retry("a", params(), policy, now).is_none());
+        assert_eq!(queue.gave_up("a", policy).unwrap().attempts, 2);
+
+        queue.set_status("a", JobStatus::Failed);
+        queue.retry("a").unwrap();
+        assert_eq!(queue.jobs()[0].auto_retries, 0);
+    }
+
+    #[test]
+    fn restarts_throttled_jobs_with_another_client() {
+        let mut queue = DownloadQueue::default();
+        let now = Instant::now();
+        queue.schedule_rate_limit_retry("a", params(), now).unwrap();
+
+        let switch = queue.restart_with_client("a", params(), "android").unwrap();
+        assert_eq!(switch.player_client, "android");
+        let (job_id, params) = queue.take_next_pending(now).unwrap();
+        assert_eq!(job_id, "a");
+        assert_eq!(params.options.player_client.as_deref(), Some("android"));
+    }
+
+    #[test]
+    fn keeps_the_codec_when_capping_quality() {
+        assert_eq!(capped_quality(Some("1080p60 AV1")), "720p AV1");
+        assert_eq!(capped_quality(Some("vp9 2160p")), "720p vp9");
+        assert_eq!(capped_quality(Some("4320p")), "720p");
+        assert_eq!(capped_quality(None), "720p");
+    }
+}
@@ -0,0 +1,377 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::queue::RetryPolicy;
+use crate::{AppState, CookieBrowser, DownloadOptions};
+
+// ── Persisted settings ────────────────────────────────────────────────────────
+//
+// Settings live in `settings.json` in the app data folder. Every file records
+// the schema version it was written with; files from older app versions are
+// upgraded before they are read, and the original is kept next to it as a
+// backup.
+
+/// Bump this and add a step to `migrate` whenever a field is renamed or its
+/// meaning changes. Fields that are only added need no step, since missing
+/// fields fall back to their defaults.
+const SETTINGS_VERSION: u32 = 1;
+
+/// Free space below which `adaptive_quality_on_low_disk` kicks in when no
+/// threshold is set.
+const DEFAULT_LOW_DISK_THRESHOLD_MB: u64 = 5 * 1024;
+/// First wait before retrying a failed job when no backoff is set.
+const DEFAULT_RETRY_BACKOFF_SECS: u64 = 60;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    version: u32,
+    /// Folder downloads are saved to; the Downloads folder when unset.
+    output_path: Option<String>,
+    cookie_browser: CookieBrowser,
+    /// Options applied to new downloads.
+    options: DownloadOptions,
+    /// Most bandwidth all downloads together may use, such as `2M` or
+    /// `500K` (bytes per second).
+    global_rate_limit: Option<String>,
+    /// A program run after every completed download, with the downloaded
+    /// file as its only argument. It runs with the user's full permissions,
+    /// so only point this at a script you trust. It is started directly,
+    /// not through a shell.
+    post_hook: Option<String>,
+    /// Save each download into a `YYYY-MM-DD` folder, named after the day
+    /// it was downloaded, inside the output folder.
+    datestamp_folders: bool,
+    /// Queued jobs failing with a network error or rate limit run again up
+    /// to this many times before giving up; 0 turns this off. Errors such
+    /// as unavailable videos are never retried.
+    max_retries: u32,
+    /// Seconds before the first retry, doubling with every further one; 60
+    /// when unset.
+    retry_backoff_secs: Option<u64>,
+    /// A JSON file kept up to date with the running downloads (job id, URL,
+    /// phase, percent, speed and ETA, keyed by job id) for dashboards and
+    /// scripts to poll. Holds `{}` while nothing downloads.
+    status_file: Option<String>,
+    /// Start queued video jobs at 720p at most while the output disk has
+    /// less than `low_disk_threshold_mb` free, instead of letting them fail
+    /// once it is full.
+    adaptive_quality_on_low_disk: bool,
+    /// Free space in MiB for `adaptive_quality_on_low_disk`; 5 GiB when
+    /// unset.
+    low_disk_threshold_mb: Option<u64>,
+    /// An `http://` or `https://` proxy for downloads, update checks and
+    /// setup, such as `http://proxy.example.com:8080`.
+    proxy_url: Option<String>,
+    /// Whether the queue was paused, so a paused batch stays paused across
+    /// restarts. Set by `pause_queue` and `resume_queue` only.
+    queue_paused: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            output_path: None,
+            cookie_browser: CookieBrowser::None,
+            options: DownloadOptions::default(),
+            global_rate_limit: None,
+            post_hook: None,
+            datestamp_folders: false,
+            max_retries: 0,
+            retry_backoff_secs: None,
+            status_file: None,
+            adaptive_quality_on_low_disk: false,
+            low_disk_threshold_mb: None,
+            proxy_url: None,
+            queue_paused: false,
+        }
+    }
+}
+
+/// Reads a bandwidth limit such as `500K`, `2.5M` or `1G` into bytes per
+/// second. Suffixes are binary multiples, as in yt-dlp's `--limit-rate`.
+pub(crate) fn parse_rate_limit(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let invalid = || format!("Invalid bandwidth limit \"{value}\". Use a rate such as 500K or 2M");
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&value[..index], 1024.0),
+        Some((index, 'm' | 'M')) => (&value[..index], 1024.0 * 1024.0),
+        Some((index, 'g' | 'G')) => (&value[..index], 1024.0 * 1024.0 * 1024.0),
+        _ => (value, 1.0),
+    };
+    let bytes = number.trim().parse::<f64>().map_err(|_| invalid())? * multiplier;
+    if !bytes.is_finite() || bytes < 1.0 {
+        return Err(invalid());
+    }
+    Ok(bytes.round() as u64)
+}
+
+fn rate_limit_of(settings: &Settings) -> Result<Option<u64>, String> {
+    settings
+        .global_rate_limit
+        .as_deref()
+        .filter(|limit| !limit.trim().is_empty())
+        .map(parse_rate_limit)
+        .transpose()
+}
+
+fn post_hook_of(settings: &Settings) -> Result<Option<PathBuf>, String> {
+    let Some(hook) = settings
+        .post_hook
+        .as_deref()
+        .map(str::trim)
+        .filter(|hook| !hook.is_empty())
+    else {
+        return Ok(None);
+    };
+    let hook = PathBuf::from(hook);
+    if !hook.is_file() {
+        return Err(format!(
+            "Post-download program not found: {}",
+            hook.display()
+        ));
+    }
+    Ok(Some(hook))
+}
+
+fn low_disk_threshold_of(settings: &Settings) -> Option<u64> {
+    settings.adaptive_quality_on_low_disk.then(|| {
+        settings
+            .low_disk_threshold_mb
+            .unwrap_or(DEFAULT_LOW_DISK_THRESHOLD_MB)
+            .saturating_mul(1024 * 1024)
+    })
+}
+
+fn retry_policy_of(settings: &Settings) -> Option<RetryPolicy> {
+    (settings.max_retries > 0).then(|| RetryPolicy {
+        max_retries: settings.max_retries,
+        backoff: std::time::Duration::from_secs(
+            settings
+                .retry_backoff_secs
+                .unwrap_or(DEFAULT_RETRY_BACKOFF_SECS)
+                .max(1),
+        ),
+    })
+}
+
+fn status_file_of(settings: &Settings) -> Result<Option<PathBuf>, String> {
+    let Some(file) = settings
+        .status_file
+        .as_deref()
+        .map(str::trim)
+        .filter(|file| !file.is_empty())
+    else {
+        return Ok(None);
+    };
+    let file = PathBuf::from(file);
+    if !file.parent().is_some_and(Path::is_dir) {
+        return Err(format!(
+            "The folder for the status file does not exist: {}",
+            file.display()
+        ));
+    }
+    Ok(Some(file))
+}
+
+fn proxy_url_of(settings: &Settings) -> Result<Option<String>, String> {
+    let Some(proxy) = settings
+        .proxy_url
+        .as_deref()
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+    else {
+        return Ok(None);
+    };
+    let url = reqwest::Url::parse(proxy).map_err(|_| format!("Invalid proxy URL: {proxy}"))?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(format!(
+            "The proxy must be an http:// or https:// URL with a host, not {proxy}"
+        ));
+    }
+    Ok(Some(proxy.to_string()))
+}
+
+fn settings_path(app: &AppHandle) -> PathBuf {
+    app.path().app_data_dir().unwrap().join("settings.json")
+}
+
+/// Upgrades settings written by an older app version to `SETTINGS_VERSION`
+/// and returns the version they had. Settings from a newer version are left
+/// alone; fields this version does not know are ignored when reading them.
+fn migrate(settings: &mut serde_json::Value) -> Result<u32, String> {
+    let settings = settings
+        .as_object_mut()
+        .ok_or_else(|| "The settings file does not contain settings".to_string())?;
+    let version = settings
+        .get("version")
+        .and_then(serde_json::Value::as_u64)
+        .map_or(0, |version| version as u32);
+    if version >= SETTINGS_VERSION {
+        return Ok(version);
+    }
+    // Version 0 files were written before settings were versioned and use
+    // the same field names, so they only need the version recorded.
+    settings.insert("version".to_string(), SETTINGS_VERSION.into());
+    Ok(version)
+}
+
+/// Reads the settings at `path`, migrating and rewriting the file when it
+/// comes from an older version. A missing file yields the defaults.
+async fn read_settings(path: &Path) -> Result<Settings, String> {
+    let text = match tokio::fs::read_to_string(path).await {
+        Ok(text) => text,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Settings::default())
+        }
+        Err(error) => return Err(format!("Could not read the settings: {error}")),
+    };
+    let mut value: serde_json::Value = serde_json::from_str(&text)
+        .map_err(|e| format!("The settings file is not valid JSON: {e}"))?;
+    let version = migrate(&mut value)?;
+    let settings: Settings = serde_json::from_value(value)
+        .map_err(|e| format!("The settings file could not be read: {e}"))?;
+    if version < SETTINGS_VERSION {
+        let backup = path.with_extension(format!("v{version}.json.bak"));
+        tokio::fs::write(&backup, &text)
+            .await
+            .map_err(|e| format!("Could not back up the settings before upgrading: {e}"))?;
+        write_settings(path, &settings).await?;
+    }
+    Ok(settings)
+}
+
+async fn write_settings(path: &Path, settings: &Settings) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Cannot create the settings folder: {e}"))?;
+    }
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    tokio::fs::write(path, json)
+        .await
+        .map_err(|e| format!("Could not save the settings: {e}"))
+}
+
+/// Returns the saved settings, upgrading a file from an older app version.
+#[tauri::command]
+pub(crate) async fn load_settings(app: AppHandle) -> Result<Settings, String> {
+    read_settings(&settings_path(&app)).await
+}
+
+#[tauri::command]
+pub(crate) async fn save_settings(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    settings: Settings,
+) -> Result<(), String> {
+    let rate_limit = rate_limit_of(&settings)?;
+    let post_hook = post_hook_of(&settings)?;
+    let status_file = status_file_of(&settings)?;
+    let proxy_url = proxy_url_of(&settings)?;
+    let settings = Settings {
+        version: SETTINGS_VERSION,
+        queue_paused: state.queue_paused.load(Ordering::SeqCst),
+        ..settings
+    };
+    write_settings(&settings_path(&app), &settings).await?;
+    *state.global_rate_limit.lock().await = rate_limit;
+    state.bandwidth_changed.notify_one();
+    *state.post_hook.lock().await = post_hook;
+    *state.status_file.lock().await = status_file;
+    *state.proxy_url.lock().await = proxy_url;
+    *state.retry_policy.lock().await = retry_policy_of(&settings);
+    *state.low_disk_threshold.lock().await = low_disk_threshold_of(&settings);
+    state
+        .datestamp_folders
+        .store(settings.datestamp_folders, Ordering::SeqCst);
+    Ok(())
+}
+
+/// The saved output folder, if one was chosen. An unreadable settings file
+/// counts as none.
+pub(crate) async fn saved_output_path(app: &AppHandle) -> Option<String> {
+    read_settings(&settings_path(app))
+        .await
+        .ok()?
+        .output_path
+        .filter(|path| !path.trim().is_empty())
+}
+
+/// Records whether the queue is paused in the saved settings.
+pub(crate) async fn save_queue_paused(app: &AppHandle, paused: bool) -> Result<(), String> {
+    let path = settings_path(app);
+    let settings = Settings {
+        queue_paused: paused,
+        ..read_settings(&path).await?
+    };
+    write_settings(&path, &settings).await
+}
+
+/// Loads the settings the backend applies itself, at startup.
+pub(crate) async fn apply_saved_settings(app: AppHandle) {
+    let Ok(settings) = read_settings(&settings_path(&app)).await else {
+        return;
+    };
+    let state = app.state::<AppState>();
+    *state.global_rate_limit.lock().await = rate_limit_of(&settings).ok().flatten();
+    *state.post_hook.lock().await = post_hook_of(&settings).ok().flatten();
+    *state.status_file.lock().await = status_file_of(&settings).ok().flatten();
+    *state.proxy_url.lock().await = proxy_url_of(&settings).ok().flatten();
+    *state.retry_policy.lock().await = retry_policy_of(&settings);
+    *state.low_disk_threshold.lock().await = low_disk_threshold_of(&settings);
+    state
+        .datestamp_folders
+        .store(settings.datestamp_folders, Ordering::SeqCst);
+    state
+        .queue_paused
+        .store(settings.queue_paused, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate, parse_rate_limit, proxy_url_of, Settings, SETTINGS_VERSION};
+
+    #[test]
+    fn accepts_only_http_proxies() {
+        let proxy = |url: &str| {
+            proxy_url_of(&Settings {
+                proxy_url: Some(url.to_string()),
+                ..Settings::default()
+            })
+        };
+        assert_eq!(
+            proxy(" http://proxy.local:3128 "),
+            Ok(Some("http://proxy.local:3128".to_string()))
+        );
+        assert_eq!(proxy(""), Ok(None));
+        assert!(proxy("socks5://proxy.local:1080").is_err());
+        assert!(proxy("proxy.local:3128").is_err());
+    }
+
+    #[test]
+    fn upgrades_unversioned_settings() {
+        let mut settings = serde_json::json!({ "output_path": "/videos" });
+        assert_eq!(migrate(&mut settings), Ok(0));
+        assert_eq!(settings["version"], SETTINGS_VERSION);
+        assert_eq!(settings["output_path"], "/videos");
+
+        let mut newer = serde_json::json!({ "version": SETTINGS_VERSION + 1 });
+        assert_eq!(migrate(&mut newer), Ok(SETTINGS_VERSION + 1));
+        assert_eq!(newer["version"], SETTINGS_VERSION + 1);
+        assert!(migrate(&mut serde_json::json!([])).is_err());
+    }
+
+    #[test]
+    fn reads_bandwidth_limits() {
+        assert_eq!(parse_rate_limit("500K"), Ok(500 * 1024));
+        assert_eq!(parse_rate_limit(" 2.5m "), Ok(5 * 512 * 1024));
+        assert_eq!(parse_rate_limit("4096"), Ok(4096));
+        assert!(parse_rate_limit("fast").is_err());
+        assert!(parse_rate_limit("0K").is_err());
+    }
+}
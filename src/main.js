@@ -17,6 +17,7 @@ const urlInput         = document.getElementById("url-input");
 const pasteBtn         = document.getElementById("paste-btn");
 const qualitySelect    = document.getElementById("quality-select");
 const cookieSelect     = document.getElementById("cookie-select");
+const checkCookiesBtn  = document.getElementById("check-cookies-btn");
 const outputDisplay    = document.getElementById("output-display");
 const folderBtn        = document.getElementById("folder-btn");
 const downloadBtn      = document.getElementById("download-btn");
@@ -37,6 +38,8 @@ let isDownloading = false;
 let isUpdatingYtdlp = false;
 let ytdlpManagedByApp = true;
 let hadAnyDownload = false;
+// Job id of the download this window shows; events of other jobs are ignored.
+let currentJobId = null;
 
 // ── Init ──────────────────────────────────────────────────────
 async function init() {
@@ -129,34 +132,57 @@ async function registerListeners() {
   });
 
   await listen("download-log", (event) => {
-    appendLog(event.payload);
-    if (event.payload.includes("[download] 100%")) {
+    const { job_id: jobId, line } = event.payload;
+    if (jobId && isOtherJob(jobId)) return;
+    appendLog(line);
+    if (line.includes("[download] 100%")) {
       hadAnyDownload = true;
     }
-    if (event.payload.includes("Could not copy Chrome cookie database")) {
+    if (line.includes("Could not copy Chrome cookie database")) {
       dlStatus.textContent = "Close Chrome completely and try again, or switch to Firefox cookies.";
       dlStatus.style.color = "var(--error)";
     }
   });
 
   await listen("download-progress", (event) => {
-    const pct = Math.round(event.payload * 100);
+    if (isOtherJob(event.payload.job_id)) return;
+    const pct = Math.round(event.payload.progress * 100);
     dlProgressFill.style.width = pct + "%";
     dlPct.textContent = pct + "%";
   });
 
-  await listen("download-complete", () => {
+  await listen("download-stats", (event) => {
+    if (isOtherJob(event.payload.job_id)) return;
+    const { speed_bytes_per_sec: speed, eta_seconds: eta } = event.payload;
+    const parts = [];
+    if (speed != null) parts.push(`${formatBytes(speed)}/s`);
+    if (eta != null) parts.push(`${formatDuration(eta)} left`);
+    dlStatus.textContent = parts.length ? "Downloading… " + parts.join(" · ") : "Downloading…";
+  });
+
+  await listen("download-complete", (event) => {
+    if (isOtherJob(event.payload.job_id)) return;
     setDownloading(false);
     dlProgressFill.style.width = "100%";
     dlPct.textContent = "100%";
-    dlStatus.textContent = "Complete ✓";
+    dlStatus.textContent = "Complete ✓" + describeSummary(event.payload);
     dlStatus.style.color = "var(--success)";
     openFolderBtn.classList.remove("hidden");
   });
 
+  // Sizing a download can take a while before start_download returns, so
+  // its id arrives here first for the Cancel button.
+  await listen("download-preparing", (event) => {
+    if (isDownloading && currentJobId === null) {
+      currentJobId = event.payload.job_id;
+      dlStatus.textContent = "Checking disk space…";
+    }
+  });
+
   await listen("download-error", (event) => {
+    if (isOtherJob(event.payload.job_id)) return;
     setDownloading(false);
-    dlStatus.textContent = "Error: " + event.payload;
+    dlStatus.textContent = "Error: " + event.payload.message;
     dlStatus.style.color = "var(--error)";
     if (hadAnyDownload) {
       openFolderBtn.classList.remove("hidden");
@@ -187,6 +213,31 @@ folderBtn.addEventListener("click", async () => {
   }
 });
 
+checkCookiesBtn.addEventListener("click", async () => {
+  const testUrl = urlInput.value.trim();
+  if (!testUrl) { urlInput.focus(); return; }
+  if (cookieSelect.value === "none") { cookieSelect.focus(); return; }
+
+  checkCookiesBtn.disabled = true;
+  dlStatus.textContent = "Checking cookies…";
+  dlStatus.style.color = "var(--muted)";
+  try {
+    const authenticated = await invoke("check_cookies", {
+      browser: cookieSelect.value,
+      testUrl,
+    });
+    dlStatus.textContent = authenticated
+      ? "Cookies work for this URL ✓"
+      : "The site rejected these cookies. Log in with this browser and try again.";
+    dlStatus.style.color = authenticated ? "var(--success)" : "var(--warning)";
+  } catch (err) {
+    dlStatus.textContent = "Cookie check failed: " + err;
+    dlStatus.style.color = "var(--error)";
+  } finally {
+    checkCookiesBtn.disabled = false;
+  }
+});
+
 setupRetryBtn.addEventListener("click", runSetup);
 
 downloadBtn.addEventListener("click", async () => {
@@ -200,14 +251,19 @@ downloadBtn.addEventListener("click", async () => {
 
   const formatArgs = buildFormatArgs(qualitySelect.value);
   const cookieBrowser = cookieSelect.value;
+  const maxHeight = Number(qualitySelect.value);
 
   try {
-    await invoke("start_download", {
+    const jobId = await invoke("start_download", {
       url,
       formatArgs,
       outputPath,
       cookieBrowser,
+      options: buildOptions(qualitySelect.value, maxHeight),
     });
+    // Cancelled while its size was being checked.
+    if (!isDownloading) return;
+    currentJobId = jobId;
     dlStatus.textContent = "Downloading…";
   } catch (err) {
     setDownloading(false);
@@ -217,7 +273,9 @@ downloadBtn.addEventListener("click", async () => {
 });
 
 cancelBtn.addEventListener("click", async () => {
-  await invoke("cancel_download");
+  if (currentJobId) {
+    await invoke("cancel_download", { jobId: currentJobId });
+  }
   setDownloading(false);
   dlStatus.textContent = "Cancelled";
   dlStatus.style.color = "var(--muted)";
@@ -293,25 +351,33 @@ async function refreshYtdlpStatus() {
   }
 }
 
+function buildOptions(value, maxHeight) {
+  if (value === "mp3") {
+    return { download_kind: "audio", audio_format: "mp3" };
+  }
+  return Number.isInteger(maxHeight) ? { max_height: maxHeight } : {};
+}
+
 function buildFormatArgs(value) {
+  // MP3 is extracted through the download options instead.
   if (value === "mp3") {
-    return ["-x", "--audio-format", "mp3"];
+    return [];
   }
   if (value === "audio") {
     return ["-f", "bestaudio/best"];
   }
 
-  const heightFilter = value === "best" ? "" : `[height<=${value}]`;
+  // Height presets are passed as `max_height`, which ranks the formats.
   const compatibleFormat = [
-    `bestvideo${heightFilter}[vcodec^=avc1]+bestaudio[ext=m4a]`,
-    `bestvideo${heightFilter}[vcodec^=h264]+bestaudio[ext=m4a]`,
-    `bestvideo${heightFilter}[vcodec^=avc1]+bestaudio`,
-    `bestvideo${heightFilter}[vcodec^=h264]+bestaudio`,
-    `best${heightFilter}[vcodec^=avc1][ext=mp4]`,
-    `best${heightFilter}[vcodec^=h264][ext=mp4]`,
-    `best${heightFilter}[vcodec^=avc1]`,
-    `best${heightFilter}[vcodec^=h264]`,
-    `best${heightFilter}[ext=mp4][vcodec!^=?vp09]`,
+    "bestvideo[vcodec^=avc1]+bestaudio[ext=m4a]",
+    "bestvideo[vcodec^=h264]+bestaudio[ext=m4a]",
+    "bestvideo[vcodec^=avc1]+bestaudio",
+    "bestvideo[vcodec^=h264]+bestaudio",
+    "best[vcodec^=avc1][ext=mp4]",
+    "best[vcodec^=h264][ext=mp4]",
+    "best[vcodec^=avc1]",
+    "best[vcodec^=h264]",
+    "best[ext=mp4][vcodec!^=?vp09]",
   ].join("/");
 
   if (value === "best") {
@@ -325,6 +391,31 @@ function buildFormatArgs(value) {
   return ["-f", compatibleFormat];
 }
 
+function describeSummary(summary) {
+  if (!summary || !summary.total_bytes) {
+    return "";
+  }
+  return ` · ${formatBytes(summary.total_bytes)} in ${formatDuration(summary.elapsed_secs)}` +
+    ` (${formatBytes(summary.average_speed)}/s)`;
+}
+
+function formatBytes(bytes) {
+  const units = ["B", "KB", "MB", "GB", "TB"];
+  let value = bytes;
+  let unit = 0;
+  while (value >= 1000 && unit < units.length - 1) {
+    value /= 1000;
+    unit += 1;
+  }
+  return `${value.toFixed(unit === 0 || value >= 100 ? 0 : 1)} ${units[unit]}`;
+}
+
+function formatDuration(seconds) {
+  const total = Math.round(seconds);
+  const minutes = Math.floor(total / 60);
+  return minutes > 0 ? `${minutes}m ${total % 60}s` : `${total}s`;
+}
+
 function resetDownloadUI() {
   dlProgressFill.style.width = "0%";
   dlPct.textContent = "0%";
@@ -333,6 +424,12 @@ function resetDownloadUI() {
   logOutput.textContent = "";
   openFolderBtn.classList.add("hidden");
   hadAnyDownload = false;
+  currentJobId = null;
+}
+
+// Until start_download returns its id, every job's events are shown.
+function isOtherJob(jobId) {
+  return currentJobId !== null && jobId !== currentJobId;
 }
 
 function setDownloading(active) {